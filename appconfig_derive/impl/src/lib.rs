@@ -67,12 +67,13 @@ fn parse_attrs<T>(fields: &Punctuated<Field, T>) -> HashMap<String, HashMap<Stri
         };
         let tts = split_tts(g.stream());
         for tt in tts {
-            let res: Result<Expr, syn::Error> = syn::parse2(tt);
-            if res.is_err() {
-                eprintln!("err: {}", res.unwrap_err());
-                continue;
-            }
-            let expr = res.unwrap();
+            let expr: Expr = match syn::parse2(tt) {
+                Ok(expr) => expr,
+                Err(e) => {
+                    eprintln!("err: {}", e);
+                    continue;
+                }
+            };
             match expr {
                 Expr::Assign(ExprAssign { left, right, .. }) => {
                     let key = match *left {
@@ -121,19 +122,19 @@ pub fn app_config(input: TokenStream) -> TokenStream {
         let name = f.ident.as_ref().unwrap().to_string();
         attrs
             .get(&name)
-            .map_or(true, |a| !a.contains_key(NESTED) && !a.contains_key(SKIP))
+            .is_none_or(|a| !a.contains_key(NESTED) && !a.contains_key(SKIP))
     });
 
     let nested_fields = fields.iter().filter(|f| {
         let name = f.ident.as_ref().unwrap().to_string();
         attrs
             .get(&name)
-            .map_or(false, |a| a.contains_key(NESTED) && !a.contains_key(SKIP))
+            .is_some_and(|a| a.contains_key(NESTED) && !a.contains_key(SKIP))
     });
 
     let skipped_fields = fields.iter().filter(|f| {
         let name = f.ident.as_ref().unwrap().to_string();
-        attrs.get(&name).map_or(false, |a| a.contains_key(SKIP))
+        attrs.get(&name).is_some_and(|a| a.contains_key(SKIP))
     });
 
     // 1. Generate a `builder` struct, with all optional fields
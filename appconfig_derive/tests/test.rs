@@ -106,7 +106,7 @@ mod tests {
     }
 
     fn return_four() -> i32 {
-        return 4;
+        4
     }
 
     #[derive(AppConfig)]
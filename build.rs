@@ -0,0 +1,4 @@
+fn main() {
+    // The migrations are embedded in the binary, see `utils::migrations`.
+    println!("cargo:rerun-if-changed=migrations");
+}
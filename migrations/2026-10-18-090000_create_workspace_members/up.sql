@@ -0,0 +1,19 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS public.workspace_members
+(
+    user_uuid uuid NOT NULL,
+    workspace_uuid uuid NOT NULL,
+    role character varying(16) COLLATE pg_catalog."default" NOT NULL,
+    CONSTRAINT workspace_members_pkey PRIMARY KEY (user_uuid, workspace_uuid),
+    CONSTRAINT workspace_members_user_uuid_fkey FOREIGN KEY (user_uuid)
+        REFERENCES public.users (uuid) MATCH SIMPLE
+        ON UPDATE NO ACTION
+        ON DELETE CASCADE,
+    CONSTRAINT workspace_members_workspace_uuid_fkey FOREIGN KEY (workspace_uuid)
+        REFERENCES public.workspaces (uuid) MATCH SIMPLE
+        ON UPDATE NO ACTION
+        ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS workspace_members_workspace_uuid_idx
+    ON public.workspace_members (workspace_uuid);
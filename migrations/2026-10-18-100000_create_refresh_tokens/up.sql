@@ -0,0 +1,20 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS public.refresh_tokens
+(
+    uuid uuid NOT NULL,
+    user_uuid uuid NOT NULL,
+    family_uuid uuid NOT NULL,
+    token_hash character(64) COLLATE pg_catalog."default" NOT NULL,
+    created_at timestamp without time zone NOT NULL DEFAULT now(),
+    expires_at timestamp without time zone NOT NULL,
+    revoked_at timestamp without time zone,
+    CONSTRAINT refresh_tokens_pkey PRIMARY KEY (uuid),
+    CONSTRAINT refresh_tokens_token_hash_key UNIQUE (token_hash),
+    CONSTRAINT refresh_tokens_user_uuid_fkey FOREIGN KEY (user_uuid)
+        REFERENCES public.users (uuid) MATCH SIMPLE
+        ON UPDATE NO ACTION
+        ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS refresh_tokens_family_uuid_idx
+    ON public.refresh_tokens (family_uuid);
@@ -0,0 +1,6 @@
+-- Atoms are positioned within their slot by `idx`, which is managed by the
+-- application so that atoms can be inserted and moved.
+ALTER TABLE public.atoms ALTER COLUMN idx DROP IDENTITY IF EXISTS;
+
+CREATE INDEX IF NOT EXISTS slots_page_uuid_order_idx
+    ON public.slots (page_uuid, "order");
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE public.slots ALTER COLUMN "order"
+    TYPE character(16) COLLATE pg_catalog."default";
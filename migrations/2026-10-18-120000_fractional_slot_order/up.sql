@@ -0,0 +1,4 @@
+-- Slot order keys are fractional indexes compared byte by byte, so they need
+-- the "C" collation and must not be blank padded.
+ALTER TABLE public.slots ALTER COLUMN "order"
+    TYPE character varying(16) COLLATE "C" USING rtrim("order");
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM public.atoms WHERE typ <> 'Text';
+
+UPDATE public.atoms SET data = data::json->>'text' WHERE typ = 'Text';
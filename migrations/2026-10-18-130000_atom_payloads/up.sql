@@ -0,0 +1,4 @@
+-- Atom data is now a JSON payload that depends on the atom's type.
+UPDATE public.atoms
+    SET data = json_build_object('text', coalesce(data, ''))::text
+    WHERE typ = 'Text';
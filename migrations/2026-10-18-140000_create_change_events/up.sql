@@ -0,0 +1,11 @@
+-- Change events shared between instances. They're only kept for a while, so
+-- instances can catch up on what they missed while disconnected.
+CREATE TABLE public.change_events
+(
+    id bigserial NOT NULL,
+    payload text COLLATE pg_catalog."default" NOT NULL,
+    created_at timestamp without time zone NOT NULL DEFAULT now(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX change_events_created_at_idx ON public.change_events (created_at);
@@ -0,0 +1,5 @@
+DROP TABLE public.text_edits;
+
+ALTER TABLE public.atoms
+    DROP COLUMN version,
+    DROP COLUMN uuid;
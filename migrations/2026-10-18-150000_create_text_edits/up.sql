@@ -0,0 +1,17 @@
+-- Atoms get a stable identifier, since their index changes as atoms are
+-- inserted and moved, and a version that's bumped whenever their data
+-- changes.
+ALTER TABLE public.atoms
+    ADD COLUMN uuid uuid NOT NULL DEFAULT gen_random_uuid(),
+    ADD COLUMN version integer NOT NULL DEFAULT 0,
+    ADD CONSTRAINT atoms_uuid_key UNIQUE (uuid);
+
+-- The recent edits of text atoms, which edits made against older versions
+-- are rebased onto.
+CREATE TABLE public.text_edits
+(
+    atom_uuid uuid NOT NULL REFERENCES public.atoms (uuid) ON DELETE CASCADE,
+    version integer NOT NULL,
+    operations text COLLATE pg_catalog."default" NOT NULL,
+    PRIMARY KEY (atom_uuid, version)
+);
@@ -0,0 +1,11 @@
+DROP TRIGGER set_updated_at ON public.atoms;
+ALTER TABLE public.atoms DROP COLUMN updated_at;
+
+DROP TRIGGER set_updated_at ON public.slots;
+ALTER TABLE public.slots DROP COLUMN updated_at, DROP COLUMN version;
+
+DROP TRIGGER set_updated_at ON public.pages;
+ALTER TABLE public.pages DROP COLUMN updated_at, DROP COLUMN version;
+
+DROP TRIGGER set_updated_at ON public.workspaces;
+ALTER TABLE public.workspaces DROP COLUMN updated_at, DROP COLUMN version;
@@ -0,0 +1,21 @@
+-- Updates only apply if the row still has the version they were based on,
+-- and bump it. `updated_at` is maintained by `diesel_set_updated_at`.
+ALTER TABLE public.workspaces
+    ADD COLUMN version integer NOT NULL DEFAULT 0,
+    ADD COLUMN updated_at timestamp without time zone NOT NULL DEFAULT now();
+SELECT diesel_manage_updated_at('public.workspaces');
+
+ALTER TABLE public.pages
+    ADD COLUMN version integer NOT NULL DEFAULT 0,
+    ADD COLUMN updated_at timestamp without time zone NOT NULL DEFAULT now();
+SELECT diesel_manage_updated_at('public.pages');
+
+ALTER TABLE public.slots
+    ADD COLUMN version integer NOT NULL DEFAULT 0,
+    ADD COLUMN updated_at timestamp without time zone NOT NULL DEFAULT now();
+SELECT diesel_manage_updated_at('public.slots');
+
+-- Atoms already have a version, see `text_edits`.
+ALTER TABLE public.atoms
+    ADD COLUMN updated_at timestamp without time zone NOT NULL DEFAULT now();
+SELECT diesel_manage_updated_at('public.atoms');
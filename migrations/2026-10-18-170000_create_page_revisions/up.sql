@@ -0,0 +1,12 @@
+-- Snapshots of the content of pages, see `PageSnapshot`. Consecutive changes
+-- by the same user are coalesced into one revision.
+CREATE TABLE public.page_revisions
+(
+    page_uuid uuid NOT NULL REFERENCES public.pages (uuid) ON DELETE CASCADE,
+    number integer NOT NULL,
+    user_uuid uuid REFERENCES public.users (uuid) ON DELETE SET NULL,
+    created_at timestamp NOT NULL DEFAULT now(),
+    updated_at timestamp NOT NULL DEFAULT now(),
+    snapshot text COLLATE pg_catalog."default" NOT NULL,
+    PRIMARY KEY (page_uuid, number)
+);
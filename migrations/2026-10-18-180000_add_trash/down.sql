@@ -0,0 +1,2 @@
+ALTER TABLE public.pages DROP COLUMN deleted_at;
+ALTER TABLE public.workspaces DROP COLUMN deleted_at;
@@ -0,0 +1,9 @@
+-- Deleted pages and workspaces are moved to the trash by setting
+-- `deleted_at`, and purged once they've been there for the retention period.
+ALTER TABLE public.workspaces ADD COLUMN deleted_at timestamp without time zone;
+ALTER TABLE public.pages ADD COLUMN deleted_at timestamp without time zone;
+
+CREATE INDEX workspaces_deleted_at_idx ON public.workspaces (deleted_at)
+    WHERE deleted_at IS NOT NULL;
+CREATE INDEX pages_deleted_at_idx ON public.pages (deleted_at)
+    WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,16 @@
+DROP INDEX public.pages_workspace_uuid_idx;
+
+ALTER TABLE public.atoms
+    DROP CONSTRAINT atoms_slot_uuid_fkey,
+    ADD CONSTRAINT atoms_slot_uuid_fkey FOREIGN KEY (slot_uuid)
+        REFERENCES public.slots (uuid) ON DELETE NO ACTION;
+
+ALTER TABLE public.slots
+    DROP CONSTRAINT slots_page_uuid_fkey,
+    ADD CONSTRAINT slots_page_uuid_fkey FOREIGN KEY (page_uuid)
+        REFERENCES public.pages (uuid) ON DELETE NO ACTION;
+
+ALTER TABLE public.pages
+    DROP CONSTRAINT pages_workspace_uuid_fkey,
+    ADD CONSTRAINT pages_workspace_uuid_fkey FOREIGN KEY (workspace_uuid)
+        REFERENCES public.workspaces (uuid) ON DELETE NO ACTION;
@@ -0,0 +1,19 @@
+-- Deleting a workspace, page or slot removes everything below it.
+ALTER TABLE public.pages
+    DROP CONSTRAINT pages_workspace_uuid_fkey,
+    ADD CONSTRAINT pages_workspace_uuid_fkey FOREIGN KEY (workspace_uuid)
+        REFERENCES public.workspaces (uuid) ON DELETE CASCADE;
+
+ALTER TABLE public.slots
+    DROP CONSTRAINT slots_page_uuid_fkey,
+    ADD CONSTRAINT slots_page_uuid_fkey FOREIGN KEY (page_uuid)
+        REFERENCES public.pages (uuid) ON DELETE CASCADE;
+
+ALTER TABLE public.atoms
+    DROP CONSTRAINT atoms_slot_uuid_fkey,
+    ADD CONSTRAINT atoms_slot_uuid_fkey FOREIGN KEY (slot_uuid)
+        REFERENCES public.slots (uuid) ON DELETE CASCADE;
+
+-- Slots and atoms are found through `slots_page_uuid_order_idx` and the atoms
+-- primary key, pages had no index on their workspace yet.
+CREATE INDEX IF NOT EXISTS pages_workspace_uuid_idx ON public.pages (workspace_uuid);
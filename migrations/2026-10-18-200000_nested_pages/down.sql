@@ -0,0 +1,3 @@
+DROP INDEX public.pages_parent_page_uuid_order_idx;
+ALTER TABLE public.pages DROP COLUMN "order";
+ALTER TABLE public.pages DROP COLUMN parent_page_uuid;
@@ -0,0 +1,11 @@
+-- Pages form a tree within their workspace. Root pages have no parent, and
+-- siblings are ordered by fractional index keys like slots.
+ALTER TABLE public.pages
+    ADD COLUMN parent_page_uuid uuid REFERENCES public.pages (uuid) ON DELETE CASCADE;
+-- Existing pages share a key until their siblings are rebalanced.
+ALTER TABLE public.pages
+    ADD COLUMN "order" character varying(16) COLLATE "C" NOT NULL DEFAULT 'V';
+ALTER TABLE public.pages ALTER COLUMN "order" DROP DEFAULT;
+
+CREATE INDEX pages_parent_page_uuid_order_idx
+    ON public.pages (parent_page_uuid, "order");
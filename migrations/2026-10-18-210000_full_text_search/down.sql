@@ -0,0 +1,3 @@
+DROP INDEX public.atoms_search_idx;
+DROP INDEX public.pages_search_idx;
+DROP FUNCTION public.atom_search_text(character varying, text);
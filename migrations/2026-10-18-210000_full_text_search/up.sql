@@ -0,0 +1,28 @@
+-- The searchable text of an atom's data, see `AtomPayload`. Marks are kept
+-- apart from the text of Text atoms, so the text needs no stripping.
+CREATE FUNCTION public.atom_search_text(typ character varying, data text)
+    RETURNS text AS $$
+BEGIN
+    RETURN CASE typ
+        WHEN 'Text' THEN data::jsonb ->> 'text'
+        WHEN 'Heading' THEN data::jsonb ->> 'text'
+        WHEN 'BulletedListItem' THEN data::jsonb ->> 'text'
+        WHEN 'NumberedListItem' THEN data::jsonb ->> 'text'
+        WHEN 'TodoListItem' THEN data::jsonb ->> 'text'
+        WHEN 'Quote' THEN data::jsonb ->> 'text'
+        WHEN 'Code' THEN data::jsonb ->> 'code'
+        WHEN 'Image' THEN data::jsonb ->> 'caption'
+        WHEN 'Math' THEN data::jsonb ->> 'tex'
+    END;
+EXCEPTION WHEN invalid_text_representation THEN
+    RETURN NULL;
+END
+$$ LANGUAGE plpgsql IMMUTABLE;
+
+-- Expression indexes are kept up to date by Postgres on every write, and
+-- searches must use the same expressions to hit them. The `simple`
+-- configuration doesn't assume a language.
+CREATE INDEX pages_search_idx ON public.pages
+    USING gin (to_tsvector('simple', title));
+CREATE INDEX atoms_search_idx ON public.atoms
+    USING gin (to_tsvector('simple', coalesce(public.atom_search_text(typ, data), '')));
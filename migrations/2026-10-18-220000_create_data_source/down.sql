@@ -0,0 +1 @@
+DROP TABLE public.data_source;
@@ -0,0 +1,8 @@
+-- Settings loaded by the app config, such as the JWT signing keys. Older
+-- versions created this table on startup, hence the IF NOT EXISTS.
+CREATE TABLE IF NOT EXISTS public.data_source
+(
+    key text COLLATE pg_catalog."default" NOT NULL,
+    value text COLLATE pg_catalog."default" NOT NULL,
+    PRIMARY KEY (key)
+);
@@ -8,56 +8,94 @@ use std::sync::Arc;
 
 use crate::{
     repos::{
-        images_repo::S3ImagesRepo,
-        page_repo::PageRepo,
-        traits::{ImagesRepo, WorkspaceRepo},
-        users_repo::PostgresqlUsersRepo,
-        workspace_repo::PostgresqlWorkspaceRepo,
+        database::Database,
+        images_repo::{serve_local_images, ImagesStorageKind, LocalFsImagesRepo, S3ImagesRepo},
+        traits::ImagesRepo,
+        Repos, StorageKind,
     },
     utils::{
+        auth::authenticate,
         config::{BaseConfig, Config},
+        events::{EventBroker, EventBrokerKind, LocalEventBroker},
+        jwt::KeyStore,
+        migrations::run_migrations,
         postgresql_data_source::PostgresqlDataSource,
+        postgresql_event_broker::PostgresqlEventBroker,
+        trash::purge_expired_trash,
     },
 };
 use actix_cors::Cors;
-use actix_web::{guard, http, middleware::Logger, web, web::Data, App, HttpResponse, HttpServer};
-use appconfig_derive::NopDataSource;
+use actix_web::{
+    guard, http, middleware::Logger, web, web::Data, App, HttpRequest, HttpResponse, HttpServer,
+};
+use appconfig_derive::{DataSource, NopDataSource};
 use async_graphql::{
     extensions::{Analyzer, ApolloTracing, Logger as GQLLogger},
     http::GraphiQLSource,
-    EmptySubscription, Schema,
+    Data as GQLData, ErrorExtensions, Pos, Response,
 };
-use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
+use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse, GraphQLSubscription};
+use chrono::Duration;
 use diesel::prelude::*;
 use diesel::r2d2::ConnectionManager;
 use diesel::r2d2::Pool;
 use dotenvy::dotenv;
-use log::info;
+use log::{error, info, warn};
 use repos::traits::UserRepo;
-use resolvers::{MutationsRoot, QueryRoot};
+use resolvers::{ancestors_loader, schema_builder, AppSchema};
 
 async fn index(
-    schema: web::Data<Schema<QueryRoot, MutationsRoot, EmptySubscription>>,
-    // db: web::Data<dyn UserRepo>,
-    // config: web::Data<BaseConfig>,
+    schema: web::Data<AppSchema>,
+    user_repo: web::Data<dyn UserRepo>,
+    page_repo: web::Data<dyn repos::traits::PageRepo>,
+    keys: web::Data<KeyStore>,
     req: GraphQLRequest,
-    // http_req: actix_web::HttpRequest,
+    http_req: HttpRequest,
 ) -> GraphQLResponse {
-    // let mut loggedin_user = None;
-    // if let Some(header_data) = http_req
-    //     .headers()
-    //     .get(actix_web::http::header::AUTHORIZATION)
-    // {
-    //     if let Ok(header) = header_data.to_str() {
-    //         if let Some(token) = header.split("Bearer ").last() {
-    //             if let Ok(uuid) = verify_token(&config.jwt_secret, token) {
-    //                 loggedin_user = db.get_user_by_uuid(&uuid).await.unwrap();
-    //             }
-    //         }
-    //     }
-    // }
+    let header = http_req
+        .headers()
+        .get(http::header::AUTHORIZATION)
+        .and_then(|header| header.to_str().ok());
+    let loggedin_user = match authenticate(&**user_repo, &keys, header).await {
+        Ok(user) => user,
+        Err(err) => {
+            let err = err.extend().into_server_error(Pos::default());
+            return Response::from_errors(vec![err]).into();
+        }
+    };
+
+    let loader = ancestors_loader(page_repo.into_inner());
+    schema
+        .execute(req.into_inner().data(loggedin_user).data(loader))
+        .await
+        .into()
+}
 
-    schema.execute(req.into_inner()).await.into()
+/// Serves subscriptions over graphql-ws. Clients authenticate by sending the
+/// `Authorization` header value in the connection init payload.
+async fn index_ws(
+    schema: web::Data<AppSchema>,
+    user_repo: web::Data<dyn UserRepo>,
+    keys: web::Data<KeyStore>,
+    req: HttpRequest,
+    payload: web::Payload,
+) -> actix_web::Result<HttpResponse> {
+    let user_repo = user_repo.into_inner();
+    let keys = keys.into_inner();
+    GraphQLSubscription::new(AppSchema::clone(&schema))
+        .on_connection_init(move |value| async move {
+            let header = value
+                .get("Authorization")
+                .or_else(|| value.get("authorization"))
+                .and_then(|header| header.as_str());
+            let loggedin_user = authenticate(&*user_repo, &keys, header)
+                .await
+                .map_err(|err| err.extend())?;
+            let mut data = GQLData::default();
+            data.insert(loggedin_user);
+            Ok(data)
+        })
+        .start(&req, payload)
 }
 
 async fn gql_playgound() -> HttpResponse {
@@ -66,48 +104,138 @@ async fn gql_playgound() -> HttpResponse {
         .body(
             GraphiQLSource::build()
                 .endpoint("http://localhost:8000")
+                .subscription_endpoint("ws://localhost:8000")
                 .finish(),
         )
 }
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    dotenv().ok();
-    pretty_env_logger::init();
-    let base_config = BaseConfig::build(&mut NopDataSource {}, None)
-        .await
-        .unwrap();
+/// Applies migrations if asked to, and loads the config stored in Postgres.
+/// Returns `None` if the app was only started to migrate.
+async fn postgresql_storage(base_config: BaseConfig) -> Option<(Config, Repos, KeyStore)> {
+    if base_config.database_url.is_empty() {
+        error!("DATABASE_URL must be set with the postgresql storage");
+        std::process::exit(1);
+    }
+    let migrate = std::env::args().any(|arg| arg == "--migrate");
+    let mut conn = PgConnection::establish(&base_config.database_url).unwrap();
+    if let Err(err) = run_migrations(&mut conn, migrate || base_config.auto_migrate) {
+        error!("{}", err);
+        std::process::exit(1);
+    }
+    drop(conn);
+    if migrate {
+        return None;
+    }
     let mut psql_ds = PostgresqlDataSource::new(&base_config.database_url)
         .await
         .unwrap();
-    let config = Config::build(&mut psql_ds, None, base_config)
+    let mut config = Config::build(&mut psql_ds, None, base_config)
         .await
         .unwrap();
-    let config = Arc::new(config);
+    // Other running instances reload the rotated keys once they see a token
+    // signed with the new one, see `KeyStore`.
+    if std::env::args().any(|arg| arg == "--rotate-jwt-key") {
+        let ttl = Duration::seconds(config.access_token_ttl);
+        let kid = config.jwt_keys.rotate(ttl).kid.clone();
+        psql_ds
+            .set("JWT_KEYS", config.jwt_keys.to_string())
+            .await
+            .unwrap();
+        info!("Rotated JWT signing key, new key id: {}", kid);
+    }
+    let images_repo: Arc<dyn ImagesRepo> = match config.images_storage {
+        ImagesStorageKind::S3 => {
+            if config.s3_bucket.is_empty() || config.s3_endpoint.is_empty() {
+                error!("S3_BUCKET and S3_ENDPOINT must be set with the s3 images storage");
+                std::process::exit(1);
+            }
+            Arc::new(S3ImagesRepo::new(&config.s3_bucket, &config.s3_endpoint).unwrap())
+        }
+        ImagesStorageKind::Local => {
+            if let Err(err) = std::fs::create_dir_all(&config.images_dir) {
+                error!("Cannot create IMAGES_DIR {}: {}", config.images_dir, err);
+                std::process::exit(1);
+            }
+            Arc::new(LocalFsImagesRepo::new(
+                &config.images_dir,
+                &config.images_url,
+            ))
+        }
+    };
 
     let manager = ConnectionManager::<PgConnection>::new(&config.base.database_url);
-    let pool = Pool::new(manager).unwrap();
+    let db = Database::new(Pool::new(manager).unwrap());
+    let keys = KeyStore::new(config.jwt_keys.clone(), Some(Box::new(psql_ds)));
+    Some((config, Repos::postgresql(db, images_repo), keys))
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    dotenv().ok();
+    pretty_env_logger::init();
+    let mut base_config = BaseConfig::build(&mut NopDataSource {}, None)
+        .await
+        .unwrap();
+    if let Some(storage) = std::env::args().find_map(|arg| {
+        arg.strip_prefix("--storage=")
+            .map(|storage| storage.to_string())
+    }) {
+        match storage.parse() {
+            Ok(storage) => base_config.storage = storage,
+            Err(_) => {
+                error!("Unknown storage: {}", storage);
+                std::process::exit(1);
+            }
+        }
+    }
+    let (config, repos, keys) = match base_config.storage {
+        StorageKind::Postgresql => match postgresql_storage(base_config).await {
+            Some(storage) => storage,
+            None => return Ok(()),
+        },
+        StorageKind::Memory => {
+            info!("Keeping all data in memory, it's lost when the app stops");
+            let config = Config::build(&mut NopDataSource {}, None, base_config)
+                .await
+                .unwrap();
+            let keys = KeyStore::new(config.jwt_keys.clone(), None);
+            (config, Repos::memory(), keys)
+        }
+    };
+    let config = Arc::new(config);
+    let keys = Arc::new(keys);
 
     info!("GraphiQL IDE: http://localhost:8000");
 
+    let event_broker: Arc<dyn EventBroker> = match config.event_broker {
+        EventBrokerKind::Postgresql if config.base.storage == StorageKind::Memory => {
+            warn!("The postgresql event broker needs the postgresql storage, using the local one");
+            Arc::new(LocalEventBroker::new())
+        }
+        EventBrokerKind::Local => Arc::new(LocalEventBroker::new()),
+        EventBrokerKind::Postgresql => {
+            Arc::new(PostgresqlEventBroker::new(&config.base.database_url))
+        }
+    };
+
+    tokio::spawn(purge_expired_trash(
+        Arc::clone(&repos.workspace_repo),
+        Arc::clone(&repos.page_repo),
+        Arc::clone(&repos.images_repo),
+        config.trash_retention_days,
+    ));
+
+    let schema = schema_builder(&repos, Arc::clone(&config), Arc::clone(&keys), event_broker)
+        .extension(ApolloTracing)
+        .extension(GQLLogger)
+        .extension(Analyzer)
+        .finish();
     let config_clone = Arc::clone(&config);
+    let serve_images = config.base.storage == StorageKind::Postgresql
+        && config.images_storage == ImagesStorageKind::Local;
 
     HttpServer::new(move || {
         let logger = Logger::default();
-        let pool = pool.clone();
-        let user_repo = PostgresqlUsersRepo::new(pool.clone());
-        let userrepo_arc: Arc<dyn UserRepo> = Arc::new(user_repo);
-        let workspace_repo = PostgresqlWorkspaceRepo::new(pool.clone());
-        let workspacerepo_arc: Arc<dyn WorkspaceRepo> = Arc::new(workspace_repo);
-        let s3_images_repo = S3ImagesRepo::new(
-            &config_clone.s3_bucket.clone(),
-            &config_clone.s3_endpoint.clone(),
-        )
-        .unwrap();
-        let s3_images_repo_arc: Arc<dyn ImagesRepo> = Arc::new(s3_images_repo);
-        let page_repo = PageRepo::new(pool.clone());
-        let pagerepo_arc: Arc<dyn repos::traits::PageRepo> = Arc::new(page_repo);
-
         let cors = Cors::default()
             .allow_any_origin()
             .allowed_methods(vec!["GET", "POST"])
@@ -120,29 +248,32 @@ async fn main() -> std::io::Result<()> {
         App::new()
             .wrap(cors)
             .wrap(logger)
-            .app_data(Data::new(
-                Schema::build(
-                    QueryRoot::default(),
-                    MutationsRoot::default(),
-                    EmptySubscription,
-                )
-                .extension(ApolloTracing)
-                .extension(GQLLogger)
-                .extension(Analyzer)
-                .data(Arc::clone(&userrepo_arc))
-                .data(Arc::clone(&config_clone))
-                .data(Arc::clone(&workspacerepo_arc))
-                .data(Arc::clone(&s3_images_repo_arc))
-                .data(Arc::clone(&pagerepo_arc))
-                .finish(),
-            ))
-            .app_data(Data::from(Arc::clone(&userrepo_arc)))
+            .app_data(Data::new(schema.clone()))
+            .app_data(Data::from(Arc::clone(&repos.user_repo)))
             .app_data(Data::from(Arc::clone(&config_clone)))
-            .app_data(Data::from(Arc::clone(&workspacerepo_arc)))
-            .app_data(Data::from(Arc::clone(&s3_images_repo_arc)))
-            .app_data(Data::from(Arc::clone(&pagerepo_arc)))
+            .app_data(Data::from(Arc::clone(&keys)))
+            .app_data(Data::from(Arc::clone(&repos.workspace_repo)))
+            .app_data(Data::from(Arc::clone(&repos.images_repo)))
+            .app_data(Data::from(Arc::clone(&repos.page_repo)))
+            .app_data(Data::from(Arc::clone(&repos.refresh_token_repo)))
+            .app_data(Data::from(Arc::clone(&repos.slot_repo)))
+            .app_data(Data::from(Arc::clone(&repos.atom_repo)))
+            .app_data(Data::from(Arc::clone(&repos.revision_repo)))
+            .app_data(Data::from(Arc::clone(&repos.search_repo)))
+            .app_data(Data::from(Arc::clone(&repos.unit_of_work_repo)))
             .service(web::resource("/").guard(guard::Post()).to(index))
+            .service(
+                web::resource("/")
+                    .guard(guard::Get())
+                    .guard(guard::Header("upgrade", "websocket"))
+                    .to(index_ws),
+            )
             .service(web::resource("/").guard(guard::Get()).to(gql_playgound))
+            .configure(|cfg| {
+                if serve_images {
+                    serve_local_images(cfg, &config_clone.images_dir);
+                }
+            })
     })
     .bind(&config.base.bind_addr.clone())?
     .run()
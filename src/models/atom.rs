@@ -1,5 +1,6 @@
 use crate::schema::atoms;
 use async_graphql::{Enum, SimpleObject};
+use chrono::{NaiveDateTime, Utc};
 use diesel::{
     backend::{self, Backend},
     deserialize::{self, FromSql},
@@ -13,7 +14,7 @@ use std::str::FromStr;
 use strum::{EnumString, IntoStaticStr};
 use uuid::Uuid;
 
-use super::slot::Slot;
+use super::{slot::Slot, AtomPayload};
 
 #[derive(
     Enum,
@@ -32,6 +33,16 @@ use super::slot::Slot;
 #[diesel(sql_type = Text)]
 pub enum AtomType {
     Text,
+    Heading,
+    BulletedListItem,
+    NumberedListItem,
+    TodoListItem,
+    Code,
+    Quote,
+    Divider,
+    Image,
+    PageLink,
+    Math,
 }
 
 impl<DB> ToSql<Text, DB> for AtomType
@@ -70,6 +81,7 @@ where
 )]
 #[diesel(table_name = atoms)]
 #[diesel(belongs_to(Slot, foreign_key = slot_uuid))]
+#[graphql(complex)]
 pub struct Atom {
     /// The slot to which this atom belongs.
     pub slot_uuid: Uuid,
@@ -77,6 +89,31 @@ pub struct Atom {
     pub idx: i32,
     /// The atom's type.
     pub typ: AtomType,
-    /// The atom's data.
+    /// The atom's data as JSON, see `AtomPayload`.
     pub data: Option<String>,
+    /// Identifies the atom independently of its index.
+    pub uuid: Uuid,
+    /// Incremented whenever the atom's data changes.
+    pub version: i32,
+    /// When the atom was last changed.
+    pub updated_at: NaiveDateTime,
+}
+
+impl Atom {
+    pub fn new(slot_uuid: Uuid, idx: i32, typ: AtomType, data: Option<String>) -> Self {
+        Self {
+            slot_uuid,
+            idx,
+            typ,
+            data,
+            uuid: Uuid::new_v4(),
+            version: 0,
+            updated_at: Utc::now().naive_utc(),
+        }
+    }
+
+    /// The url of the image of an image atom.
+    pub fn image_url(&self) -> Option<String> {
+        AtomPayload::image_url(self.typ, self.data.as_deref())
+    }
 }
@@ -0,0 +1,256 @@
+use ::uuid::Uuid;
+use async_graphql::{SimpleObject, Union};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{
+    atom::AtomType,
+    rich_text::{normalize_marks, validate_marks, Mark, MarkError},
+};
+
+/// Maximum length of a code block's language name.
+const LANGUAGE_LEN: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum PayloadError {
+    #[error("Data is required for this atom type")]
+    Missing,
+    #[error("This atom type takes no data")]
+    Unexpected,
+    #[error("Malformed data: {0}")]
+    Malformed(#[from] serde_json::Error),
+    #[error("{0}")]
+    Invalid(&'static str),
+    #[error("{0}")]
+    Marks(#[from] MarkError),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, SimpleObject)]
+#[serde(deny_unknown_fields)]
+pub struct TextPayload {
+    pub text: String,
+    /// The text's formatting.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub marks: Vec<Mark>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, SimpleObject)]
+#[serde(deny_unknown_fields)]
+pub struct HeadingPayload {
+    /// The heading level, from 1 to 6.
+    pub level: u8,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, SimpleObject)]
+#[serde(deny_unknown_fields)]
+pub struct BulletedListItemPayload {
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, SimpleObject)]
+#[serde(deny_unknown_fields)]
+pub struct NumberedListItemPayload {
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, SimpleObject)]
+#[serde(deny_unknown_fields)]
+pub struct TodoListItemPayload {
+    pub text: String,
+    #[serde(default)]
+    pub checked: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, SimpleObject)]
+#[serde(deny_unknown_fields)]
+pub struct CodePayload {
+    pub code: String,
+    /// The language used for syntax highlighting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, SimpleObject)]
+#[serde(deny_unknown_fields)]
+pub struct QuotePayload {
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, SimpleObject)]
+#[serde(deny_unknown_fields)]
+pub struct ImagePayload {
+    /// The url returned by the images repository.
+    #[serde(default)]
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, SimpleObject)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct PageLinkPayload {
+    /// The linked page, which must be in the same workspace.
+    pub page_uuid: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, SimpleObject)]
+#[serde(deny_unknown_fields)]
+pub struct MathPayload {
+    /// The formula in TeX notation.
+    pub tex: String,
+}
+
+/// The typed data of an atom, stored as JSON in `atoms.data`.
+///
+/// Dividers carry no data and have no payload.
+#[derive(Debug, Clone, PartialEq, Eq, Union)]
+pub enum AtomPayload {
+    Text(TextPayload),
+    Heading(HeadingPayload),
+    BulletedListItem(BulletedListItemPayload),
+    NumberedListItem(NumberedListItemPayload),
+    TodoListItem(TodoListItemPayload),
+    Code(CodePayload),
+    Quote(QuotePayload),
+    Image(ImagePayload),
+    PageLink(PageLinkPayload),
+    Math(MathPayload),
+}
+
+fn parse<T: DeserializeOwned>(data: Option<&str>) -> Result<T, PayloadError> {
+    Ok(serde_json::from_str(data.ok_or(PayloadError::Missing)?)?)
+}
+
+impl AtomPayload {
+    /// Parses and validates the data of an atom of type `typ`.
+    pub fn parse(typ: AtomType, data: Option<&str>) -> Result<Option<Self>, PayloadError> {
+        let payload = Self::from_json(typ, data)?;
+        if let Some(payload) = &payload {
+            payload.validate()?;
+        }
+        Ok(payload)
+    }
+
+    /// Parses the data of an atom of type `typ` without validating it.
+    pub fn from_json(typ: AtomType, data: Option<&str>) -> Result<Option<Self>, PayloadError> {
+        let payload = match typ {
+            AtomType::Divider => {
+                return match data {
+                    Some(_) => Err(PayloadError::Unexpected),
+                    None => Ok(None),
+                }
+            }
+            AtomType::Text => Self::Text(parse(data)?),
+            AtomType::Heading => Self::Heading(parse(data)?),
+            AtomType::BulletedListItem => Self::BulletedListItem(parse(data)?),
+            AtomType::NumberedListItem => Self::NumberedListItem(parse(data)?),
+            AtomType::TodoListItem => Self::TodoListItem(parse(data)?),
+            AtomType::Code => Self::Code(parse(data)?),
+            AtomType::Quote => Self::Quote(parse(data)?),
+            AtomType::Image => Self::Image(parse(data)?),
+            AtomType::PageLink => Self::PageLink(parse(data)?),
+            AtomType::Math => Self::Math(parse(data)?),
+        };
+        Ok(Some(payload))
+    }
+
+    pub fn validate(&self) -> Result<(), PayloadError> {
+        match self {
+            Self::Text(text) => Ok(validate_marks(&text.text, &text.marks)?),
+            Self::Heading(heading) if !(1..=6).contains(&heading.level) => Err(
+                PayloadError::Invalid("Heading level must be between 1 and 6"),
+            ),
+            Self::Code(CodePayload {
+                language: Some(language),
+                ..
+            }) if language.is_empty() || language.len() > LANGUAGE_LEN => {
+                Err(PayloadError::Invalid("Invalid code language"))
+            }
+            Self::Image(image) if image.url.is_empty() => {
+                Err(PayloadError::Invalid("Image is required"))
+            }
+            Self::Math(math) if math.tex.trim().is_empty() => {
+                Err(PayloadError::Invalid("Formula is required"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The url of the image of an image atom's data.
+    pub fn image_url(typ: AtomType, data: Option<&str>) -> Option<String> {
+        match Self::from_json(typ, data) {
+            Ok(Some(Self::Image(image))) if !image.url.is_empty() => Some(image.url),
+            _ => None,
+        }
+    }
+
+    /// Brings the payload into its canonical form.
+    pub fn normalize(&mut self) {
+        if let Self::Text(text) = self {
+            normalize_marks(&mut text.marks);
+        }
+    }
+
+    /// Serializes the payload into the JSON stored in `atoms.data`.
+    pub fn to_json(&self) -> String {
+        let json = match self {
+            Self::Text(payload) => serde_json::to_string(payload),
+            Self::Heading(payload) => serde_json::to_string(payload),
+            Self::BulletedListItem(payload) => serde_json::to_string(payload),
+            Self::NumberedListItem(payload) => serde_json::to_string(payload),
+            Self::TodoListItem(payload) => serde_json::to_string(payload),
+            Self::Code(payload) => serde_json::to_string(payload),
+            Self::Quote(payload) => serde_json::to_string(payload),
+            Self::Image(payload) => serde_json::to_string(payload),
+            Self::PageLink(payload) => serde_json::to_string(payload),
+            Self::Math(payload) => serde_json::to_string(payload),
+        };
+        json.expect("payloads serialize to JSON")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_payloads() {
+        let heading = AtomPayload::parse(AtomType::Heading, Some(r#"{"level":2,"text":"Hi"}"#));
+        assert_eq!(
+            heading.unwrap(),
+            Some(AtomPayload::Heading(HeadingPayload {
+                level: 2,
+                text: "Hi".to_string()
+            }))
+        );
+        let todo = AtomPayload::parse(AtomType::TodoListItem, Some(r#"{"text":"a"}"#)).unwrap();
+        assert_eq!(todo.unwrap().to_json(), r#"{"text":"a","checked":false}"#);
+        assert!(AtomPayload::parse(AtomType::Divider, None)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_reject_malformed_payloads() {
+        let cases = [
+            (AtomType::Text, None),
+            (AtomType::Text, Some("plain text")),
+            (AtomType::Text, Some(r#"{"text":"a","level":1}"#)),
+            (AtomType::Heading, Some(r#"{"level":7,"text":"a"}"#)),
+            (AtomType::Code, Some(r#"{"code":"a","language":""}"#)),
+            (AtomType::Divider, Some("{}")),
+            (AtomType::Image, Some("{}")),
+            (AtomType::PageLink, Some(r#"{"pageUuid":"nope"}"#)),
+            (AtomType::Math, Some(r#"{"tex":" "}"#)),
+        ];
+        for (typ, data) in cases {
+            assert!(
+                AtomPayload::parse(typ, data).is_err(),
+                "{:?} {:?}",
+                typ,
+                data
+            );
+        }
+    }
+}
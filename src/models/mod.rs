@@ -1,10 +1,21 @@
 pub mod atom;
+pub mod atom_payload;
 pub mod page;
+pub mod page_revision;
+pub mod refresh_token;
+pub mod rich_text;
+pub mod search;
 pub mod slot;
+pub mod text_ops;
 pub mod user;
 pub mod workspace;
+pub mod workspace_member;
 pub use atom::{Atom, AtomType};
+pub use atom_payload::AtomPayload;
 pub use page::Page;
+pub use page_revision::PageRevision;
+pub use refresh_token::RefreshToken;
 pub use slot::Slot;
 pub use user::User;
 pub use workspace::Workspace;
+pub use workspace_member::{WorkspaceMember, WorkspaceRole};
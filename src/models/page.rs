@@ -1,5 +1,8 @@
+use std::collections::{HashMap, HashSet};
+
 use ::uuid::Uuid;
 use async_graphql::SimpleObject;
+use chrono::{NaiveDateTime, Utc};
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -14,12 +17,14 @@ use super::workspace::Workspace;
     Clone,
     SimpleObject,
     Queryable,
+    QueryableByName,
     Insertable,
     AsChangeset,
     Associations,
 )]
 #[diesel(table_name = pages)]
 #[diesel(belongs_to(Workspace, foreign_key = workspace_uuid))]
+#[graphql(complex)]
 pub struct Page {
     /// The workspace to which this page belongs.
     pub workspace_uuid: Uuid,
@@ -32,15 +37,109 @@ pub struct Page {
 
     /// The page's image or icon.
     pub image: Option<String>,
+    /// Incremented by every update. Updates must pass the version they're
+    /// based on, see `ConflictError`.
+    pub version: i32,
+    /// When the page was last changed.
+    pub updated_at: NaiveDateTime,
+    /// When the page was moved to the trash.
+    pub deleted_at: Option<NaiveDateTime>,
+    /// The page this page is nested under, or `None` for a root page.
+    pub parent_page_uuid: Option<Uuid>,
+    /// The page's fractional index key among its siblings, see
+    /// `utils::fractional_index`.
+    pub order: String,
 }
 
 impl Page {
-    pub fn new(workspace_uuid: Uuid, title: String, image: Option<String>) -> Self {
+    pub fn new(
+        workspace_uuid: Uuid,
+        parent_page_uuid: Option<Uuid>,
+        order: String,
+        title: String,
+        image: Option<String>,
+    ) -> Self {
         Self {
             workspace_uuid,
             uuid: Uuid::new_v4(),
             title,
             image,
+            version: 0,
+            updated_at: Utc::now().naive_utc(),
+            deleted_at: None,
+            parent_page_uuid,
+            order,
         }
     }
 }
+
+/// The outcome of `PageRepo::move_page`.
+pub enum PageMove {
+    Moved(Page),
+    /// The page changed since it was loaded, or was deleted.
+    Outdated,
+    /// The new parent is the page itself or nested under it.
+    Cycle,
+}
+
+/// Sorts the pages of a workspace depth-first, each page followed by its
+/// children. Pages whose parent isn't among `pages` are treated as roots.
+pub fn sort_tree(pages: Vec<Page>) -> Vec<Page> {
+    let uuids = pages.iter().map(|page| page.uuid).collect::<HashSet<_>>();
+    let mut children = HashMap::<Option<Uuid>, Vec<Page>>::new();
+    for page in pages {
+        let parent = page
+            .parent_page_uuid
+            .filter(|parent| uuids.contains(parent));
+        children.entry(parent).or_default().push(page);
+    }
+    for siblings in children.values_mut() {
+        // Popped from the back below.
+        siblings.sort_by(|a, b| (&b.order, b.uuid).cmp(&(&a.order, a.uuid)));
+    }
+
+    let mut sorted = Vec::with_capacity(uuids.len());
+    let mut stack = children.remove(&None).unwrap_or_default();
+    while let Some(page) = stack.pop() {
+        if let Some(page_children) = children.remove(&Some(page.uuid)) {
+            stack.extend(page_children);
+        }
+        sorted.push(page);
+    }
+    sorted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(n: u128, parent: Option<u128>, order: &str) -> Page {
+        Page {
+            uuid: Uuid::from_u128(n),
+            ..Page::new(
+                Uuid::nil(),
+                parent.map(Uuid::from_u128),
+                order.to_string(),
+                n.to_string(),
+                None,
+            )
+        }
+    }
+
+    #[test]
+    fn test_sort_tree() {
+        let pages = vec![
+            page(1, None, "b"),
+            page(2, Some(1), "b"),
+            page(3, None, "a"),
+            page(4, Some(1), "a"),
+            page(5, Some(4), "V"),
+            page(6, Some(99), "c"),
+        ];
+        let sorted = sort_tree(pages)
+            .iter()
+            .map(|page| page.uuid.as_u128())
+            .collect::<Vec<_>>();
+        assert_eq!(sorted, vec![3, 1, 4, 5, 2, 6]);
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+
+use ::uuid::Uuid;
+use async_graphql::{Enum, SimpleObject};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::page_revisions;
+
+use super::{atom::AtomType, page::Page, AtomPayload};
+
+/// A snapshot of a page's content after a change, or after a series of
+/// changes made by the same user in quick succession.
+///
+/// Images stay in storage while a revision may refer to them, so they're only
+/// deleted once their page is purged.
+#[derive(Debug, Clone, SimpleObject, Queryable, Insertable, Associations)]
+#[diesel(table_name = page_revisions)]
+#[diesel(belongs_to(Page, foreign_key = page_uuid))]
+#[graphql(complex)]
+pub struct PageRevision {
+    /// The page this revision belongs to.
+    pub page_uuid: Uuid,
+    /// Numbers the revisions of a page from 1.
+    pub number: i32,
+    /// The user who made the changes.
+    pub user_uuid: Option<Uuid>,
+    /// When the first change of this revision was made.
+    pub created_at: NaiveDateTime,
+    /// When the last change of this revision was made.
+    pub updated_at: NaiveDateTime,
+    /// The page's content as a `PageSnapshot` in JSON.
+    #[graphql(skip)]
+    pub snapshot: String,
+}
+
+impl PageRevision {
+    pub fn parse_snapshot(&self) -> serde_json::Result<PageSnapshot> {
+        serde_json::from_str(&self.snapshot)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, SimpleObject)]
+pub struct PageSnapshot {
+    pub title: String,
+    pub image: Option<String>,
+    /// The page's slots, sorted by their order.
+    pub slots: Vec<SlotSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, SimpleObject)]
+pub struct SlotSnapshot {
+    pub uuid: Uuid,
+    pub order: String,
+    /// The slot's atoms, sorted by their index.
+    pub atoms: Vec<AtomSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, SimpleObject)]
+pub struct AtomSnapshot {
+    pub uuid: Uuid,
+    pub typ: AtomType,
+    pub data: Option<String>,
+}
+
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AtomChangeKind {
+    Added,
+    Removed,
+    /// The atom's type or data changed.
+    Updated,
+    /// The atom was moved to another slot or reordered within its slot.
+    Moved,
+}
+
+/// A change to an atom between two revisions. An atom that was both updated
+/// and moved has a change of each kind.
+#[derive(Debug, Clone, PartialEq, Eq, SimpleObject)]
+pub struct AtomChange {
+    pub kind: AtomChangeKind,
+    pub atom_uuid: Uuid,
+    /// The atom's slot in the newer revision, or in the older one if it was
+    /// removed.
+    pub slot_uuid: Uuid,
+    /// The atom in the older revision.
+    pub before: Option<AtomSnapshot>,
+    /// The atom in the newer revision.
+    pub after: Option<AtomSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, SimpleObject)]
+pub struct RevisionDiff {
+    pub title_changed: bool,
+    pub image_changed: bool,
+    /// The atom changes, in the order of the newer revision with removed
+    /// atoms last.
+    pub atoms: Vec<AtomChange>,
+}
+
+/// Returns the atoms of `a` and `b`, given in the same order, that aren't part
+/// of their longest common subsequence.
+fn out_of_order(a: &[Uuid], b: &[Uuid]) -> Vec<Uuid> {
+    // lengths[i][j] is the length of the LCS of a[i..] and b[j..].
+    let mut lengths = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if a[i] == b[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut moved = Vec::new();
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            moved.push(a[i]);
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    moved.extend(&a[i..]);
+    moved
+}
+
+impl PageSnapshot {
+    fn atoms(&self) -> impl Iterator<Item = (&SlotSnapshot, &AtomSnapshot)> {
+        self.slots
+            .iter()
+            .flat_map(|slot| slot.atoms.iter().map(move |atom| (slot, atom)))
+    }
+
+    /// The urls of the page image and the images of image atoms.
+    pub fn image_urls(&self) -> Vec<String> {
+        let atom_images = self
+            .atoms()
+            .filter_map(|(_, atom)| AtomPayload::image_url(atom.typ, atom.data.as_deref()));
+        self.image.iter().cloned().chain(atom_images).collect()
+    }
+
+    /// Compares this snapshot with a newer one.
+    pub fn diff(&self, newer: &PageSnapshot) -> RevisionDiff {
+        let old_atoms = self
+            .atoms()
+            .map(|(slot, atom)| (atom.uuid, (slot, atom)))
+            .collect::<HashMap<_, _>>();
+        let new_atoms = newer
+            .atoms()
+            .map(|(slot, atom)| (atom.uuid, (slot, atom)))
+            .collect::<HashMap<_, _>>();
+
+        // Atoms that stayed in their slot but not in the same order relative
+        // to the other atoms that stayed.
+        let mut reordered = Vec::new();
+        for new_slot in &newer.slots {
+            if let Some(old_slot) = self.slots.iter().find(|slot| slot.uuid == new_slot.uuid) {
+                let stayed = |from: &SlotSnapshot, other: &SlotSnapshot| {
+                    from.atoms
+                        .iter()
+                        .map(|atom| atom.uuid)
+                        .filter(|uuid| other.atoms.iter().any(|atom| atom.uuid == *uuid))
+                        .collect::<Vec<_>>()
+                };
+                reordered.extend(out_of_order(
+                    &stayed(new_slot, old_slot),
+                    &stayed(old_slot, new_slot),
+                ));
+            }
+        }
+
+        let mut changes = Vec::new();
+        for (slot, atom) in newer.atoms() {
+            let change = |kind, before: Option<&AtomSnapshot>| AtomChange {
+                kind,
+                atom_uuid: atom.uuid,
+                slot_uuid: slot.uuid,
+                before: before.cloned(),
+                after: Some(atom.clone()),
+            };
+            match old_atoms.get(&atom.uuid) {
+                None => changes.push(change(AtomChangeKind::Added, None)),
+                Some((old_slot, old_atom)) => {
+                    if old_atom.typ != atom.typ || old_atom.data != atom.data {
+                        changes.push(change(AtomChangeKind::Updated, Some(old_atom)));
+                    }
+                    if old_slot.uuid != slot.uuid || reordered.contains(&atom.uuid) {
+                        changes.push(change(AtomChangeKind::Moved, Some(old_atom)));
+                    }
+                }
+            }
+        }
+        for (slot, atom) in self.atoms() {
+            if !new_atoms.contains_key(&atom.uuid) {
+                changes.push(AtomChange {
+                    kind: AtomChangeKind::Removed,
+                    atom_uuid: atom.uuid,
+                    slot_uuid: slot.uuid,
+                    before: Some(atom.clone()),
+                    after: None,
+                });
+            }
+        }
+
+        RevisionDiff {
+            title_changed: self.title != newer.title,
+            image_changed: self.image != newer.image,
+            atoms: changes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn atom(n: u128, text: &str) -> AtomSnapshot {
+        AtomSnapshot {
+            uuid: Uuid::from_u128(n),
+            typ: AtomType::Text,
+            data: Some(format!(r#"{{"text":"{}"}}"#, text)),
+        }
+    }
+
+    fn slot(n: u128, atoms: Vec<AtomSnapshot>) -> SlotSnapshot {
+        SlotSnapshot {
+            uuid: Uuid::from_u128(n),
+            order: n.to_string(),
+            atoms,
+        }
+    }
+
+    fn page(slots: Vec<SlotSnapshot>) -> PageSnapshot {
+        PageSnapshot {
+            title: "Page".to_string(),
+            image: None,
+            slots,
+        }
+    }
+
+    fn changes(diff: &RevisionDiff) -> Vec<(AtomChangeKind, u128)> {
+        diff.atoms
+            .iter()
+            .map(|change| (change.kind, change.atom_uuid.as_u128()))
+            .collect()
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = page(vec![
+            slot(
+                100,
+                vec![atom(1, "a"), atom(2, "b"), atom(3, "c"), atom(4, "d")],
+            ),
+            slot(200, vec![atom(5, "e")]),
+        ]);
+        let new = page(vec![
+            slot(
+                100,
+                vec![atom(6, "f"), atom(2, "b"), atom(3, "c"), atom(1, "a")],
+            ),
+            slot(200, vec![atom(5, "E"), atom(4, "d")]),
+        ]);
+        let diff = old.diff(&new);
+        assert!(!diff.title_changed);
+        assert_eq!(
+            changes(&diff),
+            vec![
+                (AtomChangeKind::Added, 6),
+                (AtomChangeKind::Moved, 1),
+                (AtomChangeKind::Updated, 5),
+                (AtomChangeKind::Moved, 4),
+            ]
+        );
+        assert_eq!(
+            changes(&new.diff(&old)),
+            vec![
+                (AtomChangeKind::Moved, 1),
+                (AtomChangeKind::Moved, 4),
+                (AtomChangeKind::Updated, 5),
+                (AtomChangeKind::Removed, 6),
+            ]
+        );
+        assert!(changes(&old.diff(&old)).is_empty());
+    }
+
+    #[test]
+    fn test_image_urls() {
+        let image = AtomSnapshot {
+            uuid: Uuid::from_u128(2),
+            typ: AtomType::Image,
+            data: Some(r#"{"url":"https://s3/b.png"}"#.to_string()),
+        };
+        let snapshot = PageSnapshot {
+            image: Some("https://s3/a.png".to_string()),
+            ..page(vec![slot(100, vec![atom(1, "a"), image])])
+        };
+        assert_eq!(
+            snapshot.image_urls(),
+            vec![
+                "https://s3/a.png".to_string(),
+                "https://s3/b.png".to_string()
+            ]
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use ::uuid::Uuid;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    schema::refresh_tokens,
+    utils::random::{random_hex, to_hex},
+};
+
+use super::user::User;
+
+/// A long-lived token used to obtain new access tokens.
+///
+/// Only a hash of the token is stored. Every refresh rotates the token within
+/// its family, so presenting a revoked token reveals that it was leaked.
+#[derive(Debug, Clone, Queryable, Insertable, Associations)]
+#[diesel(table_name = refresh_tokens)]
+#[diesel(belongs_to(User, foreign_key = user_uuid))]
+pub struct RefreshToken {
+    /// The token's unique identifier.
+    pub uuid: Uuid,
+
+    /// The user the token was issued to.
+    pub user_uuid: Uuid,
+
+    /// The login session this token was rotated from.
+    pub family_uuid: Uuid,
+
+    /// The SHA-256 hash of the token, hex encoded.
+    pub token_hash: String,
+
+    /// When the token was issued.
+    pub created_at: NaiveDateTime,
+
+    /// When the token stops being valid.
+    pub expires_at: NaiveDateTime,
+
+    /// When the token was used or revoked.
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+impl RefreshToken {
+    /// Creates a new token in `family_uuid` and returns it together with its
+    /// plaintext value, which is never stored.
+    pub fn new(user_uuid: Uuid, family_uuid: Uuid, ttl: Duration) -> (Self, String) {
+        let token = random_hex(32);
+        let now = Utc::now().naive_utc();
+        let refresh_token = Self {
+            uuid: Uuid::new_v4(),
+            user_uuid,
+            family_uuid,
+            token_hash: Self::hash(&token),
+            created_at: now,
+            expires_at: now + ttl,
+            revoked_at: None,
+        };
+        (refresh_token, token)
+    }
+
+    pub fn hash(token: &str) -> String {
+        to_hex(&Sha256::digest(token.as_bytes()))
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now().naive_utc()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_refresh_token() {
+        let family = Uuid::new_v4();
+        let (refresh_token, token) = RefreshToken::new(Uuid::new_v4(), family, Duration::days(1));
+        assert_eq!(token.len(), 64);
+        assert_eq!(refresh_token.token_hash, RefreshToken::hash(&token));
+        assert_ne!(refresh_token.token_hash, token);
+        assert_eq!(refresh_token.family_uuid, family);
+        assert!(!refresh_token.is_expired());
+    }
+}
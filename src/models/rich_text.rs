@@ -0,0 +1,259 @@
+use async_graphql::{Enum, SimpleObject};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Maximum length of a link mark's target.
+const HREF_LEN: usize = 2048;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum MarkError {
+    #[error("Mark range {0}..{1} is outside of the text")]
+    OutOfRange(usize, usize),
+    #[error("Link marks need an http, https or mailto target")]
+    InvalidHref,
+    #[error("Colour marks need a #rrggbb colour")]
+    InvalidColor,
+    #[error("Only link marks have a target and only colour marks have a colour")]
+    UnexpectedAttribute,
+    #[error("Overlapping {0:?} marks must be identical")]
+    Overlap(MarkType),
+}
+
+#[derive(
+    Enum, Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkType {
+    Bold,
+    Italic,
+    Underline,
+    Strikethrough,
+    Code,
+    Link,
+    Color,
+}
+
+/// Formatting applied to the characters `start..end` of a text.
+///
+/// Offsets count Unicode scalar values, not bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, SimpleObject)]
+#[serde(deny_unknown_fields)]
+pub struct Mark {
+    pub start: usize,
+    pub end: usize,
+    #[serde(rename = "type")]
+    pub typ: MarkType,
+    /// The target of a link mark.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub href: Option<String>,
+    /// The colour of a colour mark, as `#rrggbb`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+impl Mark {
+    fn validate(&self, len: usize) -> Result<(), MarkError> {
+        if self.start >= self.end || self.end > len {
+            return Err(MarkError::OutOfRange(self.start, self.end));
+        }
+        match (self.typ, &self.href, &self.color) {
+            (MarkType::Link, Some(href), None) => {
+                let scheme_ok = ["http://", "https://", "mailto:"]
+                    .iter()
+                    .any(|scheme| href.starts_with(scheme));
+                if !scheme_ok || href.len() > HREF_LEN {
+                    return Err(MarkError::InvalidHref);
+                }
+            }
+            (MarkType::Link, None, _) => return Err(MarkError::InvalidHref),
+            (MarkType::Color, None, Some(color)) => {
+                let hex = color.strip_prefix('#').unwrap_or_default();
+                if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(MarkError::InvalidColor);
+                }
+            }
+            (MarkType::Color, _, None) => return Err(MarkError::InvalidColor),
+            (_, None, None) => {}
+            _ => return Err(MarkError::UnexpectedAttribute),
+        }
+        Ok(())
+    }
+
+    /// Whether both marks apply the same formatting, ignoring their ranges.
+    fn same_format(&self, other: &Mark) -> bool {
+        self.typ == other.typ && self.href == other.href && self.color == other.color
+    }
+}
+
+/// Checks that every mark lies within `text` and is well formed, and that
+/// marks of the same type only overlap if they're identical.
+pub fn validate_marks(text: &str, marks: &[Mark]) -> Result<(), MarkError> {
+    let len = text.chars().count();
+    for mark in marks {
+        mark.validate(len)?;
+    }
+    let mut sorted = marks.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|mark| (mark.typ, mark.start));
+    // The mark reaching furthest among those of the current type seen so far.
+    let mut furthest: Option<&Mark> = None;
+    for mark in sorted {
+        match furthest {
+            Some(prev) if prev.typ == mark.typ => {
+                if mark.start < prev.end && !prev.same_format(mark) {
+                    return Err(MarkError::Overlap(mark.typ));
+                }
+                if mark.end > prev.end {
+                    furthest = Some(mark);
+                }
+            }
+            _ => furthest = Some(mark),
+        }
+    }
+    Ok(())
+}
+
+/// Merges identical marks that touch or overlap and sorts the result by
+/// position, so equal formatting always has the same representation.
+pub fn normalize_marks(marks: &mut Vec<Mark>) {
+    marks.sort_by(|a, b| {
+        (a.typ, &a.href, &a.color, a.start, a.end).cmp(&(b.typ, &b.href, &b.color, b.start, b.end))
+    });
+    let mut merged: Vec<Mark> = Vec::with_capacity(marks.len());
+    for mark in marks.drain(..) {
+        match merged.last_mut() {
+            Some(last) if last.same_format(&mark) && mark.start <= last.end => {
+                last.end = last.end.max(mark.end);
+            }
+            _ => merged.push(mark),
+        }
+    }
+    merged.sort_by_key(|mark| (mark.start, mark.end, mark.typ));
+    *marks = merged;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{
+        atom_payload::{AtomPayload, TextPayload},
+        AtomType,
+    };
+
+    fn mark(start: usize, end: usize, typ: MarkType) -> Mark {
+        Mark {
+            start,
+            end,
+            typ,
+            href: None,
+            color: None,
+        }
+    }
+
+    fn link(start: usize, end: usize, href: &str) -> Mark {
+        Mark {
+            href: Some(href.to_string()),
+            ..mark(start, end, MarkType::Link)
+        }
+    }
+
+    #[test]
+    fn test_validate_marks() {
+        let text = "héllo world";
+        assert!(validate_marks(text, &[mark(0, 11, MarkType::Bold)]).is_ok());
+        assert!(validate_marks(
+            text,
+            &[mark(0, 5, MarkType::Bold), mark(2, 8, MarkType::Italic)]
+        )
+        .is_ok());
+        assert_eq!(
+            validate_marks(text, &[mark(0, 12, MarkType::Bold)]),
+            Err(MarkError::OutOfRange(0, 12))
+        );
+        assert_eq!(
+            validate_marks(text, &[mark(3, 3, MarkType::Bold)]),
+            Err(MarkError::OutOfRange(3, 3))
+        );
+        assert_eq!(
+            validate_marks(text, &[link(0, 5, "javascript:alert(1)")]),
+            Err(MarkError::InvalidHref)
+        );
+        assert_eq!(
+            validate_marks(
+                text,
+                &[link(0, 5, "https://a.com"), link(4, 8, "https://b.com")]
+            ),
+            Err(MarkError::Overlap(MarkType::Link))
+        );
+        let nested = [
+            link(0, 9, "https://a.com"),
+            link(1, 2, "https://a.com"),
+            link(3, 4, "https://b.com"),
+        ];
+        assert_eq!(
+            validate_marks(text, &nested),
+            Err(MarkError::Overlap(MarkType::Link))
+        );
+        let red = Mark {
+            color: Some("#ff0000".to_string()),
+            ..mark(0, 5, MarkType::Color)
+        };
+        assert!(validate_marks(text, std::slice::from_ref(&red)).is_ok());
+        let red = Mark {
+            color: Some("red".to_string()),
+            ..red
+        };
+        assert_eq!(validate_marks(text, &[red]), Err(MarkError::InvalidColor));
+        let bold_link = Mark {
+            href: Some("https://a.com".to_string()),
+            ..mark(0, 5, MarkType::Bold)
+        };
+        assert_eq!(
+            validate_marks(text, &[bold_link]),
+            Err(MarkError::UnexpectedAttribute)
+        );
+    }
+
+    #[test]
+    fn test_normalize_marks() {
+        let mut marks = vec![
+            mark(5, 8, MarkType::Bold),
+            link(0, 2, "https://a.com"),
+            mark(0, 3, MarkType::Bold),
+            mark(3, 5, MarkType::Bold),
+            link(2, 4, "https://b.com"),
+            mark(6, 7, MarkType::Bold),
+        ];
+        normalize_marks(&mut marks);
+        assert_eq!(
+            marks,
+            vec![
+                link(0, 2, "https://a.com"),
+                mark(0, 8, MarkType::Bold),
+                link(2, 4, "https://b.com"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_text_payload_round_trip() {
+        let payload = AtomPayload::Text(TextPayload {
+            text: "bold and linked".to_string(),
+            marks: vec![mark(0, 4, MarkType::Bold), link(9, 15, "https://a.com")],
+        });
+        let json = payload.to_json();
+        assert_eq!(
+            json,
+            r#"{"text":"bold and linked","marks":[{"start":0,"end":4,"type":"bold"},{"start":9,"end":15,"type":"link","href":"https://a.com"}]}"#
+        );
+        let parsed = AtomPayload::parse(AtomType::Text, Some(&json))
+            .unwrap()
+            .unwrap();
+        assert_eq!(parsed, payload);
+        assert_eq!(parsed.to_json(), json);
+
+        let plain = AtomPayload::parse(AtomType::Text, Some(r#"{"text":"a"}"#))
+            .unwrap()
+            .unwrap();
+        assert_eq!(plain.to_json(), r#"{"text":"a"}"#);
+    }
+}
@@ -0,0 +1,81 @@
+use ::uuid::Uuid;
+use async_graphql::SimpleObject;
+
+/// Marks the start of a highlighted match in the snippets returned by the
+/// database. Private use characters can't clash with the indexed text the way
+/// markup could.
+pub const HIGHLIGHT_START: char = '\u{E000}';
+/// Marks the end of a highlighted match, see `HIGHLIGHT_START`.
+pub const HIGHLIGHT_END: char = '\u{E001}';
+
+/// A part of a snippet matching the search query. Offsets count Unicode scalar
+/// values, like those of marks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, SimpleObject)]
+pub struct Highlight {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A page title or atom matching a search query.
+#[derive(Debug, Clone, PartialEq, SimpleObject)]
+#[graphql(complex)]
+pub struct SearchHit {
+    pub page_uuid: Uuid,
+    /// The slot of the matching atom, or `None` if the page's title matched.
+    pub slot_uuid: Option<Uuid>,
+    /// The matching atom, or `None` if the page's title matched.
+    pub atom_uuid: Option<Uuid>,
+    /// How well the hit matches the query. Title matches rank above headings,
+    /// which rank above other atoms.
+    pub rank: f32,
+    /// An excerpt of the matching text.
+    pub snippet: String,
+    /// The matches within the snippet.
+    pub highlights: Vec<Highlight>,
+}
+
+/// Strips the highlight markers from a snippet and returns the plain snippet
+/// with the highlighted ranges.
+pub fn parse_snippet(marked: &str) -> (String, Vec<Highlight>) {
+    let mut snippet = String::with_capacity(marked.len());
+    let mut highlights = Vec::new();
+    let mut len = 0;
+    let mut start = None;
+    for c in marked.chars() {
+        match c {
+            HIGHLIGHT_START => start = Some(len),
+            HIGHLIGHT_END => {
+                if let Some(start) = start.take().filter(|start| *start < len) {
+                    highlights.push(Highlight { start, end: len });
+                }
+            }
+            c => {
+                snippet.push(c);
+                len += 1;
+            }
+        }
+    }
+    (snippet, highlights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_snippet() {
+        let marked = format!(
+            "Sé {}café{} and {}tea{}{}",
+            HIGHLIGHT_START, HIGHLIGHT_END, HIGHLIGHT_START, HIGHLIGHT_END, HIGHLIGHT_END
+        );
+        let (snippet, highlights) = parse_snippet(&marked);
+        assert_eq!(snippet, "Sé café and tea");
+        assert_eq!(
+            highlights,
+            vec![
+                Highlight { start: 3, end: 7 },
+                Highlight { start: 12, end: 15 }
+            ]
+        );
+    }
+}
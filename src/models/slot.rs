@@ -1,6 +1,7 @@
 use crate::schema::slots;
 use ::uuid::Uuid;
 use async_graphql::SimpleObject;
+use chrono::{NaiveDateTime, Utc};
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -19,11 +20,29 @@ use super::page::Page;
 )]
 #[diesel(table_name = slots)]
 #[diesel(belongs_to(Page, foreign_key = page_uuid))]
+#[graphql(complex)]
 pub struct Slot {
     /// The page to which this slot belongs.
     pub page_uuid: Uuid,
     /// The slot's unique identifier.
     pub uuid: Uuid,
-    /// The slot's order.
+    /// The slot's fractional index key, see `utils::fractional_index`.
     pub order: String,
+    /// Incremented by every update. Updates must pass the version they're
+    /// based on, see `ConflictError`.
+    pub version: i32,
+    /// When the slot was last changed.
+    pub updated_at: NaiveDateTime,
+}
+
+impl Slot {
+    pub fn new(page_uuid: Uuid, order: String) -> Self {
+        Self {
+            page_uuid,
+            uuid: Uuid::new_v4(),
+            order,
+            version: 0,
+            updated_at: Utc::now().naive_utc(),
+        }
+    }
 }
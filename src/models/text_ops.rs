@@ -0,0 +1,518 @@
+use async_graphql::{InputObject, OneofObject, SimpleObject, Union};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::{
+    atom_payload::TextPayload,
+    rich_text::{normalize_marks, Mark},
+};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TextOpError {
+    #[error("Edit at {0} is outside of the text")]
+    OutOfRange(usize),
+}
+
+/// Inserts `text` before the character at `pos`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, SimpleObject, InputObject)]
+#[graphql(input_name = "InsertTextInput")]
+pub struct InsertText {
+    pub pos: usize,
+    pub text: String,
+}
+
+/// Deletes `len` characters starting at `pos`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, SimpleObject, InputObject)]
+#[graphql(input_name = "DeleteTextInput")]
+pub struct DeleteText {
+    pub pos: usize,
+    pub len: usize,
+}
+
+/// A single change to a text. Like mark offsets, positions count Unicode
+/// scalar values.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Union)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum TextOp {
+    Insert(InsertText),
+    Delete(DeleteText),
+}
+
+#[derive(OneofObject)]
+pub enum TextOpInput {
+    Insert(InsertText),
+    Delete(DeleteText),
+}
+
+impl From<TextOpInput> for TextOp {
+    fn from(op: TextOpInput) -> Self {
+        match op {
+            TextOpInput::Insert(op) => Self::Insert(op),
+            TextOpInput::Delete(op) => Self::Delete(op),
+        }
+    }
+}
+
+/// The ops that took a text atom from `version - 1` to `version`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, SimpleObject)]
+pub struct TextEdit {
+    pub atom_uuid: Uuid,
+    pub version: i32,
+    pub ops: Vec<TextOp>,
+}
+
+fn insert(pos: usize, text: &str) -> TextOp {
+    TextOp::Insert(InsertText {
+        pos,
+        text: text.to_string(),
+    })
+}
+
+fn delete(pos: usize, len: usize) -> TextOp {
+    TextOp::Delete(DeleteText { pos, len })
+}
+
+/// Where the character at `pos` ends up once `del` has been applied.
+fn map_through_delete(pos: usize, del: &DeleteText) -> usize {
+    if pos <= del.pos {
+        pos
+    } else if pos >= del.pos.saturating_add(del.len) {
+        pos - del.len
+    } else {
+        del.pos
+    }
+}
+
+/// Rewrites `del` to apply after `ins`, splitting it if `ins` lands inside the
+/// deleted range so that the inserted text survives.
+fn delete_after_insert(del: &DeleteText, ins: &InsertText) -> Vec<TextOp> {
+    let inserted = ins.text.chars().count();
+    if ins.pos <= del.pos {
+        vec![delete(del.pos.saturating_add(inserted), del.len)]
+    } else if ins.pos >= del.pos.saturating_add(del.len) {
+        vec![TextOp::Delete(del.clone())]
+    } else {
+        let before = ins.pos - del.pos;
+        vec![
+            delete(del.pos, before),
+            delete(del.pos.saturating_add(inserted), del.len - before),
+        ]
+    }
+}
+
+fn delete_after_delete(del: &DeleteText, other: &DeleteText) -> Vec<TextOp> {
+    let start = map_through_delete(del.pos, other);
+    let end = map_through_delete(del.pos.saturating_add(del.len), other);
+    if end > start {
+        vec![delete(start, end - start)]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Transforms two concurrent ops against each other. `a_first` decides which
+/// insert goes first when both insert at the same position.
+fn transform_op(a: &TextOp, b: &TextOp, a_first: bool) -> (Vec<TextOp>, Vec<TextOp>) {
+    match (a, b) {
+        (TextOp::Insert(x), TextOp::Insert(y)) => {
+            if x.pos < y.pos || (x.pos == y.pos && a_first) {
+                let shifted = insert(y.pos.saturating_add(x.text.chars().count()), &y.text);
+                (vec![a.clone()], vec![shifted])
+            } else {
+                let shifted = insert(x.pos.saturating_add(y.text.chars().count()), &x.text);
+                (vec![shifted], vec![b.clone()])
+            }
+        }
+        (TextOp::Insert(x), TextOp::Delete(y)) => (
+            vec![insert(map_through_delete(x.pos, y), &x.text)],
+            delete_after_insert(y, x),
+        ),
+        (TextOp::Delete(x), TextOp::Insert(y)) => (
+            delete_after_insert(x, y),
+            vec![insert(map_through_delete(y.pos, x), &y.text)],
+        ),
+        (TextOp::Delete(x), TextOp::Delete(y)) => {
+            (delete_after_delete(x, y), delete_after_delete(y, x))
+        }
+    }
+}
+
+/// Transforms two concurrent sequences of ops made against the same text, so
+/// that applying `a` and then the returned `b'` gives the same text as
+/// applying `b` and then the returned `a'`.
+pub fn transform(a: &[TextOp], b: &[TextOp], a_first: bool) -> (Vec<TextOp>, Vec<TextOp>) {
+    match (a, b) {
+        ([], _) | (_, []) => (a.to_vec(), b.to_vec()),
+        ([a], [b]) => transform_op(a, b, a_first),
+        ([first, rest @ ..], _) if !rest.is_empty() => {
+            let (first, b) = transform(std::slice::from_ref(first), b, a_first);
+            let (mut rest, b) = transform(rest, &b, a_first);
+            let mut a = first;
+            a.append(&mut rest);
+            (a, b)
+        }
+        (_, [first, rest @ ..]) => {
+            let (a, first) = transform(a, std::slice::from_ref(first), a_first);
+            let (a, mut rest) = transform(&a, rest, a_first);
+            let mut b = first;
+            b.append(&mut rest);
+            (a, b)
+        }
+    }
+}
+
+/// Rebases `ops` made against an older version of a text onto the edits
+/// applied since, in order. Inserts that were applied first win ties.
+pub fn rebase(ops: &[TextOp], since: &[TextEdit]) -> Vec<TextOp> {
+    since.iter().fold(ops.to_vec(), |ops, edit| {
+        transform(&ops, &edit.ops, false).0
+    })
+}
+
+/// Returns the length in characters of a text before `since` was applied to
+/// it, given its length `len` after.
+pub fn len_before(len: usize, since: &[TextEdit]) -> usize {
+    let ops = since.iter().rev().flat_map(|edit| edit.ops.iter().rev());
+    ops.fold(len, |len, op| match op {
+        TextOp::Insert(ins) => len.saturating_sub(ins.text.chars().count()),
+        TextOp::Delete(del) => len.saturating_add(del.len),
+    })
+}
+
+/// Checks that `ops` fit a text of `len` characters, each one applying to the
+/// text as left by the previous ones.
+pub fn check_ops(ops: &[TextOp], mut len: usize) -> Result<(), TextOpError> {
+    for op in ops {
+        match op {
+            TextOp::Insert(ins) if ins.pos <= len => len += ins.text.chars().count(),
+            TextOp::Insert(ins) => return Err(TextOpError::OutOfRange(ins.pos)),
+            TextOp::Delete(del) => match del.pos.checked_add(del.len) {
+                Some(end) if end <= len => len -= del.len,
+                _ => return Err(TextOpError::OutOfRange(del.pos.saturating_add(del.len))),
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Returns the byte offset of the character at `pos`, or the text's length
+/// for the position just past its end.
+fn byte_offset(text: &str, pos: usize) -> Option<usize> {
+    text.char_indices()
+        .map(|(offset, _)| offset)
+        .chain(std::iter::once(text.len()))
+        .nth(pos)
+}
+
+impl TextOp {
+    /// Applies the op to `text`, keeping `marks` on the characters they cover.
+    /// Text inserted at the end of a mark extends it.
+    fn apply(&self, text: &mut String, marks: &mut Vec<Mark>) -> Result<(), TextOpError> {
+        match self {
+            TextOp::Insert(ins) => {
+                let offset = byte_offset(text, ins.pos).ok_or(TextOpError::OutOfRange(ins.pos))?;
+                text.insert_str(offset, &ins.text);
+                let inserted = ins.text.chars().count();
+                for mark in marks.iter_mut() {
+                    if ins.pos <= mark.start {
+                        mark.start += inserted;
+                    }
+                    if ins.pos <= mark.end {
+                        mark.end += inserted;
+                    }
+                }
+            }
+            TextOp::Delete(del) => {
+                let start = byte_offset(text, del.pos).ok_or(TextOpError::OutOfRange(del.pos))?;
+                let end = byte_offset(&text[start..], del.len)
+                    .ok_or_else(|| TextOpError::OutOfRange(del.pos.saturating_add(del.len)))?;
+                text.replace_range(start..start + end, "");
+                for mark in marks.iter_mut() {
+                    mark.start = map_through_delete(mark.start, del);
+                    mark.end = map_through_delete(mark.end, del);
+                }
+                marks.retain(|mark| mark.start < mark.end);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Applies `ops` in order to the text of `payload` and moves its marks along.
+pub fn apply_ops(payload: &mut TextPayload, ops: &[TextOp]) -> Result<(), TextOpError> {
+    for op in ops {
+        op.apply(&mut payload.text, &mut payload.marks)?;
+    }
+    normalize_marks(&mut payload.marks);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::models::rich_text::MarkType;
+
+    fn text(text: &str) -> TextPayload {
+        TextPayload {
+            text: text.to_string(),
+            marks: Vec::new(),
+        }
+    }
+
+    fn applied(mut payload: TextPayload, ops: &[TextOp]) -> TextPayload {
+        apply_ops(&mut payload, ops).unwrap();
+        payload
+    }
+
+    #[test]
+    fn test_apply_ops() {
+        let bold = Mark {
+            start: 2,
+            end: 5,
+            typ: MarkType::Bold,
+            href: None,
+            color: None,
+        };
+        let payload = TextPayload {
+            marks: vec![bold.clone()],
+            ..text("héllo world")
+        };
+        let edited = applied(payload.clone(), &[insert(5, "!!"), delete(0, 2)]);
+        assert_eq!(edited.text, "llo!! world");
+        assert_eq!((edited.marks[0].start, edited.marks[0].end), (0, 5));
+        let edited = applied(payload.clone(), &[delete(1, 6)]);
+        assert_eq!(edited.text, "horld");
+        assert!(edited.marks.is_empty());
+        assert_eq!(
+            apply_ops(&mut payload.clone(), &[delete(10, 2)]),
+            Err(TextOpError::OutOfRange(12))
+        );
+        assert_eq!(
+            apply_ops(&mut payload.clone(), &[insert(12, "a")]),
+            Err(TextOpError::OutOfRange(12))
+        );
+        assert_eq!(
+            apply_ops(&mut payload.clone(), &[delete(1, usize::MAX)]),
+            Err(TextOpError::OutOfRange(usize::MAX))
+        );
+    }
+
+    #[test]
+    fn test_check_ops() {
+        assert_eq!(check_ops(&[insert(5, "ab"), delete(0, 7)], 5), Ok(()));
+        assert_eq!(
+            check_ops(&[insert(5, "ab"), delete(0, 8)], 5),
+            Err(TextOpError::OutOfRange(8))
+        );
+        assert_eq!(
+            check_ops(&[delete(1, usize::MAX)], 5),
+            Err(TextOpError::OutOfRange(usize::MAX))
+        );
+        let edits = [TextEdit {
+            atom_uuid: Uuid::nil(),
+            version: 1,
+            ops: vec![insert(0, "ab"), delete(1, 3)],
+        }];
+        assert_eq!(len_before(4, &edits), 5);
+    }
+
+    #[test]
+    fn test_rebase_keeps_both_edits() {
+        let edits = [TextEdit {
+            atom_uuid: Uuid::nil(),
+            version: 1,
+            ops: vec![insert(0, "Hello ")],
+        }];
+        let ops = rebase(&[delete(0, 1), insert(0, "W")], &edits);
+        let server = applied(text("world"), &edits[0].ops);
+        assert_eq!(applied(server, &ops).text, "Hello World");
+    }
+
+    /// Turns random numbers into an op that fits a text of `len` characters.
+    fn op_for(len: usize, (kind, pos, size): (bool, usize, usize), inserted: &str) -> TextOp {
+        let pos = pos % (len + 1);
+        if kind || pos == len {
+            insert(pos, inserted)
+        } else {
+            delete(pos, 1 + size % (len - pos))
+        }
+    }
+
+    fn ops_for(text: &str, seeds: &[((bool, usize, usize), String)]) -> Vec<TextOp> {
+        let mut payload = self::text(text);
+        let mut ops = Vec::new();
+        for (seed, inserted) in seeds {
+            let op = op_for(payload.text.chars().count(), *seed, inserted);
+            apply_ops(&mut payload, std::slice::from_ref(&op)).unwrap();
+            ops.push(op);
+        }
+        ops
+    }
+
+    fn seeds() -> impl Strategy<Value = Vec<((bool, usize, usize), String)>> {
+        prop::collection::vec((any::<(bool, usize, usize)>(), "[a-cé]{1,3}"), 0..4)
+    }
+
+    /// Edits sent to the server as (client, base version, ops).
+    type Outbox = VecDeque<(usize, i32, Vec<TextOp>)>;
+
+    /// A client following the usual protocol for a central server: one edit
+    /// in flight at a time, further local ops buffered until it's acknowledged
+    /// and both transformed against incoming edits.
+    struct Client {
+        text: TextPayload,
+        version: i32,
+        inflight: Option<Vec<TextOp>>,
+        buffer: Vec<TextOp>,
+        inbox: VecDeque<Option<Vec<TextOp>>>,
+    }
+
+    impl Client {
+        fn edit(&mut self, ops: Vec<TextOp>, outbox: &mut Outbox, id: usize) {
+            apply_ops(&mut self.text, &ops).unwrap();
+            match self.inflight {
+                Some(_) => self.buffer.extend(ops),
+                None => {
+                    outbox.push_back((id, self.version, ops.clone()));
+                    self.inflight = Some(ops);
+                }
+            }
+        }
+
+        fn receive(&mut self, outbox: &mut Outbox, id: usize) {
+            self.version += 1;
+            match self.inbox.pop_front().unwrap() {
+                // Our own edit was applied.
+                None => {
+                    self.inflight = None;
+                    if !self.buffer.is_empty() {
+                        let ops = std::mem::take(&mut self.buffer);
+                        outbox.push_back((id, self.version, ops.clone()));
+                        self.inflight = Some(ops);
+                    }
+                }
+                Some(ops) => {
+                    let ops = match self.inflight.take() {
+                        Some(inflight) => {
+                            let (inflight, ops) = transform(&inflight, &ops, false);
+                            self.inflight = Some(inflight);
+                            ops
+                        }
+                        None => ops,
+                    };
+                    let (buffer, ops) = transform(&self.buffer, &ops, false);
+                    self.buffer = buffer;
+                    apply_ops(&mut self.text, &ops).unwrap();
+                }
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_transform_converges(
+            start in "[a-c]{0,8}",
+            a in seeds(),
+            b in seeds(),
+            a_first in any::<bool>(),
+        ) {
+            let a = ops_for(&start, &a);
+            let b = ops_for(&start, &b);
+            let (a_rebased, b_rebased) = transform(&a, &b, a_first);
+            let left = applied(applied(text(&start), &a), &b_rebased);
+            let right = applied(applied(text(&start), &b), &a_rebased);
+            prop_assert_eq!(left.text, right.text);
+        }
+
+        #[test]
+        fn test_ops_out_of_range(
+            start in "[a-c]{0,8}",
+            ops in prop::collection::vec((any::<(bool, usize, usize)>(), "[a-cé]{1,3}"), 0..4),
+            other in seeds(),
+        ) {
+            // Client supplied positions and lengths may be anything, up to
+            // overflowing when added together.
+            let ops = ops
+                .into_iter()
+                .map(|((kind, pos, len), inserted)| {
+                    let pos = if kind { pos } else { usize::MAX - pos % 4 };
+                    match len % 2 {
+                        0 => insert(pos, &inserted),
+                        _ => delete(pos % 12, len),
+                    }
+                })
+                .collect::<Vec<_>>();
+            let checked = check_ops(&ops, start.chars().count());
+            prop_assert_eq!(checked.is_ok(), apply_ops(&mut text(&start), &ops).is_ok());
+            let other = ops_for(&start, &other);
+            transform(&ops, &other, true);
+            transform(&other, &ops, false);
+        }
+
+        #[test]
+        fn test_clients_converge(
+            start in "[a-c]{0,8}",
+            steps in prop::collection::vec((0..3usize, 0..3usize, any::<(bool, usize, usize)>(), "[a-cé]{1,3}"), 0..40),
+        ) {
+            let mut server = text(&start);
+            let mut history: Vec<TextEdit> = Vec::new();
+            let mut clients = (0..3).map(|_| Client {
+                text: text(&start),
+                version: 0,
+                inflight: None,
+                buffer: Vec::new(),
+                inbox: VecDeque::new(),
+            }).collect::<Vec<_>>();
+            let mut outbox = VecDeque::new();
+
+            let mut serve = |outbox: &mut Outbox, clients: &mut Vec<Client>| {
+                if let Some((id, base, ops)) = outbox.pop_front() {
+                    let ops = rebase(&ops, &history[base as usize..]);
+                    apply_ops(&mut server, &ops).unwrap();
+                    history.push(TextEdit {
+                        atom_uuid: Uuid::nil(),
+                        version: history.len() as i32 + 1,
+                        ops: ops.clone(),
+                    });
+                    for (other, client) in clients.iter_mut().enumerate() {
+                        client.inbox.push_back((other != id).then(|| ops.clone()));
+                    }
+                }
+            };
+
+            for (action, id, seed, inserted) in steps {
+                match action {
+                    0 => {
+                        let client = &mut clients[id];
+                        let op = op_for(client.text.text.chars().count(), seed, &inserted);
+                        client.edit(vec![op], &mut outbox, id);
+                    }
+                    1 => serve(&mut outbox, &mut clients),
+                    _ => {
+                        if !clients[id].inbox.is_empty() {
+                            clients[id].receive(&mut outbox, id);
+                        }
+                    }
+                }
+            }
+            // Deliver everything that's still in flight.
+            loop {
+                if !outbox.is_empty() {
+                    serve(&mut outbox, &mut clients);
+                } else if let Some(id) = clients.iter().position(|client| !client.inbox.is_empty()) {
+                    clients[id].receive(&mut outbox, id);
+                } else {
+                    break;
+                }
+            }
+            for client in &clients {
+                prop_assert_eq!(&client.text.text, &server.text);
+            }
+        }
+    }
+}
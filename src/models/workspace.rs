@@ -1,6 +1,7 @@
 use crate::schema::workspaces;
 use ::uuid::Uuid;
 use async_graphql::SimpleObject;
+use chrono::{NaiveDateTime, Utc};
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -18,6 +19,13 @@ pub struct Workspace {
 
     /// The workspace's image or icon.
     pub image: String,
+    /// Incremented by every update. Updates must pass the version they're
+    /// based on, see `ConflictError`.
+    pub version: i32,
+    /// When the workspace was last changed.
+    pub updated_at: NaiveDateTime,
+    /// When the workspace was moved to the trash.
+    pub deleted_at: Option<NaiveDateTime>,
 }
 
 impl Workspace {
@@ -26,6 +34,9 @@ impl Workspace {
             uuid: Uuid::new_v4(),
             name: name.to_string(),
             image: image.to_string(),
+            version: 0,
+            updated_at: Utc::now().naive_utc(),
+            deleted_at: None,
         }
     }
 }
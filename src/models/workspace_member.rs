@@ -0,0 +1,111 @@
+use crate::schema::workspace_members;
+use ::uuid::Uuid;
+use async_graphql::{Enum, SimpleObject};
+use diesel::{
+    backend::{self, Backend},
+    deserialize::{self, FromSql},
+    prelude::*,
+    serialize::{self, Output, ToSql},
+    sql_types::Text,
+    AsExpression, FromSqlRow,
+};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use strum::{EnumString, IntoStaticStr};
+
+use super::{user::User, workspace::Workspace};
+
+/// The role of a user within a workspace.
+///
+/// Roles are ordered by privilege, so `Owner > Editor > Viewer`.
+#[derive(
+    Enum,
+    Copy,
+    Clone,
+    Debug,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    EnumString,
+    IntoStaticStr,
+    AsExpression,
+    FromSqlRow,
+    Serialize,
+    Deserialize,
+)]
+#[diesel(sql_type = Text)]
+pub enum WorkspaceRole {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl<DB> ToSql<Text, DB> for WorkspaceRole
+where
+    DB: Backend,
+    str: ToSql<Text, DB>,
+{
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, DB>) -> serialize::Result {
+        let s: &'b str = self.into();
+        s.to_sql(out)
+    }
+}
+
+impl<DB> FromSql<Text, DB> for WorkspaceRole
+where
+    DB: Backend,
+    String: FromSql<Text, DB>,
+{
+    fn from_sql(bytes: backend::RawValue<DB>) -> deserialize::Result<Self> {
+        let data = String::from_sql(bytes)?;
+        WorkspaceRole::from_str(&data)
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
+    }
+}
+
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    Clone,
+    SimpleObject,
+    Queryable,
+    Insertable,
+    AsChangeset,
+    Associations,
+)]
+#[diesel(table_name = workspace_members)]
+#[diesel(belongs_to(User, foreign_key = user_uuid))]
+#[diesel(belongs_to(Workspace, foreign_key = workspace_uuid))]
+#[graphql(complex)]
+pub struct WorkspaceMember {
+    /// The member's user.
+    pub user_uuid: Uuid,
+
+    /// The workspace the user is a member of.
+    pub workspace_uuid: Uuid,
+
+    /// The member's role within the workspace.
+    pub role: WorkspaceRole,
+}
+
+impl WorkspaceMember {
+    pub fn new(user_uuid: Uuid, workspace_uuid: Uuid, role: WorkspaceRole) -> Self {
+        Self {
+            user_uuid,
+            workspace_uuid,
+            role,
+        }
+    }
+}
+
+/// The outcome of `WorkspaceRepo::update_member` and `remove_member`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum MemberChange {
+    Changed,
+    /// The user isn't a member of the workspace.
+    NotFound,
+    /// The change would leave the workspace without an owner.
+    LastOwner,
+}
@@ -0,0 +1,257 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Integer, Uuid as SqlUuid};
+use uuid::Uuid;
+
+use super::{database::Database, traits::AtomRepo};
+use crate::models::{text_ops::TextEdit, Atom};
+
+/// Index an atom is parked at while the others are shifted around it.
+const PARKED_IDX: i32 = i32::MIN;
+
+/// How many edits are kept per text atom for rebasing concurrent edits.
+pub(super) const TEXT_EDIT_HISTORY: i32 = 200;
+
+pub struct PostgresqlAtomRepo {
+    db: Database,
+}
+
+impl PostgresqlAtomRepo {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+/// Shifts the indices of the atoms of a slot within `from..=to` by `delta`.
+///
+/// The index is part of the primary key, so the rows are first flipped to
+/// negative indices and then back, which avoids transient key conflicts.
+fn shift_atoms(
+    conn: &mut PgConnection,
+    slot_uuid: &Uuid,
+    from: i32,
+    to: i32,
+    delta: i32,
+) -> QueryResult<()> {
+    sql_query(
+        "UPDATE atoms SET idx = -(idx + $4) - 1 \
+         WHERE slot_uuid = $1 AND idx BETWEEN $2 AND $3",
+    )
+    .bind::<SqlUuid, _>(slot_uuid)
+    .bind::<Integer, _>(from)
+    .bind::<Integer, _>(to)
+    .bind::<Integer, _>(delta)
+    .execute(conn)?;
+    sql_query("UPDATE atoms SET idx = -idx - 1 WHERE slot_uuid = $1 AND idx < 0 AND idx > $2")
+        .bind::<SqlUuid, _>(slot_uuid)
+        .bind::<Integer, _>(PARKED_IDX)
+        .execute(conn)?;
+    Ok(())
+}
+
+fn set_atom_idx(
+    conn: &mut PgConnection,
+    slot_uuid_val: &Uuid,
+    from: i32,
+    to: i32,
+) -> QueryResult<()> {
+    use crate::schema::atoms::dsl::*;
+
+    diesel::update(atoms)
+        .filter(slot_uuid.eq(slot_uuid_val))
+        .filter(idx.eq(from))
+        .set(idx.eq(to))
+        .execute(conn)?;
+    Ok(())
+}
+
+#[async_trait]
+impl AtomRepo for PostgresqlAtomRepo {
+    async fn get_atom(&self, slot_uuid_val: &Uuid, idx_val: i32) -> Result<Option<Atom>> {
+        use crate::schema::atoms::dsl::*;
+
+        let slot_uuid_val = *slot_uuid_val;
+        self.db
+            .run(move |conn| {
+                let result = atoms
+                    .filter(slot_uuid.eq(slot_uuid_val))
+                    .filter(idx.eq(idx_val))
+                    .first::<Atom>(conn)
+                    .optional()?;
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn get_atom_by_uuid(&self, uuid_val: &Uuid) -> Result<Option<Atom>> {
+        use crate::schema::atoms::dsl::*;
+
+        let uuid_val = *uuid_val;
+        self.db
+            .run(move |conn| {
+                let result = atoms
+                    .filter(uuid.eq(uuid_val))
+                    .first::<Atom>(conn)
+                    .optional()?;
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn get_slot_atoms(&self, slot_uuid_val: &Uuid) -> Result<Vec<Atom>> {
+        use crate::schema::atoms::dsl::*;
+
+        let slot_uuid_val = *slot_uuid_val;
+        self.db
+            .run(move |conn| {
+                let result = atoms
+                    .filter(slot_uuid.eq(slot_uuid_val))
+                    .order(idx.asc())
+                    .load::<Atom>(conn)?;
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn insert_atom(&self, atom: &Atom) -> Result<()> {
+        use crate::schema::atoms::dsl::*;
+
+        let atom = atom.clone();
+        self.db
+            .run(move |conn| {
+                conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    shift_atoms(conn, &atom.slot_uuid, atom.idx, i32::MAX, 1)?;
+                    diesel::insert_into(atoms).values(atom).execute(conn)?;
+                    Ok(())
+                })?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn update_atom(&self, atom: &Atom) -> Result<Option<Atom>> {
+        use crate::schema::atoms::dsl::*;
+
+        let atom = atom.clone();
+        self.db
+            .run(move |conn| {
+                let result = diesel::update(atoms)
+                    .filter(uuid.eq(&atom.uuid))
+                    .filter(version.eq(atom.version))
+                    .set((
+                        typ.eq(&atom.typ),
+                        data.eq(&atom.data),
+                        version.eq(version + 1),
+                    ))
+                    .get_result::<Atom>(conn)
+                    .optional()?;
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn get_text_edits(&self, atom_uuid_val: &Uuid, since: i32) -> Result<Vec<TextEdit>> {
+        use crate::schema::text_edits::dsl::*;
+
+        let atom_uuid_val = *atom_uuid_val;
+        self.db
+            .run(move |conn| {
+                let rows = text_edits
+                    .select((version, operations))
+                    .filter(atom_uuid.eq(atom_uuid_val))
+                    .filter(version.gt(since))
+                    .order(version.asc())
+                    .load::<(i32, String)>(conn)?;
+                rows.into_iter()
+                    .map(|(version_val, ops_val)| {
+                        Ok(TextEdit {
+                            atom_uuid: atom_uuid_val,
+                            version: version_val,
+                            ops: serde_json::from_str(&ops_val)?,
+                        })
+                    })
+                    .collect()
+            })
+            .await
+    }
+
+    async fn apply_text_edit(&self, atom: &Atom, edit: &TextEdit) -> Result<bool> {
+        use crate::schema::{atoms, text_edits};
+
+        let ops = serde_json::to_string(&edit.ops)?;
+        let atom = atom.clone();
+        let edit = edit.clone();
+        self.db
+            .run(move |conn| {
+                let applied = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    let updated = diesel::update(atoms::table)
+                        .filter(atoms::uuid.eq(&atom.uuid))
+                        .filter(atoms::version.eq(edit.version - 1))
+                        .set((atoms::data.eq(&atom.data), atoms::version.eq(edit.version)))
+                        .execute(conn)?;
+                    if updated == 0 {
+                        return Ok(false);
+                    }
+                    diesel::insert_into(text_edits::table)
+                        .values((
+                            text_edits::atom_uuid.eq(&atom.uuid),
+                            text_edits::version.eq(edit.version),
+                            text_edits::operations.eq(&ops),
+                        ))
+                        .execute(conn)?;
+                    diesel::delete(
+                        text_edits::table
+                            .filter(text_edits::atom_uuid.eq(&atom.uuid))
+                            .filter(text_edits::version.le(edit.version - TEXT_EDIT_HISTORY)),
+                    )
+                    .execute(conn)?;
+                    Ok(true)
+                })?;
+                Ok(applied)
+            })
+            .await
+    }
+
+    async fn move_atom(&self, slot_uuid: &Uuid, from: i32, to: i32) -> Result<()> {
+        if from == to {
+            return Ok(());
+        }
+        let slot_uuid = *slot_uuid;
+        self.db
+            .run(move |conn| {
+                conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    set_atom_idx(conn, &slot_uuid, from, PARKED_IDX)?;
+                    if from < to {
+                        shift_atoms(conn, &slot_uuid, from + 1, to, -1)?;
+                    } else {
+                        shift_atoms(conn, &slot_uuid, to, from - 1, 1)?;
+                    }
+                    set_atom_idx(conn, &slot_uuid, PARKED_IDX, to)
+                })?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn delete_atom(&self, slot_uuid_val: &Uuid, idx_val: i32) -> Result<()> {
+        use crate::schema::atoms::dsl::*;
+
+        let slot_uuid_val = *slot_uuid_val;
+        self.db
+            .run(move |conn| {
+                conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    diesel::delete(
+                        atoms
+                            .filter(slot_uuid.eq(slot_uuid_val))
+                            .filter(idx.eq(idx_val)),
+                    )
+                    .execute(conn)?;
+                    shift_atoms(conn, &slot_uuid_val, idx_val + 1, i32::MAX, -1)
+                })?;
+                Ok(())
+            })
+            .await
+    }
+}
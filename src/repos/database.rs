@@ -0,0 +1,195 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use diesel::{
+    connection::{AnsiTransactionManager, TransactionManager},
+    r2d2::{ConnectionManager, Pool, PooledConnection},
+    PgConnection,
+};
+use log::warn;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// A connection pool running Diesel's blocking queries on tokio's blocking
+/// threads rather than on the executor.
+///
+/// At most as many queries as the pool has connections run at once. The
+/// others wait for a permit without holding a thread, so a saturated pool
+/// delays only the requests that use it.
+#[derive(Clone)]
+pub struct Database {
+    pool: Pool<ConnectionManager<PgConnection>>,
+    permits: Arc<Semaphore>,
+    transaction: Option<Arc<Transaction>>,
+}
+
+/// The connection of a transaction, and its permit. `None` once the
+/// transaction is committed or rolled back.
+struct Transaction(
+    Mutex<
+        Option<(
+            PooledConnection<ConnectionManager<PgConnection>>,
+            OwnedSemaphorePermit,
+        )>,
+    >,
+);
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        // Dropped without committing, e.g. because an error was returned
+        // early or the request was cancelled.
+        if let Some((mut conn, permit)) = self.0.get_mut().unwrap().take() {
+            let rollback = move || {
+                let _permit = permit;
+                if let Err(err) = AnsiTransactionManager::rollback_transaction(&mut *conn) {
+                    warn!("Failed to roll back transaction: {}", err);
+                }
+            };
+            match tokio::runtime::Handle::try_current() {
+                Ok(handle) => drop(handle.spawn_blocking(rollback)),
+                Err(_) => rollback(),
+            }
+        }
+    }
+}
+
+impl Database {
+    pub fn new(pool: Pool<ConnectionManager<PgConnection>>) -> Self {
+        let permits = Arc::new(Semaphore::new(pool.max_size() as usize));
+        Self {
+            pool,
+            permits,
+            transaction: None,
+        }
+    }
+
+    /// Runs `f` with a connection from the pool, or with the connection of
+    /// the transaction if this database was returned by `begin`.
+    pub async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut PgConnection) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        if let Some(transaction) = &self.transaction {
+            let transaction = Arc::clone(transaction);
+            return tokio::task::spawn_blocking(move || {
+                let mut held = transaction.0.lock().unwrap();
+                let (conn, _) = held
+                    .as_mut()
+                    .ok_or_else(|| anyhow!("Transaction already finished"))?;
+                f(conn)
+            })
+            .await?;
+        }
+
+        // The permit moves into the task, so it's held until the query
+        // finishes even if the caller stops waiting.
+        let permit = Arc::clone(&self.permits).acquire_owned().await?;
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            let mut conn = pool.get()?;
+            f(&mut conn)
+        })
+        .await?
+    }
+
+    /// Starts a transaction and returns a database running its queries in
+    /// it, so repositories created with it share the transaction. Their own
+    /// transactions become savepoints.
+    ///
+    /// The transaction holds on to its connection until `commit` or
+    /// `rollback` is called, and is rolled back if all its databases are
+    /// dropped before that.
+    pub async fn begin(&self) -> Result<Database> {
+        let permit = Arc::clone(&self.permits).acquire_owned().await?;
+        let pool = self.pool.clone();
+        let conn = tokio::task::spawn_blocking(move || -> Result<_> {
+            let mut conn = pool.get()?;
+            AnsiTransactionManager::begin_transaction(&mut *conn)?;
+            Ok(conn)
+        })
+        .await??;
+        Ok(Database {
+            pool: self.pool.clone(),
+            permits: Arc::clone(&self.permits),
+            transaction: Some(Arc::new(Transaction(Mutex::new(Some((conn, permit)))))),
+        })
+    }
+
+    /// Commits the transaction started by `begin`.
+    pub async fn commit(&self) -> Result<()> {
+        self.finish(AnsiTransactionManager::commit_transaction)
+            .await
+    }
+
+    /// Rolls back the transaction started by `begin`.
+    pub async fn rollback(&self) -> Result<()> {
+        self.finish(AnsiTransactionManager::rollback_transaction)
+            .await
+    }
+
+    async fn finish(&self, end: fn(&mut PgConnection) -> diesel::QueryResult<()>) -> Result<()> {
+        let transaction = Arc::clone(
+            self.transaction
+                .as_ref()
+                .ok_or_else(|| anyhow!("Not a transaction"))?,
+        );
+        tokio::task::spawn_blocking(move || {
+            let (mut conn, _permit) = transaction
+                .0
+                .lock()
+                .unwrap()
+                .take()
+                .ok_or_else(|| anyhow!("Transaction already finished"))?;
+            Ok(end(&mut conn)?)
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use diesel::{sql_query, RunQueryDsl};
+
+    use super::*;
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database, see TEST_DATABASE_URL"]
+    async fn test_saturated_pool() {
+        let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set");
+        let pool = Pool::builder()
+            .max_size(2)
+            .build(ConnectionManager::new(url))
+            .unwrap();
+        let db = Database::new(pool);
+
+        // Ten times as many slow queries as connections, on the single thread
+        // of the test's runtime like on an actix worker.
+        let queries = (0..20)
+            .map(|_| {
+                let db = db.clone();
+                tokio::spawn(async move {
+                    db.run(|conn| Ok(sql_query("SELECT pg_sleep(0.1)").execute(conn)?))
+                        .await
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Work that doesn't touch the database keeps its latency meanwhile.
+        let start = Instant::now();
+        let mut worst = Duration::ZERO;
+        while start.elapsed() < Duration::from_millis(500) {
+            let tick = Instant::now();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            worst = worst.max(tick.elapsed() - Duration::from_millis(10));
+        }
+        assert!(worst < Duration::from_millis(50), "worst delay {:?}", worst);
+
+        for query in queries {
+            query.await.unwrap().unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+}
@@ -1,19 +1,44 @@
-use std::str::FromStr;
+use std::{
+    io::ErrorKind,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
 
-use anyhow::Result;
+use actix_files::Files;
+use actix_web::{
+    http::header::{self, DispositionType},
+    middleware::DefaultHeaders,
+    web,
+};
+use anyhow::{bail, Result};
 use async_trait::async_trait;
 use log::info;
 use s3::{creds::Credentials, Bucket, Region};
+use strum::{Display, EnumString};
 
 use super::traits::ImagesRepo;
 
+/// The route the files of `LocalFsImagesRepo` are served from.
+pub const LOCAL_IMAGES_ROUTE: &str = "/files";
+
+/// Selects where images are kept in `Config`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, EnumString, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum ImagesStorageKind {
+    /// Keeps images in an S3 bucket.
+    S3,
+    /// Keeps images in a local directory and serves them from
+    /// `LOCAL_IMAGES_ROUTE`.
+    Local,
+}
+
 pub struct S3ImagesRepo {
     base_path: String,
     bucket: Bucket,
 }
 
 impl S3ImagesRepo {
-    pub fn new(bucket_name: &String, endpoint: &String) -> Result<Self> {
+    pub fn new(bucket_name: &str, endpoint: &str) -> Result<Self> {
         let mut region = Region::from_str(endpoint)?;
         if let Region::Custom { ref endpoint, .. } = region {
             region = Region::Custom {
@@ -43,3 +68,134 @@ impl ImagesRepo for S3ImagesRepo {
         Ok(())
     }
 }
+
+/// Keeps images in a local directory.
+pub struct LocalFsImagesRepo {
+    dir: PathBuf,
+    base_url: String,
+}
+
+impl LocalFsImagesRepo {
+    /// Images are written under `dir`, and their urls start with `base_url`.
+    pub fn new(dir: &str, base_url: &str) -> Self {
+        Self {
+            dir: PathBuf::from(dir),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Joins `path` to the directory. Only plain names are allowed as parts of
+    /// `path`, so it can't lead outside of the directory.
+    fn file_path(&self, path: &str) -> Result<PathBuf> {
+        let mut file_path = self.dir.clone();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(name) => file_path.push(name),
+                _ => bail!("Invalid image path: {}", path),
+            }
+        }
+        if file_path == self.dir {
+            bail!("Invalid image path: {}", path);
+        }
+        Ok(file_path)
+    }
+}
+
+#[async_trait]
+impl ImagesRepo for LocalFsImagesRepo {
+    async fn upload_image(&self, path: &str, image: &[u8]) -> Result<String> {
+        let file_path = self.file_path(path)?;
+        if let Some(parent) = file_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&file_path, image).await?;
+        Ok(format!("{}/{}", self.base_url, path))
+    }
+
+    async fn delete_image(&self, path: &str) -> Result<()> {
+        let path = path
+            .strip_prefix(&format!("{}/", self.base_url))
+            .unwrap_or(path);
+        info!("Deleting image: {}", path);
+        match tokio::fs::remove_file(self.file_path(path)?).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Serves the files of `dir` from `LOCAL_IMAGES_ROUTE`. actix-files falls back
+/// to the working directory when `dir` can't be resolved, so every request is
+/// answered with 404 in that case. Files are served as attachments that
+/// browsers mustn't sniff, so they can't run scripts on the app's origin.
+pub fn serve_local_images(cfg: &mut web::ServiceConfig, dir: &str) {
+    let found = Path::new(dir).canonicalize().is_ok();
+    let files = Files::new("", dir)
+        .mime_override(|_| DispositionType::Attachment)
+        .path_filter(move |_, _| found);
+    cfg.service(
+        web::scope(LOCAL_IMAGES_ROUTE)
+            .wrap(DefaultHeaders::new().add((header::X_CONTENT_TYPE_OPTIONS, "nosniff")))
+            .service(files),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_local_fs_images_repo() {
+        let dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let repo = LocalFsImagesRepo::new(dir.to_str().unwrap(), "http://localhost/files/");
+
+        let url = repo.upload_image("images/a/b.png", b"png").await.unwrap();
+        assert_eq!(url, "http://localhost/files/images/a/b.png");
+        let file_path = dir.join("images/a/b.png");
+        assert_eq!(std::fs::read(&file_path).unwrap(), b"png");
+        repo.delete_image(&url).await.unwrap();
+        assert!(!file_path.exists());
+        repo.delete_image(&url).await.unwrap();
+
+        for path in [
+            "",
+            "../b.png",
+            "images/../../b.png",
+            "/tmp/b.png",
+            "./b.png",
+        ] {
+            assert!(repo.upload_image(path, b"png").await.is_err(), "{}", path);
+            assert!(repo.delete_image(path).await.is_err(), "{}", path);
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_serve_local_images() {
+        use actix_web::{http::StatusCode, test, App};
+
+        let dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let dir_str = dir.to_str().unwrap().to_string();
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+        let app =
+            test::init_service(App::new().configure(|cfg| serve_local_images(cfg, &dir_str))).await;
+        let res = test::call_service(&app, get("/files/Cargo.toml")).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        std::fs::create_dir_all(dir.join("images")).unwrap();
+        std::fs::write(dir.join("images/a.png"), b"png").unwrap();
+        let app =
+            test::init_service(App::new().configure(|cfg| serve_local_images(cfg, &dir_str))).await;
+        let res = test::call_service(&app, get("/files/images/a.png")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let value = |name| res.headers().get(name).unwrap().to_str().unwrap();
+        assert_eq!(value(header::X_CONTENT_TYPE_OPTIONS), "nosniff");
+        assert!(value(header::CONTENT_DISPOSITION).starts_with("attachment"));
+        let res = test::call_service(&app, get("/files/Cargo.toml")).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
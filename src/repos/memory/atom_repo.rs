@@ -0,0 +1,173 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::database::{MemoryDatabase, Tables};
+use crate::{
+    models::{text_ops::TextEdit, Atom},
+    repos::{atom_repo::TEXT_EDIT_HISTORY, traits::AtomRepo},
+};
+
+pub struct MemoryAtomRepo {
+    db: MemoryDatabase,
+}
+
+impl MemoryAtomRepo {
+    pub fn new(db: MemoryDatabase) -> Self {
+        Self { db }
+    }
+}
+
+fn find_atom<'a>(tables: &'a Tables, slot_uuid: &Uuid, idx: i32) -> Option<&'a Atom> {
+    tables
+        .atoms
+        .values()
+        .find(|atom| atom.slot_uuid == *slot_uuid && atom.idx == idx)
+}
+
+/// Shifts the indices of the atoms of a slot within `from..=to` by `delta`.
+fn shift_atoms(tables: &mut Tables, slot_uuid: &Uuid, from: i32, to: i32, delta: i32) {
+    for atom in tables.atoms.values_mut() {
+        if atom.slot_uuid == *slot_uuid && (from..=to).contains(&atom.idx) {
+            atom.idx += delta;
+        }
+    }
+}
+
+#[async_trait]
+impl AtomRepo for MemoryAtomRepo {
+    async fn get_atom(&self, slot_uuid: &Uuid, idx: i32) -> Result<Option<Atom>> {
+        self.db
+            .run(|tables| Ok(find_atom(tables, slot_uuid, idx).cloned()))
+            .await
+    }
+
+    async fn get_atom_by_uuid(&self, uuid: &Uuid) -> Result<Option<Atom>> {
+        self.db
+            .run(|tables| Ok(tables.atoms.get(uuid).cloned()))
+            .await
+    }
+
+    async fn get_slot_atoms(&self, slot_uuid: &Uuid) -> Result<Vec<Atom>> {
+        self.db
+            .run(|tables| {
+                let mut result = tables
+                    .atoms
+                    .values()
+                    .filter(|atom| atom.slot_uuid == *slot_uuid)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                result.sort_by_key(|atom| atom.idx);
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn insert_atom(&self, atom: &Atom) -> Result<()> {
+        self.db
+            .run(|tables| {
+                if tables.atoms.contains_key(&atom.uuid) {
+                    bail!("An atom with the same uuid already exists");
+                }
+                shift_atoms(tables, &atom.slot_uuid, atom.idx, i32::MAX, 1);
+                tables.atoms.insert(atom.uuid, atom.clone());
+                Ok(())
+            })
+            .await
+    }
+
+    async fn update_atom(&self, atom: &Atom) -> Result<Option<Atom>> {
+        self.db
+            .run(|tables| {
+                let result = tables
+                    .atoms
+                    .get_mut(&atom.uuid)
+                    .filter(|stored| stored.version == atom.version)
+                    .map(|stored| {
+                        stored.typ = atom.typ;
+                        stored.data = atom.data.clone();
+                        stored.version += 1;
+                        stored.updated_at = Utc::now().naive_utc();
+                        stored.clone()
+                    });
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn get_text_edits(&self, atom_uuid: &Uuid, version: i32) -> Result<Vec<TextEdit>> {
+        self.db
+            .run(|tables| {
+                let mut result = tables
+                    .text_edits
+                    .iter()
+                    .filter(|edit| edit.atom_uuid == *atom_uuid && edit.version > version)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                result.sort_by_key(|edit| edit.version);
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn apply_text_edit(&self, atom: &Atom, edit: &TextEdit) -> Result<bool> {
+        self.db
+            .run(|tables| {
+                let stored = match tables
+                    .atoms
+                    .get_mut(&atom.uuid)
+                    .filter(|stored| stored.version == edit.version - 1)
+                {
+                    Some(stored) => stored,
+                    None => return Ok(false),
+                };
+                stored.data = atom.data.clone();
+                stored.version = edit.version;
+                stored.updated_at = Utc::now().naive_utc();
+                tables.text_edits.push(TextEdit {
+                    atom_uuid: atom.uuid,
+                    ..edit.clone()
+                });
+                tables.text_edits.retain(|kept| {
+                    kept.atom_uuid != atom.uuid || kept.version > edit.version - TEXT_EDIT_HISTORY
+                });
+                Ok(true)
+            })
+            .await
+    }
+
+    async fn move_atom(&self, slot_uuid: &Uuid, from: i32, to: i32) -> Result<()> {
+        if from == to {
+            return Ok(());
+        }
+        self.db
+            .run(|tables| {
+                let moved = find_atom(tables, slot_uuid, from).map(|atom| atom.uuid);
+                if from < to {
+                    shift_atoms(tables, slot_uuid, from + 1, to, -1);
+                } else {
+                    shift_atoms(tables, slot_uuid, to, from - 1, 1);
+                }
+                if let Some(atom) = moved.and_then(|uuid| tables.atoms.get_mut(&uuid)) {
+                    atom.idx = to;
+                }
+                Ok(())
+            })
+            .await
+    }
+
+    async fn delete_atom(&self, slot_uuid: &Uuid, idx: i32) -> Result<()> {
+        self.db
+            .run(|tables| {
+                if let Some(uuid) = find_atom(tables, slot_uuid, idx).map(|atom| atom.uuid) {
+                    tables.delete_atoms(&HashSet::from([uuid]));
+                }
+                shift_atoms(tables, slot_uuid, idx + 1, i32::MAX, -1);
+                Ok(())
+            })
+            .await
+    }
+}
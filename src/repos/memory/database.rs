@@ -0,0 +1,198 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+use uuid::Uuid;
+
+use crate::models::{
+    text_ops::TextEdit, Atom, Page, PageRevision, RefreshToken, Slot, User, Workspace,
+    WorkspaceMember,
+};
+
+/// The rows of the tables in the Postgres schema.
+#[derive(Clone, Default)]
+pub struct Tables {
+    pub users: HashMap<Uuid, User>,
+    pub refresh_tokens: HashMap<Uuid, RefreshToken>,
+    pub workspaces: HashMap<Uuid, Workspace>,
+    pub workspace_members: Vec<WorkspaceMember>,
+    pub pages: HashMap<Uuid, Page>,
+    pub slots: HashMap<Uuid, Slot>,
+    pub atoms: HashMap<Uuid, Atom>,
+    pub text_edits: Vec<TextEdit>,
+    pub page_revisions: Vec<PageRevision>,
+}
+
+impl Tables {
+    /// Deletes workspaces together with their members and pages, like the
+    /// foreign keys cascading in Postgres.
+    pub fn delete_workspaces(&mut self, uuids: &HashSet<Uuid>) {
+        let pages = self
+            .pages
+            .values()
+            .filter(|page| uuids.contains(&page.workspace_uuid))
+            .map(|page| page.uuid)
+            .collect();
+        self.delete_pages(&pages);
+        self.workspace_members
+            .retain(|member| !uuids.contains(&member.workspace_uuid));
+        self.workspaces.retain(|uuid, _| !uuids.contains(uuid));
+    }
+
+    /// Deletes pages together with their slots and revisions.
+    pub fn delete_pages(&mut self, uuids: &HashSet<Uuid>) {
+        let slots = self
+            .slots
+            .values()
+            .filter(|slot| uuids.contains(&slot.page_uuid))
+            .map(|slot| slot.uuid)
+            .collect();
+        self.delete_slots(&slots);
+        self.page_revisions
+            .retain(|revision| !uuids.contains(&revision.page_uuid));
+        self.pages.retain(|uuid, _| !uuids.contains(uuid));
+    }
+
+    /// Deletes slots together with their atoms.
+    pub fn delete_slots(&mut self, uuids: &HashSet<Uuid>) {
+        let atoms = self
+            .atoms
+            .values()
+            .filter(|atom| uuids.contains(&atom.slot_uuid))
+            .map(|atom| atom.uuid)
+            .collect();
+        self.delete_atoms(&atoms);
+        self.slots.retain(|uuid, _| !uuids.contains(uuid));
+    }
+
+    /// Deletes atoms together with their text edits.
+    pub fn delete_atoms(&mut self, uuids: &HashSet<Uuid>) {
+        self.text_edits
+            .retain(|edit| !uuids.contains(&edit.atom_uuid));
+        self.atoms.retain(|uuid, _| !uuids.contains(uuid));
+    }
+}
+
+/// Tables kept in memory and shared by the repositories created with clones
+/// of this database.
+///
+/// Every call holds all tables, so calls don't interleave. The repositories
+/// check their inputs before changing anything, so a failed call leaves the
+/// tables untouched.
+#[derive(Clone, Default)]
+pub struct MemoryDatabase {
+    tables: Arc<AsyncMutex<Tables>>,
+    transaction: Option<Arc<Transaction>>,
+}
+
+/// The tables held by a transaction, and a copy of them from when it began.
+/// `None` once the transaction is committed or rolled back.
+struct Transaction(Mutex<Option<(OwnedMutexGuard<Tables>, Tables)>>);
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        // Dropped without committing, so the changes are undone.
+        if let Some((mut tables, copy)) = self.0.get_mut().unwrap().take() {
+            *tables = copy;
+        }
+    }
+}
+
+impl MemoryDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `f` with the tables, or with the tables of the transaction if
+    /// this database was returned by `begin`.
+    pub async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Tables) -> Result<T>,
+    {
+        match &self.transaction {
+            Some(transaction) => {
+                let mut held = transaction.0.lock().unwrap();
+                let (tables, _) = held
+                    .as_mut()
+                    .ok_or_else(|| anyhow!("Transaction already finished"))?;
+                f(tables)
+            }
+            None => f(&mut *self.tables.lock().await),
+        }
+    }
+
+    /// Starts a transaction and returns a database running its calls in it.
+    ///
+    /// The transaction holds the tables until `commit` or `rollback` is
+    /// called, so other calls wait for it, and is rolled back if all its
+    /// databases are dropped before that.
+    pub async fn begin(&self) -> Result<MemoryDatabase> {
+        let tables = Arc::clone(&self.tables).lock_owned().await;
+        let copy = tables.clone();
+        Ok(MemoryDatabase {
+            tables: Arc::clone(&self.tables),
+            transaction: Some(Arc::new(Transaction(Mutex::new(Some((tables, copy)))))),
+        })
+    }
+
+    /// Commits the transaction started by `begin`.
+    pub async fn commit(&self) -> Result<()> {
+        self.finish(|_, _| {})
+    }
+
+    /// Rolls back the transaction started by `begin`.
+    pub async fn rollback(&self) -> Result<()> {
+        self.finish(|tables, copy| *tables = copy)
+    }
+
+    fn finish(&self, end: fn(&mut Tables, Tables)) -> Result<()> {
+        let transaction = self
+            .transaction
+            .as_ref()
+            .ok_or_else(|| anyhow!("Not a transaction"))?;
+        let (mut tables, copy) = transaction
+            .0
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| anyhow!("Transaction already finished"))?;
+        end(&mut tables, copy);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_transaction() {
+        let db = MemoryDatabase::new();
+        let workspace = Workspace::new("Workspace", "memory://workspace.png");
+        let insert = |tables: &mut Tables| {
+            tables.workspaces.insert(workspace.uuid, workspace.clone());
+            Ok(())
+        };
+        let count = || db.run(|tables| Ok(tables.workspaces.len()));
+
+        let transaction = db.begin().await.unwrap();
+        transaction.run(insert).await.unwrap();
+        transaction.rollback().await.unwrap();
+        assert_eq!(count().await.unwrap(), 0);
+
+        // Dropping a transaction rolls it back.
+        let transaction = db.begin().await.unwrap();
+        transaction.run(insert).await.unwrap();
+        drop(transaction);
+        assert_eq!(count().await.unwrap(), 0);
+
+        let transaction = db.begin().await.unwrap();
+        transaction.run(insert).await.unwrap();
+        transaction.commit().await.unwrap();
+        assert_eq!(count().await.unwrap(), 1);
+        assert!(transaction.run(insert).await.is_err());
+    }
+}
@@ -0,0 +1,39 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::repos::traits::ImagesRepo;
+
+/// Prefix of the urls of images kept in memory. They can't be fetched from
+/// outside the app.
+const BASE_URL: &str = "memory://";
+
+/// Keeps uploaded images in memory.
+#[derive(Default)]
+pub struct MemoryImagesRepo {
+    images: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryImagesRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ImagesRepo for MemoryImagesRepo {
+    async fn upload_image(&self, path: &str, image: &[u8]) -> Result<String> {
+        self.images
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), image.to_vec());
+        Ok(format!("{}{}", BASE_URL, path))
+    }
+
+    async fn delete_image(&self, path: &str) -> Result<()> {
+        let path = path.strip_prefix(BASE_URL).unwrap_or(path);
+        self.images.lock().unwrap().remove(path);
+        Ok(())
+    }
+}
@@ -0,0 +1,14 @@
+//! Repositories keeping their data in memory, for tests and for running the
+//! app without Postgres or S3. The data is lost when the app stops.
+
+pub mod atom_repo;
+pub mod database;
+pub mod images_repo;
+pub mod page_repo;
+pub mod refresh_token_repo;
+pub mod revision_repo;
+pub mod search_repo;
+pub mod slot_repo;
+pub mod unit_of_work;
+pub mod users_repo;
+pub mod workspace_repo;
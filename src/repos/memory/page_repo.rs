@@ -0,0 +1,372 @@
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+};
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use uuid::Uuid;
+
+use super::{
+    database::{MemoryDatabase, Tables},
+    revision_repo::snapshot_page,
+};
+use crate::{
+    models::{page::PageMove, Page},
+    repos::traits::PageRepo,
+    utils::fractional_index,
+};
+
+pub struct MemoryPageRepo {
+    db: MemoryDatabase,
+}
+
+impl MemoryPageRepo {
+    pub fn new(db: MemoryDatabase) -> Self {
+        Self { db }
+    }
+}
+
+/// Returns the uuids of the pages nested under `uuid` whose `deleted_at`
+/// matches, without descending past other pages. `uuid` is included if its
+/// own `deleted_at` matches.
+fn subtree_deleted_at(
+    tables: &Tables,
+    uuid: &Uuid,
+    deleted_at: Option<NaiveDateTime>,
+) -> Vec<Uuid> {
+    let mut subtree = Vec::new();
+    let mut queue = tables
+        .pages
+        .get(uuid)
+        .filter(|page| page.deleted_at == deleted_at)
+        .map(|page| page.uuid)
+        .into_iter()
+        .collect::<Vec<_>>();
+    while let Some(parent) = queue.pop() {
+        subtree.push(parent);
+        queue.extend(
+            tables
+                .pages
+                .values()
+                .filter(|page| {
+                    page.parent_page_uuid == Some(parent) && page.deleted_at == deleted_at
+                })
+                .map(|page| page.uuid),
+        );
+    }
+    subtree
+}
+
+/// Returns `uuids` together with the uuids of all pages nested under them.
+fn with_descendants(tables: &Tables, uuids: &[Uuid]) -> HashSet<Uuid> {
+    let mut result = HashSet::new();
+    let mut queue = uuids
+        .iter()
+        .filter(|uuid| tables.pages.contains_key(uuid))
+        .copied()
+        .collect::<Vec<_>>();
+    while let Some(parent) = queue.pop() {
+        if result.insert(parent) {
+            queue.extend(
+                tables
+                    .pages
+                    .values()
+                    .filter(|page| page.parent_page_uuid == Some(parent))
+                    .map(|page| page.uuid),
+            );
+        }
+    }
+    result
+}
+
+/// Returns the pages outside the trash under `parent_page_uuid`, or the root
+/// pages of the workspace if it's `None`, sorted by their order.
+fn child_pages(
+    tables: &Tables,
+    workspace_uuid: &Uuid,
+    parent_page_uuid: Option<&Uuid>,
+) -> Vec<Page> {
+    let mut result = tables
+        .pages
+        .values()
+        .filter(|page| {
+            page.workspace_uuid == *workspace_uuid
+                && page.deleted_at.is_none()
+                && page.parent_page_uuid.as_ref() == parent_page_uuid
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    result.sort_by(|a, b| (&a.order, a.uuid).cmp(&(&b.order, b.uuid)));
+    result
+}
+
+/// Deletes pages together with the pages nested under them, their content and
+/// revisions, and returns the urls of the images any of them referred to.
+pub(super) fn purge_pages(tables: &mut Tables, uuids: &[Uuid]) -> Vec<String> {
+    let uuids = with_descendants(tables, uuids);
+    let mut images = Vec::new();
+    for page in tables
+        .pages
+        .values()
+        .filter(|page| uuids.contains(&page.uuid))
+    {
+        images.extend(snapshot_page(tables, page).image_urls());
+    }
+    for revision in tables
+        .page_revisions
+        .iter()
+        .filter(|revision| uuids.contains(&revision.page_uuid))
+    {
+        // A snapshot that can't be read can't be restored either.
+        if let Ok(snapshot) = revision.parse_snapshot() {
+            images.extend(snapshot.image_urls());
+        }
+    }
+    tables.delete_pages(&uuids);
+
+    images.sort();
+    images.dedup();
+    images
+}
+
+/// Moves the pages `uuids` to the trash, or out of it if `deleted_at` is
+/// `None`. Returns the changed pages with `first` in front.
+fn set_deleted_at(
+    tables: &mut Tables,
+    uuids: &[Uuid],
+    deleted_at: Option<NaiveDateTime>,
+    first: &Uuid,
+) -> Vec<Page> {
+    let now = Utc::now().naive_utc();
+    let mut result = Vec::new();
+    for uuid in uuids {
+        if let Some(page) = tables.pages.get_mut(uuid) {
+            page.deleted_at = deleted_at;
+            page.updated_at = now;
+            result.push(page.clone());
+        }
+    }
+    result.sort_by_key(|page| page.uuid != *first);
+    result
+}
+
+#[async_trait]
+impl PageRepo for MemoryPageRepo {
+    async fn get_page_by_uuid(&self, uuid: &Uuid) -> Result<Option<Page>> {
+        self.db
+            .run(|tables| {
+                let page = tables
+                    .pages
+                    .get(uuid)
+                    .filter(|page| page.deleted_at.is_none())
+                    .cloned();
+                Ok(page)
+            })
+            .await
+    }
+
+    async fn get_trashed_page(&self, uuid: &Uuid) -> Result<Option<Page>> {
+        self.db
+            .run(|tables| {
+                let page = tables
+                    .pages
+                    .get(uuid)
+                    .filter(|page| page.deleted_at.is_some())
+                    .cloned();
+                Ok(page)
+            })
+            .await
+    }
+
+    async fn get_trashed_pages(&self, workspace_uuid: &Uuid) -> Result<Vec<Page>> {
+        self.db
+            .run(|tables| {
+                let mut result = tables
+                    .pages
+                    .values()
+                    .filter(|page| {
+                        page.workspace_uuid == *workspace_uuid && page.deleted_at.is_some()
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>();
+                result.sort_by_key(|page| Reverse(page.deleted_at));
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn create_page(&self, page: &Page) -> Result<()> {
+        self.db
+            .run(|tables| {
+                if tables.pages.contains_key(&page.uuid) {
+                    bail!("A page with the same uuid already exists");
+                }
+                tables.pages.insert(page.uuid, page.clone());
+                Ok(())
+            })
+            .await
+    }
+
+    async fn update_page(&self, page: &Page) -> Result<Option<Page>> {
+        self.db
+            .run(|tables| {
+                let result = tables
+                    .pages
+                    .get_mut(&page.uuid)
+                    .filter(|stored| stored.version == page.version)
+                    .map(|stored| {
+                        stored.title = page.title.clone();
+                        stored.image = page.image.clone();
+                        stored.version += 1;
+                        stored.updated_at = Utc::now().naive_utc();
+                        stored.clone()
+                    });
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn get_child_pages(
+        &self,
+        workspace_uuid: &Uuid,
+        parent_page_uuid: Option<&Uuid>,
+    ) -> Result<Vec<Page>> {
+        self.db
+            .run(|tables| Ok(child_pages(tables, workspace_uuid, parent_page_uuid)))
+            .await
+    }
+
+    async fn get_ancestors(&self, uuids: &[Uuid]) -> Result<HashMap<Uuid, Vec<Page>>> {
+        self.db
+            .run(|tables| {
+                let result = uuids
+                    .iter()
+                    .map(|uuid| {
+                        let mut ancestors = Vec::new();
+                        let mut parent = tables
+                            .pages
+                            .get(uuid)
+                            .and_then(|page| page.parent_page_uuid);
+                        while let Some(page) = parent.and_then(|parent| tables.pages.get(&parent)) {
+                            ancestors.push(page.clone());
+                            parent = page.parent_page_uuid;
+                        }
+                        ancestors.reverse();
+                        (*uuid, ancestors)
+                    })
+                    .collect();
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn rebalance_child_pages(
+        &self,
+        workspace_uuid: &Uuid,
+        parent_page_uuid: Option<&Uuid>,
+    ) -> Result<Vec<Page>> {
+        self.db
+            .run(|tables| {
+                let mut result = child_pages(tables, workspace_uuid, parent_page_uuid);
+                let keys = fractional_index::evenly_spaced(result.len());
+                let now = Utc::now().naive_utc();
+                for (page, key) in result.iter_mut().zip(keys) {
+                    page.order = key;
+                    page.updated_at = now;
+                    tables.pages.insert(page.uuid, page.clone());
+                }
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn move_page(&self, page: &Page) -> Result<PageMove> {
+        self.db
+            .run(|tables| {
+                if let Some(parent) = page.parent_page_uuid {
+                    if with_descendants(tables, &[page.uuid]).contains(&parent) {
+                        return Ok(PageMove::Cycle);
+                    }
+                }
+                let moved = tables
+                    .pages
+                    .get_mut(&page.uuid)
+                    .filter(|stored| stored.version == page.version && stored.deleted_at.is_none())
+                    .map(|stored| {
+                        stored.parent_page_uuid = page.parent_page_uuid;
+                        stored.order = page.order.clone();
+                        stored.version += 1;
+                        stored.updated_at = Utc::now().naive_utc();
+                        stored.clone()
+                    });
+                Ok(moved.map_or(PageMove::Outdated, PageMove::Moved))
+            })
+            .await
+    }
+
+    async fn trash_page(&self, uuid: &Uuid) -> Result<Vec<Page>> {
+        self.db
+            .run(|tables| {
+                let subtree = subtree_deleted_at(tables, uuid, None);
+                let now = Utc::now().naive_utc();
+                Ok(set_deleted_at(tables, &subtree, Some(now), uuid))
+            })
+            .await
+    }
+
+    async fn restore_page(&self, uuid: &Uuid) -> Result<Vec<Page>> {
+        self.db
+            .run(|tables| {
+                let page = match tables
+                    .pages
+                    .get(uuid)
+                    .filter(|page| page.deleted_at.is_some())
+                {
+                    Some(page) => page.clone(),
+                    None => return Ok(Vec::new()),
+                };
+                let subtree = subtree_deleted_at(tables, uuid, page.deleted_at);
+                let mut restored = set_deleted_at(tables, &subtree, None, uuid);
+
+                // The page's parent may have been moved to the trash on its own.
+                if let Some(parent) = page.parent_page_uuid {
+                    let parent_trashed = tables
+                        .pages
+                        .get(&parent)
+                        .is_none_or(|parent| parent.deleted_at.is_some());
+                    if parent_trashed {
+                        let root = tables.pages.get_mut(uuid).unwrap();
+                        root.parent_page_uuid = None;
+                        restored[0] = root.clone();
+                    }
+                }
+                Ok(restored)
+            })
+            .await
+    }
+
+    async fn purge_page(&self, uuid: &Uuid) -> Result<Vec<String>> {
+        self.db
+            .run(|tables| Ok(purge_pages(tables, &[*uuid])))
+            .await
+    }
+
+    async fn purge_trashed_pages(&self, deleted_before: NaiveDateTime) -> Result<Vec<String>> {
+        self.db
+            .run(|tables| {
+                let expired = tables
+                    .pages
+                    .values()
+                    .filter(|page| {
+                        page.deleted_at
+                            .is_some_and(|deleted_at| deleted_at < deleted_before)
+                    })
+                    .map(|page| page.uuid)
+                    .collect::<Vec<_>>();
+                Ok(purge_pages(tables, &expired))
+            })
+            .await
+    }
+}
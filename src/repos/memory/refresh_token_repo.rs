@@ -0,0 +1,79 @@
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::database::MemoryDatabase;
+use crate::{models::RefreshToken, repos::traits::RefreshTokenRepo};
+
+pub struct MemoryRefreshTokenRepo {
+    db: MemoryDatabase,
+}
+
+impl MemoryRefreshTokenRepo {
+    pub fn new(db: MemoryDatabase) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl RefreshTokenRepo for MemoryRefreshTokenRepo {
+    async fn get_refresh_token_by_hash(&self, hash: &str) -> Result<Option<RefreshToken>> {
+        self.db
+            .run(|tables| {
+                let result = tables
+                    .refresh_tokens
+                    .values()
+                    .find(|token| token.token_hash == hash)
+                    .cloned();
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn create_refresh_token(&self, refresh_token: &RefreshToken) -> Result<()> {
+        self.db
+            .run(|tables| {
+                if tables.refresh_tokens.contains_key(&refresh_token.uuid) {
+                    bail!("A refresh token with the same uuid already exists");
+                }
+                tables
+                    .refresh_tokens
+                    .insert(refresh_token.uuid, refresh_token.clone());
+                Ok(())
+            })
+            .await
+    }
+
+    async fn revoke_refresh_token(&self, uuid: &Uuid) -> Result<bool> {
+        self.db
+            .run(|tables| {
+                match tables
+                    .refresh_tokens
+                    .get_mut(uuid)
+                    .filter(|token| token.revoked_at.is_none())
+                {
+                    Some(token) => {
+                        token.revoked_at = Some(Utc::now().naive_utc());
+                        Ok(true)
+                    }
+                    None => Ok(false),
+                }
+            })
+            .await
+    }
+
+    async fn revoke_refresh_token_family(&self, family_uuid: &Uuid) -> Result<()> {
+        self.db
+            .run(|tables| {
+                let now = Utc::now().naive_utc();
+                for token in tables.refresh_tokens.values_mut() {
+                    if token.family_uuid == *family_uuid && token.revoked_at.is_none() {
+                        token.revoked_at = Some(now);
+                    }
+                }
+                Ok(())
+            })
+            .await
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+use super::{
+    database::{MemoryDatabase, Tables},
+    slot_repo::page_slots,
+};
+use crate::{
+    models::{
+        page_revision::{AtomSnapshot, PageSnapshot, SlotSnapshot},
+        Atom, Page, PageRevision, Slot,
+    },
+    repos::traits::RevisionRepo,
+};
+
+pub struct MemoryRevisionRepo {
+    db: MemoryDatabase,
+}
+
+impl MemoryRevisionRepo {
+    pub fn new(db: MemoryDatabase) -> Self {
+        Self { db }
+    }
+}
+
+/// Snapshots the content of `page`.
+pub(super) fn snapshot_page(tables: &Tables, page: &Page) -> PageSnapshot {
+    let mut slot_atoms = HashMap::<Uuid, Vec<&Atom>>::new();
+    for atom in tables.atoms.values() {
+        slot_atoms.entry(atom.slot_uuid).or_default().push(atom);
+    }
+    PageSnapshot {
+        title: page.title.clone(),
+        image: page.image.clone(),
+        slots: page_slots(tables, &page.uuid)
+            .into_iter()
+            .map(|slot| {
+                let mut atoms = slot_atoms.remove(&slot.uuid).unwrap_or_default();
+                atoms.sort_by_key(|atom| atom.idx);
+                SlotSnapshot {
+                    atoms: atoms
+                        .into_iter()
+                        .map(|atom| AtomSnapshot {
+                            uuid: atom.uuid,
+                            typ: atom.typ,
+                            data: atom.data.clone(),
+                        })
+                        .collect(),
+                    uuid: slot.uuid,
+                    order: slot.order,
+                }
+            })
+            .collect(),
+    }
+}
+
+#[async_trait]
+impl RevisionRepo for MemoryRevisionRepo {
+    async fn record_revision(
+        &self,
+        page_uuid: &Uuid,
+        user_uuid: Option<&Uuid>,
+        coalesce: Option<Duration>,
+    ) -> Result<Option<PageRevision>> {
+        let user_uuid = user_uuid.copied();
+        self.db
+            .run(|tables| {
+                let page = match tables.pages.get(page_uuid) {
+                    Some(page) => page,
+                    None => return Ok(None),
+                };
+                let snapshot = serde_json::to_string(&snapshot_page(tables, page))?;
+                let latest = tables
+                    .page_revisions
+                    .iter_mut()
+                    .filter(|revision| revision.page_uuid == *page_uuid)
+                    .max_by_key(|revision| revision.number);
+                let now = Utc::now().naive_utc();
+                match latest {
+                    Some(latest) if latest.snapshot == snapshot => Ok(None),
+                    Some(latest)
+                        if latest.user_uuid == user_uuid
+                            && coalesce.is_some_and(|window| latest.created_at + window > now) =>
+                    {
+                        latest.snapshot = snapshot;
+                        latest.updated_at = now;
+                        Ok(Some(latest.clone()))
+                    }
+                    latest => {
+                        let revision = PageRevision {
+                            page_uuid: *page_uuid,
+                            number: latest.map_or(1, |latest| latest.number + 1),
+                            user_uuid,
+                            created_at: now,
+                            updated_at: now,
+                            snapshot,
+                        };
+                        tables.page_revisions.push(revision.clone());
+                        Ok(Some(revision))
+                    }
+                }
+            })
+            .await
+    }
+
+    async fn get_page_revisions(
+        &self,
+        page_uuid: &Uuid,
+        newer_than: Option<i32>,
+        older_than: Option<i32>,
+        limit: i64,
+        oldest_first: bool,
+    ) -> Result<Vec<PageRevision>> {
+        self.db
+            .run(|tables| {
+                let mut result = tables
+                    .page_revisions
+                    .iter()
+                    .filter(|revision| {
+                        revision.page_uuid == *page_uuid
+                            && newer_than.is_none_or(|newer_than| revision.number > newer_than)
+                            && older_than.is_none_or(|older_than| revision.number < older_than)
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>();
+                result.sort_by_key(|revision| revision.number);
+                if !oldest_first {
+                    result.reverse();
+                }
+                result.truncate(limit.max(0) as usize);
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn get_revision(&self, page_uuid: &Uuid, number: i32) -> Result<Option<PageRevision>> {
+        self.db
+            .run(|tables| {
+                let result = tables
+                    .page_revisions
+                    .iter()
+                    .find(|revision| revision.page_uuid == *page_uuid && revision.number == number)
+                    .cloned();
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn restore_snapshot(
+        &self,
+        page_uuid: &Uuid,
+        snapshot: &PageSnapshot,
+    ) -> Result<Option<Page>> {
+        self.db
+            .run(|tables| {
+                let now = Utc::now().naive_utc();
+                let page = match tables.pages.get_mut(page_uuid) {
+                    Some(page) => {
+                        page.title = snapshot.title.clone();
+                        page.image = snapshot.image.clone();
+                        page.version += 1;
+                        page.updated_at = now;
+                        page.clone()
+                    }
+                    None => return Ok(None),
+                };
+
+                // Slots and atoms that still exist keep counting their versions,
+                // so edits based on their current content are rejected.
+                let slot_uuids = tables
+                    .slots
+                    .values()
+                    .filter(|slot| slot.page_uuid == *page_uuid)
+                    .map(|slot| slot.uuid)
+                    .collect::<HashSet<_>>();
+                let mut versions = slot_uuids
+                    .iter()
+                    .map(|uuid| (*uuid, tables.slots[uuid].version))
+                    .collect::<HashMap<_, _>>();
+                versions.extend(
+                    tables
+                        .atoms
+                        .values()
+                        .filter(|atom| slot_uuids.contains(&atom.slot_uuid))
+                        .map(|atom| (atom.uuid, atom.version)),
+                );
+                let next_version =
+                    |uuid: &Uuid| versions.get(uuid).map_or(0, |version| version + 1);
+
+                tables.delete_slots(&slot_uuids);
+                for slot in &snapshot.slots {
+                    tables.slots.insert(
+                        slot.uuid,
+                        Slot {
+                            page_uuid: *page_uuid,
+                            uuid: slot.uuid,
+                            order: slot.order.clone(),
+                            version: next_version(&slot.uuid),
+                            updated_at: now,
+                        },
+                    );
+                    for (idx, atom) in slot.atoms.iter().enumerate() {
+                        tables.atoms.insert(
+                            atom.uuid,
+                            Atom {
+                                slot_uuid: slot.uuid,
+                                idx: idx as i32,
+                                typ: atom.typ,
+                                data: atom.data.clone(),
+                                uuid: atom.uuid,
+                                version: next_version(&atom.uuid),
+                                updated_at: now,
+                            },
+                        );
+                    }
+                }
+                Ok(Some(page))
+            })
+            .await
+    }
+}
@@ -0,0 +1,230 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use super::database::{MemoryDatabase, Tables};
+use crate::{
+    models::{
+        search::{Highlight, SearchHit},
+        Atom, AtomType,
+    },
+    repos::traits::SearchRepo,
+};
+
+/// The most words a snippet holds, like `MaxWords` of the Postgres search.
+const SNIPPET_WORDS: usize = 30;
+
+/// The weights Postgres gives titles, headings and other atoms by default.
+const TITLE_WEIGHT: f32 = 1.0;
+const HEADING_WEIGHT: f32 = 0.4;
+const ATOM_WEIGHT: f32 = 0.1;
+
+pub struct MemorySearchRepo {
+    db: MemoryDatabase,
+}
+
+impl MemorySearchRepo {
+    pub fn new(db: MemoryDatabase) -> Self {
+        Self { db }
+    }
+}
+
+/// A word of a text, with its position in Unicode scalar values.
+struct Word {
+    start: usize,
+    end: usize,
+    lowercase: String,
+}
+
+/// Splits a text into words the way the `simple` configuration does for
+/// plain words: runs of alphanumeric characters, lowercased.
+fn words(text: &str) -> Vec<Word> {
+    let mut words = Vec::new();
+    let mut current: Option<Word> = None;
+    for (pos, c) in text.chars().enumerate() {
+        if c.is_alphanumeric() {
+            let word = current.get_or_insert_with(|| Word {
+                start: pos,
+                end: pos,
+                lowercase: String::new(),
+            });
+            word.end = pos + 1;
+            word.lowercase.extend(c.to_lowercase());
+        } else {
+            words.extend(current.take());
+        }
+    }
+    words.extend(current);
+    words
+}
+
+/// A web search style query: `or` separates alternatives, words prefixed with
+/// `-` must not occur and all other words must. Quotes are ignored, so
+/// phrases match their words anywhere.
+struct Query {
+    alternatives: Vec<Vec<String>>,
+    excluded: Vec<String>,
+}
+
+impl Query {
+    fn parse(query: &str) -> Self {
+        let mut alternatives = vec![Vec::new()];
+        let mut excluded = Vec::new();
+        for term in query.split_whitespace() {
+            if term.eq_ignore_ascii_case("or") {
+                alternatives.push(Vec::new());
+            } else if let Some(term) = term.strip_prefix('-') {
+                excluded.extend(words(term).into_iter().map(|word| word.lowercase));
+            } else {
+                alternatives
+                    .last_mut()
+                    .unwrap()
+                    .extend(words(term).into_iter().map(|word| word.lowercase));
+            }
+        }
+        alternatives.retain(|words| !words.is_empty());
+        Self {
+            alternatives,
+            excluded,
+        }
+    }
+
+    /// The share of the query's words found in `text`, or `None` if it
+    /// doesn't match.
+    fn score(&self, text: &[Word]) -> Option<f32> {
+        let contains = |word: &String| text.iter().any(|other| other.lowercase == *word);
+        if self.excluded.iter().any(contains) {
+            return None;
+        }
+        let matched = self
+            .alternatives
+            .iter()
+            .filter(|words| words.iter().all(contains))
+            .count();
+        (matched > 0).then(|| matched as f32 / self.alternatives.len() as f32)
+    }
+
+    /// Whether `word` is one of the words looked for.
+    fn matches(&self, word: &Word) -> bool {
+        self.alternatives
+            .iter()
+            .flatten()
+            .any(|query_word| *query_word == word.lowercase)
+    }
+}
+
+/// The searchable text of an atom, like `atom_search_text` in Postgres.
+fn atom_search_text(atom: &Atom) -> Option<String> {
+    let field = match atom.typ {
+        AtomType::Text
+        | AtomType::Heading
+        | AtomType::BulletedListItem
+        | AtomType::NumberedListItem
+        | AtomType::TodoListItem
+        | AtomType::Quote => "text",
+        AtomType::Code => "code",
+        AtomType::Image => "caption",
+        AtomType::Math => "tex",
+        AtomType::Divider | AtomType::PageLink => return None,
+    };
+    let data = serde_json::from_str::<serde_json::Value>(atom.data.as_deref()?).ok()?;
+    data.get(field)?.as_str().map(str::to_string)
+}
+
+/// Cuts a snippet of at most `SNIPPET_WORDS` words out of `text`, starting
+/// shortly before the first match, and highlights the matching words.
+fn snippet(query: &Query, text: &str, words: &[Word]) -> (String, Vec<Highlight>) {
+    let first_match = words
+        .iter()
+        .position(|word| query.matches(word))
+        .unwrap_or(0);
+    let first = first_match
+        .saturating_sub(SNIPPET_WORDS / 3)
+        .min(words.len().saturating_sub(SNIPPET_WORDS));
+    let words = &words[first..words.len().min(first + SNIPPET_WORDS)];
+    let (start, end) = if first == 0 && words.len() < SNIPPET_WORDS {
+        (0, text.chars().count())
+    } else {
+        (words[0].start, words[words.len() - 1].end)
+    };
+    let snippet = text.chars().skip(start).take(end - start).collect();
+    let highlights = words
+        .iter()
+        .filter(|word| query.matches(word))
+        .map(|word| Highlight {
+            start: word.start - start,
+            end: word.end - start,
+        })
+        .collect();
+    (snippet, highlights)
+}
+
+fn search_hits(tables: &Tables, workspace_uuid: &Uuid, query: &Query) -> Vec<SearchHit> {
+    let mut hits = Vec::new();
+    let mut add_hit = |page_uuid, atom: Option<&Atom>, text: &str, weight: f32| {
+        let words = words(text);
+        if let Some(score) = query.score(&words) {
+            let (snippet, highlights) = snippet(query, text, &words);
+            hits.push(SearchHit {
+                page_uuid,
+                slot_uuid: atom.map(|atom| atom.slot_uuid),
+                atom_uuid: atom.map(|atom| atom.uuid),
+                rank: weight * score,
+                snippet,
+                highlights,
+            });
+        }
+    };
+    for page in tables
+        .pages
+        .values()
+        .filter(|page| page.workspace_uuid == *workspace_uuid && page.deleted_at.is_none())
+    {
+        add_hit(page.uuid, None, &page.title, TITLE_WEIGHT);
+        for atom in tables.atoms.values() {
+            let in_page = tables
+                .slots
+                .get(&atom.slot_uuid)
+                .is_some_and(|slot| slot.page_uuid == page.uuid);
+            if let Some(text) = atom_search_text(atom).filter(|_| in_page) {
+                let weight = match atom.typ {
+                    AtomType::Heading => HEADING_WEIGHT,
+                    _ => ATOM_WEIGHT,
+                };
+                add_hit(page.uuid, Some(atom), &text, weight);
+            }
+        }
+    }
+    hits
+}
+
+#[async_trait]
+impl SearchRepo for MemorySearchRepo {
+    /// Approximates the Postgres full-text search: words match exactly, and
+    /// ranks only reflect the weights and how many alternatives matched.
+    async fn search(
+        &self,
+        workspace_uuid: &Uuid,
+        query: &str,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<SearchHit>> {
+        let query = Query::parse(query);
+        self.db
+            .run(|tables| {
+                let mut hits = search_hits(tables, workspace_uuid, &query);
+                hits.sort_by(|a, b| {
+                    b.rank
+                        .total_cmp(&a.rank)
+                        .then(a.page_uuid.cmp(&b.page_uuid))
+                        .then(a.atom_uuid.cmp(&b.atom_uuid))
+                });
+                Ok(hits
+                    .into_iter()
+                    .skip(offset.max(0) as usize)
+                    .take(limit.max(0) as usize)
+                    .collect())
+            })
+            .await
+    }
+}
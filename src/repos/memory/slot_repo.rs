@@ -0,0 +1,108 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::database::{MemoryDatabase, Tables};
+use crate::{models::Slot, repos::traits::SlotRepo, utils::fractional_index};
+
+pub struct MemorySlotRepo {
+    db: MemoryDatabase,
+}
+
+impl MemorySlotRepo {
+    pub fn new(db: MemoryDatabase) -> Self {
+        Self { db }
+    }
+}
+
+/// Returns the slots of a page sorted by their order key.
+pub(super) fn page_slots(tables: &Tables, page_uuid: &Uuid) -> Vec<Slot> {
+    let mut result = tables
+        .slots
+        .values()
+        .filter(|slot| slot.page_uuid == *page_uuid)
+        .cloned()
+        .collect::<Vec<_>>();
+    result.sort_by(|a, b| (&a.order, a.uuid).cmp(&(&b.order, b.uuid)));
+    result
+}
+
+#[async_trait]
+impl SlotRepo for MemorySlotRepo {
+    async fn get_slot_by_uuid(&self, uuid: &Uuid) -> Result<Option<Slot>> {
+        self.db
+            .run(|tables| Ok(tables.slots.get(uuid).cloned()))
+            .await
+    }
+
+    async fn get_page_slots(&self, page_uuid: &Uuid) -> Result<Vec<Slot>> {
+        self.db
+            .run(|tables| Ok(page_slots(tables, page_uuid)))
+            .await
+    }
+
+    async fn create_slot(&self, slot: &Slot) -> Result<()> {
+        self.db
+            .run(|tables| {
+                if tables.slots.contains_key(&slot.uuid) {
+                    bail!("A slot with the same uuid already exists");
+                }
+                tables.slots.insert(slot.uuid, slot.clone());
+                Ok(())
+            })
+            .await
+    }
+
+    async fn update_slot(&self, slot: &Slot) -> Result<Option<Slot>> {
+        self.db
+            .run(|tables| {
+                let result = tables
+                    .slots
+                    .get_mut(&slot.uuid)
+                    .filter(|stored| stored.version == slot.version)
+                    .map(|stored| {
+                        stored.order = slot.order.clone();
+                        stored.version += 1;
+                        stored.updated_at = Utc::now().naive_utc();
+                        stored.clone()
+                    });
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn rebalance_page_slots(&self, page_uuid: &Uuid) -> Result<Vec<Slot>> {
+        self.db
+            .run(|tables| {
+                let mut result = page_slots(tables, page_uuid);
+                let keys = fractional_index::evenly_spaced(result.len());
+                let now = Utc::now().naive_utc();
+                for (slot, key) in result.iter_mut().zip(keys) {
+                    slot.order = key;
+                    slot.updated_at = now;
+                    tables.slots.insert(slot.uuid, slot.clone());
+                }
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn delete_slot(&self, uuid: &Uuid) -> Result<Vec<String>> {
+        self.db
+            .run(|tables| {
+                let mut atoms = tables
+                    .atoms
+                    .values()
+                    .filter(|atom| atom.slot_uuid == *uuid)
+                    .collect::<Vec<_>>();
+                atoms.sort_by_key(|atom| atom.idx);
+                let images = atoms.iter().filter_map(|atom| atom.image_url()).collect();
+                tables.delete_slots(&HashSet::from([*uuid]));
+                Ok(images)
+            })
+            .await
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{
+    database::MemoryDatabase, page_repo::MemoryPageRepo, workspace_repo::MemoryWorkspaceRepo,
+};
+use crate::repos::{
+    traits::{ImagesRepo, PageRepo, UnitOfWork, UnitOfWorkRepo, WorkspaceRepo},
+    unit_of_work::PendingImages,
+};
+
+pub struct MemoryUnitOfWorkRepo {
+    db: MemoryDatabase,
+    images_repo: Arc<dyn ImagesRepo>,
+}
+
+impl MemoryUnitOfWorkRepo {
+    pub fn new(db: MemoryDatabase, images_repo: Arc<dyn ImagesRepo>) -> Self {
+        Self { db, images_repo }
+    }
+}
+
+#[async_trait]
+impl UnitOfWorkRepo for MemoryUnitOfWorkRepo {
+    fn pending_images(&self) -> PendingImages {
+        PendingImages::new(Arc::clone(&self.images_repo))
+    }
+
+    /// Other calls wait until the unit of work is committed or rolled back,
+    /// so it must only use its own repositories.
+    async fn begin(&self, images: PendingImages) -> Result<Box<dyn UnitOfWork>> {
+        let db = self.db.begin().await?;
+        Ok(Box::new(MemoryUnitOfWork {
+            workspace_repo: MemoryWorkspaceRepo::new(db.clone()),
+            page_repo: MemoryPageRepo::new(db.clone()),
+            images_repo: images,
+            db,
+        }))
+    }
+}
+
+pub struct MemoryUnitOfWork {
+    db: MemoryDatabase,
+    workspace_repo: MemoryWorkspaceRepo,
+    page_repo: MemoryPageRepo,
+    images_repo: PendingImages,
+}
+
+#[async_trait]
+impl UnitOfWork for MemoryUnitOfWork {
+    fn workspace_repo(&self) -> &dyn WorkspaceRepo {
+        &self.workspace_repo
+    }
+
+    fn page_repo(&self) -> &dyn PageRepo {
+        &self.page_repo
+    }
+
+    async fn commit(self: Box<Self>) -> Result<()> {
+        self.db.commit().await?;
+        self.images_repo.commit().await;
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) -> Result<()> {
+        let result = self.db.rollback().await;
+        self.images_repo.rollback().await;
+        result
+    }
+}
@@ -0,0 +1,65 @@
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use super::database::MemoryDatabase;
+use crate::{models::user::User, repos::traits::UserRepo};
+
+pub struct MemoryUsersRepo {
+    db: MemoryDatabase,
+}
+
+impl MemoryUsersRepo {
+    pub fn new(db: MemoryDatabase) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl UserRepo for MemoryUsersRepo {
+    async fn get_user_by_uuid(&self, uuid: &Uuid) -> Result<Option<User>> {
+        self.db
+            .run(|tables| Ok(tables.users.get(uuid).cloned()))
+            .await
+    }
+
+    async fn create_user(&self, user: &User) -> Result<()> {
+        self.db
+            .run(|tables| {
+                if tables.users.values().any(|other| {
+                    other.uuid == user.uuid
+                        || other.email == user.email
+                        || other.username == user.username
+                }) {
+                    bail!("A user with the same uuid, email or username already exists");
+                }
+                tables.users.insert(user.uuid, user.clone());
+                Ok(())
+            })
+            .await
+    }
+
+    async fn update_user(&self, user: &User) -> Result<()> {
+        self.db
+            .run(|tables| {
+                if let Some(stored) = tables.users.get_mut(&user.uuid) {
+                    *stored = user.clone();
+                }
+                Ok(())
+            })
+            .await
+    }
+
+    async fn get_user_by_login(&self, login: &str) -> Result<Option<User>> {
+        self.db
+            .run(|tables| {
+                let result = tables
+                    .users
+                    .values()
+                    .find(|user| user.email == login || user.username == login)
+                    .cloned();
+                Ok(result)
+            })
+            .await
+    }
+}
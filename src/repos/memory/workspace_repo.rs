@@ -0,0 +1,321 @@
+use std::{cmp::Reverse, collections::HashSet};
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use uuid::Uuid;
+
+use super::{
+    database::{MemoryDatabase, Tables},
+    page_repo::purge_pages,
+};
+use crate::{
+    models::{workspace_member::MemberChange, Page, Workspace, WorkspaceMember, WorkspaceRole},
+    repos::traits::WorkspaceRepo,
+};
+
+pub struct MemoryWorkspaceRepo {
+    db: MemoryDatabase,
+}
+
+impl MemoryWorkspaceRepo {
+    pub fn new(db: MemoryDatabase) -> Self {
+        Self { db }
+    }
+}
+
+/// Gives a member the role `new_role`, or removes them if it's `None`, unless
+/// that leaves the workspace without an owner.
+fn change_member(
+    tables: &mut Tables,
+    workspace_uuid: &Uuid,
+    user_uuid: &Uuid,
+    new_role: Option<WorkspaceRole>,
+) -> MemberChange {
+    let members = || {
+        tables
+            .workspace_members
+            .iter()
+            .filter(|member| member.workspace_uuid == *workspace_uuid)
+    };
+    let member = match members().find(|member| member.user_uuid == *user_uuid) {
+        Some(member) => member,
+        None => return MemberChange::NotFound,
+    };
+    let owners = members()
+        .filter(|member| member.role == WorkspaceRole::Owner)
+        .count();
+    if member.role == WorkspaceRole::Owner && new_role != Some(WorkspaceRole::Owner) && owners == 1
+    {
+        return MemberChange::LastOwner;
+    }
+
+    let is_target = |member: &WorkspaceMember| {
+        member.workspace_uuid == *workspace_uuid && member.user_uuid == *user_uuid
+    };
+    match new_role {
+        Some(new_role) => {
+            for member in tables.workspace_members.iter_mut().filter(|m| is_target(m)) {
+                member.role = new_role;
+            }
+        }
+        None => tables.workspace_members.retain(|member| !is_target(member)),
+    }
+    MemberChange::Changed
+}
+
+/// Deletes workspaces together with their members and pages, and returns the
+/// urls of the images any of them referred to.
+fn purge_workspaces(tables: &mut Tables, uuids: &HashSet<Uuid>) -> Vec<String> {
+    let page_uuids = tables
+        .pages
+        .values()
+        .filter(|page| uuids.contains(&page.workspace_uuid))
+        .map(|page| page.uuid)
+        .collect::<Vec<_>>();
+    let mut images = purge_pages(tables, &page_uuids);
+    images.extend(
+        tables
+            .workspaces
+            .values()
+            .filter(|workspace| uuids.contains(&workspace.uuid))
+            .map(|workspace| workspace.image.clone()),
+    );
+    tables.delete_workspaces(uuids);
+    images
+}
+
+/// Returns the workspaces `user_uuid` is a member of, in the order they joined.
+fn member_workspaces<'a>(
+    tables: &'a Tables,
+    user_uuid: &'a Uuid,
+) -> impl Iterator<Item = (&'a WorkspaceMember, &'a Workspace)> {
+    tables
+        .workspace_members
+        .iter()
+        .filter(move |member| member.user_uuid == *user_uuid)
+        .filter_map(|member| {
+            tables
+                .workspaces
+                .get(&member.workspace_uuid)
+                .map(|workspace| (member, workspace))
+        })
+}
+
+#[async_trait]
+impl WorkspaceRepo for MemoryWorkspaceRepo {
+    async fn get_user_workspaces(&self, user_uuid: &Uuid) -> Result<Vec<Workspace>> {
+        self.db
+            .run(|tables| {
+                let result = member_workspaces(tables, user_uuid)
+                    .filter(|(_, workspace)| workspace.deleted_at.is_none())
+                    .map(|(_, workspace)| workspace.clone())
+                    .collect();
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn get_workspace_by_uuid(&self, uuid: &Uuid) -> Result<Option<Workspace>> {
+        self.db
+            .run(|tables| {
+                let result = tables
+                    .workspaces
+                    .get(uuid)
+                    .filter(|workspace| workspace.deleted_at.is_none())
+                    .cloned();
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn get_trashed_workspaces(&self, owner_uuid: &Uuid) -> Result<Vec<Workspace>> {
+        self.db
+            .run(|tables| {
+                let mut result = member_workspaces(tables, owner_uuid)
+                    .filter(|(member, workspace)| {
+                        member.role == WorkspaceRole::Owner && workspace.deleted_at.is_some()
+                    })
+                    .map(|(_, workspace)| workspace.clone())
+                    .collect::<Vec<_>>();
+                result.sort_by_key(|workspace| Reverse(workspace.deleted_at));
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn create_workspace(&self, workspace: &Workspace, owner_uuid: &Uuid) -> Result<()> {
+        self.db
+            .run(|tables| {
+                if tables.workspaces.contains_key(&workspace.uuid) {
+                    bail!("A workspace with the same uuid already exists");
+                }
+                tables.workspaces.insert(workspace.uuid, workspace.clone());
+                tables.workspace_members.push(WorkspaceMember::new(
+                    *owner_uuid,
+                    workspace.uuid,
+                    WorkspaceRole::Owner,
+                ));
+                Ok(())
+            })
+            .await
+    }
+
+    async fn update_workspace(&self, workspace: &Workspace) -> Result<Option<Workspace>> {
+        self.db
+            .run(|tables| {
+                let result = tables
+                    .workspaces
+                    .get_mut(&workspace.uuid)
+                    .filter(|stored| stored.version == workspace.version)
+                    .map(|stored| {
+                        stored.name = workspace.name.clone();
+                        stored.image = workspace.image.clone();
+                        stored.version += 1;
+                        stored.updated_at = Utc::now().naive_utc();
+                        stored.clone()
+                    });
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn trash_workspace(&self, uuid: &Uuid) -> Result<Option<Workspace>> {
+        self.db
+            .run(|tables| {
+                let result = tables
+                    .workspaces
+                    .get_mut(uuid)
+                    .filter(|workspace| workspace.deleted_at.is_none())
+                    .map(|workspace| {
+                        let now = Utc::now().naive_utc();
+                        workspace.deleted_at = Some(now);
+                        workspace.updated_at = now;
+                        workspace.clone()
+                    });
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn restore_workspace(&self, uuid: &Uuid) -> Result<Option<Workspace>> {
+        self.db
+            .run(|tables| {
+                let result = tables
+                    .workspaces
+                    .get_mut(uuid)
+                    .filter(|workspace| workspace.deleted_at.is_some())
+                    .map(|workspace| {
+                        workspace.deleted_at = None;
+                        workspace.updated_at = Utc::now().naive_utc();
+                        workspace.clone()
+                    });
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn purge_workspace(&self, uuid: &Uuid) -> Result<Vec<String>> {
+        self.db
+            .run(|tables| Ok(purge_workspaces(tables, &HashSet::from([*uuid]))))
+            .await
+    }
+
+    async fn purge_trashed_workspaces(&self, deleted_before: NaiveDateTime) -> Result<Vec<String>> {
+        self.db
+            .run(|tables| {
+                let expired = tables
+                    .workspaces
+                    .values()
+                    .filter(|workspace| {
+                        workspace
+                            .deleted_at
+                            .is_some_and(|deleted_at| deleted_at < deleted_before)
+                    })
+                    .map(|workspace| workspace.uuid)
+                    .collect();
+                Ok(purge_workspaces(tables, &expired))
+            })
+            .await
+    }
+
+    async fn get_pages(&self, uuid: &Uuid) -> Result<Vec<Page>> {
+        self.db
+            .run(|tables| {
+                let mut result = tables
+                    .pages
+                    .values()
+                    .filter(|page| page.workspace_uuid == *uuid && page.deleted_at.is_none())
+                    .cloned()
+                    .collect::<Vec<_>>();
+                result.sort_by(|a, b| (&a.order, a.uuid).cmp(&(&b.order, b.uuid)));
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn get_member_role(
+        &self,
+        workspace_uuid: &Uuid,
+        user_uuid: &Uuid,
+    ) -> Result<Option<WorkspaceRole>> {
+        self.db
+            .run(|tables| {
+                let result = member_workspaces(tables, user_uuid)
+                    .find(|(_, workspace)| {
+                        workspace.uuid == *workspace_uuid && workspace.deleted_at.is_none()
+                    })
+                    .map(|(member, _)| member.role);
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn get_members(&self, workspace_uuid: &Uuid) -> Result<Vec<WorkspaceMember>> {
+        self.db
+            .run(|tables| {
+                let result = tables
+                    .workspace_members
+                    .iter()
+                    .filter(|member| member.workspace_uuid == *workspace_uuid)
+                    .cloned()
+                    .collect();
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn add_member(&self, member: &WorkspaceMember) -> Result<()> {
+        self.db
+            .run(|tables| {
+                if tables.workspace_members.iter().any(|other| {
+                    other.workspace_uuid == member.workspace_uuid
+                        && other.user_uuid == member.user_uuid
+                }) {
+                    bail!("The user is already a member of the workspace");
+                }
+                tables.workspace_members.push(member.clone());
+                Ok(())
+            })
+            .await
+    }
+
+    async fn update_member(&self, member: &WorkspaceMember) -> Result<MemberChange> {
+        self.db
+            .run(|tables| {
+                Ok(change_member(
+                    tables,
+                    &member.workspace_uuid,
+                    &member.user_uuid,
+                    Some(member.role),
+                ))
+            })
+            .await
+    }
+
+    async fn remove_member(&self, workspace_uuid: &Uuid, user_uuid: &Uuid) -> Result<MemberChange> {
+        self.db
+            .run(|tables| Ok(change_member(tables, workspace_uuid, user_uuid, None)))
+            .await
+    }
+}
@@ -1,5 +1,106 @@
+use std::sync::Arc;
+
+use strum::{Display, EnumString};
+
+use self::{
+    atom_repo::PostgresqlAtomRepo,
+    database::Database,
+    memory::{
+        atom_repo::MemoryAtomRepo, database::MemoryDatabase, images_repo::MemoryImagesRepo,
+        page_repo::MemoryPageRepo, refresh_token_repo::MemoryRefreshTokenRepo,
+        revision_repo::MemoryRevisionRepo, search_repo::MemorySearchRepo,
+        slot_repo::MemorySlotRepo, unit_of_work::MemoryUnitOfWorkRepo, users_repo::MemoryUsersRepo,
+        workspace_repo::MemoryWorkspaceRepo,
+    },
+    page_repo::PageRepo,
+    refresh_token_repo::PostgresqlRefreshTokenRepo,
+    revision_repo::PostgresqlRevisionRepo,
+    search_repo::PostgresqlSearchRepo,
+    slot_repo::PostgresqlSlotRepo,
+    traits::{
+        AtomRepo, ImagesRepo, RefreshTokenRepo, RevisionRepo, SearchRepo, SlotRepo, UnitOfWorkRepo,
+        UserRepo, WorkspaceRepo,
+    },
+    unit_of_work::PostgresqlUnitOfWorkRepo,
+    users_repo::PostgresqlUsersRepo,
+    workspace_repo::PostgresqlWorkspaceRepo,
+};
+
+pub mod atom_repo;
+pub mod database;
 pub mod images_repo;
+pub mod memory;
 pub mod page_repo;
+pub mod refresh_token_repo;
+pub mod revision_repo;
+pub mod search_repo;
+pub mod slot_repo;
+#[cfg(test)]
+pub(crate) mod test_db;
 pub mod traits;
+pub mod unit_of_work;
 pub mod users_repo;
 pub mod workspace_repo;
+
+/// Selects where the app keeps its data in `BaseConfig`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, EnumString, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum StorageKind {
+    /// Keeps data in Postgres and images in S3.
+    Postgresql,
+    /// Keeps everything in memory until the app stops.
+    Memory,
+}
+
+/// The repositories the resolvers use.
+#[derive(Clone)]
+pub struct Repos {
+    pub user_repo: Arc<dyn UserRepo>,
+    pub refresh_token_repo: Arc<dyn RefreshTokenRepo>,
+    pub workspace_repo: Arc<dyn WorkspaceRepo>,
+    pub page_repo: Arc<dyn traits::PageRepo>,
+    pub slot_repo: Arc<dyn SlotRepo>,
+    pub atom_repo: Arc<dyn AtomRepo>,
+    pub revision_repo: Arc<dyn RevisionRepo>,
+    pub search_repo: Arc<dyn SearchRepo>,
+    pub images_repo: Arc<dyn ImagesRepo>,
+    pub unit_of_work_repo: Arc<dyn UnitOfWorkRepo>,
+}
+
+impl Repos {
+    pub fn postgresql(db: Database, images_repo: Arc<dyn ImagesRepo>) -> Self {
+        Self {
+            user_repo: Arc::new(PostgresqlUsersRepo::new(db.clone())),
+            refresh_token_repo: Arc::new(PostgresqlRefreshTokenRepo::new(db.clone())),
+            workspace_repo: Arc::new(PostgresqlWorkspaceRepo::new(db.clone())),
+            page_repo: Arc::new(PageRepo::new(db.clone())),
+            slot_repo: Arc::new(PostgresqlSlotRepo::new(db.clone())),
+            atom_repo: Arc::new(PostgresqlAtomRepo::new(db.clone())),
+            revision_repo: Arc::new(PostgresqlRevisionRepo::new(db.clone())),
+            search_repo: Arc::new(PostgresqlSearchRepo::new(db.clone())),
+            unit_of_work_repo: Arc::new(PostgresqlUnitOfWorkRepo::new(
+                db,
+                Arc::clone(&images_repo),
+            )),
+            images_repo,
+        }
+    }
+
+    /// Repositories sharing a new, empty in-memory database.
+    pub fn memory() -> Self {
+        let db = MemoryDatabase::new();
+        let images_repo: Arc<dyn ImagesRepo> = Arc::new(MemoryImagesRepo::new());
+        Self {
+            user_repo: Arc::new(MemoryUsersRepo::new(db.clone())),
+            refresh_token_repo: Arc::new(MemoryRefreshTokenRepo::new(db.clone())),
+            workspace_repo: Arc::new(MemoryWorkspaceRepo::new(db.clone())),
+            page_repo: Arc::new(MemoryPageRepo::new(db.clone())),
+            slot_repo: Arc::new(MemorySlotRepo::new(db.clone())),
+            atom_repo: Arc::new(MemoryAtomRepo::new(db.clone())),
+            revision_repo: Arc::new(MemoryRevisionRepo::new(db.clone())),
+            search_repo: Arc::new(MemorySearchRepo::new(db.clone())),
+            unit_of_work_repo: Arc::new(MemoryUnitOfWorkRepo::new(db, Arc::clone(&images_repo))),
+            images_repo,
+        }
+    }
+}
@@ -1,66 +1,538 @@
+use std::collections::HashMap;
+
 use anyhow::Error;
 use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
 use diesel::{
-    r2d2::ConnectionManager, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl,
-    RunQueryDsl,
+    pg::Pg,
+    sql_query,
+    sql_types::{Array, Nullable, Timestamp, Uuid as SqlUuid},
+    Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, QueryResult,
+    QueryableByName, RunQueryDsl,
 };
-use r2d2::Pool;
 use uuid::Uuid;
 
-use crate::models::Page;
+use crate::{
+    models::{page::PageMove, page_revision::PageSnapshot, Page},
+    schema::pages,
+    utils::fractional_index,
+};
 
-use super::traits;
+use super::{database::Database, revision_repo::snapshot_page, traits};
 
 pub struct PageRepo {
-    pool: Pool<ConnectionManager<PgConnection>>,
+    db: Database,
 }
 
 impl PageRepo {
-    pub fn new(pool: Pool<ConnectionManager<PgConnection>>) -> Self {
-        Self { pool }
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[derive(QueryableByName)]
+struct PageUuid {
+    #[diesel(sql_type = SqlUuid)]
+    uuid: Uuid,
+}
+
+/// Returns `uuids` together with the uuids of all pages nested under them.
+fn with_descendants(conn: &mut PgConnection, uuids: &[Uuid]) -> QueryResult<Vec<Uuid>> {
+    let rows = sql_query(
+        "WITH RECURSIVE tree AS ( \
+             SELECT uuid FROM pages WHERE uuid = ANY($1) \
+             UNION SELECT pages.uuid FROM pages \
+             JOIN tree ON pages.parent_page_uuid = tree.uuid \
+         ) SELECT uuid FROM tree",
+    )
+    .bind::<Array<SqlUuid>, _>(uuids)
+    .load::<PageUuid>(conn)?;
+    Ok(rows.into_iter().map(|row| row.uuid).collect())
+}
+
+#[derive(QueryableByName)]
+struct Ancestor {
+    #[diesel(sql_type = SqlUuid)]
+    descendant_uuid: Uuid,
+    #[diesel(embed)]
+    page: Page,
+}
+
+/// Returns `uuid` together with the uuids of the pages nested under it that
+/// have the same `deleted_at`, without descending past other pages.
+fn subtree_deleted_at(
+    conn: &mut PgConnection,
+    uuid: &Uuid,
+    deleted_at: Option<NaiveDateTime>,
+) -> QueryResult<Vec<Uuid>> {
+    let rows = sql_query(
+        "WITH RECURSIVE tree AS ( \
+             SELECT uuid FROM pages WHERE uuid = $1 AND deleted_at IS NOT DISTINCT FROM $2 \
+             UNION SELECT pages.uuid FROM pages \
+             JOIN tree ON pages.parent_page_uuid = tree.uuid \
+             WHERE pages.deleted_at IS NOT DISTINCT FROM $2 \
+         ) SELECT uuid FROM tree",
+    )
+    .bind::<SqlUuid, _>(uuid)
+    .bind::<Nullable<Timestamp>, _>(deleted_at)
+    .load::<PageUuid>(conn)?;
+    Ok(rows.into_iter().map(|row| row.uuid).collect())
+}
+
+/// Locks the workspace's page tree. Moves take turns so that two concurrent
+/// moves can't form a cycle together.
+fn lock_page_tree(conn: &mut PgConnection, workspace_uuid: &Uuid) -> QueryResult<()> {
+    use crate::schema::workspaces;
+
+    workspaces::table
+        .filter(workspaces::uuid.eq(workspace_uuid))
+        .select(workspaces::uuid)
+        .for_no_key_update()
+        .first::<Uuid>(conn)
+        .optional()?;
+    Ok(())
+}
+
+/// Selects the pages outside the trash under `parent_page_uuid`, or the root
+/// pages of the workspace if it's `None`.
+fn child_pages<'a>(
+    workspace_uuid: &'a Uuid,
+    parent_page_uuid: Option<&'a Uuid>,
+) -> pages::BoxedQuery<'a, Pg> {
+    let query = pages::table
+        .filter(pages::workspace_uuid.eq(workspace_uuid))
+        .filter(pages::deleted_at.is_null())
+        .order((pages::order.asc(), pages::uuid.asc()))
+        .into_boxed();
+    match parent_page_uuid {
+        Some(parent) => query.filter(pages::parent_page_uuid.eq(parent)),
+        None => query.filter(pages::parent_page_uuid.is_null()),
     }
 }
 
+/// Deletes pages together with the pages nested under them, their content and
+/// revisions, and returns the urls of the images any of them referred to.
+pub(super) fn purge_pages(conn: &mut PgConnection, uuids: &[Uuid]) -> QueryResult<Vec<String>> {
+    use crate::schema::page_revisions;
+
+    let uuids = &with_descendants(conn, uuids)?;
+    let mut images = Vec::new();
+    for page in pages::table
+        .filter(pages::uuid.eq_any(uuids))
+        .load::<Page>(conn)?
+    {
+        images.extend(snapshot_page(conn, &page)?.image_urls());
+    }
+    let snapshots = page_revisions::table
+        .filter(page_revisions::page_uuid.eq_any(uuids))
+        .select(page_revisions::snapshot)
+        .load::<String>(conn)?;
+    for snapshot in snapshots {
+        // A snapshot that can't be read can't be restored either.
+        if let Ok(snapshot) = serde_json::from_str::<PageSnapshot>(&snapshot) {
+            images.extend(snapshot.image_urls());
+        }
+    }
+
+    // Nested pages, slots, atoms and revisions cascade.
+    diesel::delete(pages::table.filter(pages::uuid.eq_any(uuids))).execute(conn)?;
+
+    images.sort();
+    images.dedup();
+    Ok(images)
+}
+
 #[async_trait]
 impl traits::PageRepo for PageRepo {
     async fn get_page_by_uuid(&self, uuid: &Uuid) -> Result<Option<Page>, Error> {
         use crate::schema::pages;
 
-        let mut conn = self.pool.get()?;
-        let page = pages::dsl::pages
-            .filter(pages::columns::uuid.eq(uuid))
-            .first::<Page>(&mut conn)
-            .optional()?;
+        let uuid = *uuid;
+        self.db
+            .run(move |conn| {
+                let page = pages::dsl::pages
+                    .filter(pages::columns::uuid.eq(uuid))
+                    .filter(pages::columns::deleted_at.is_null())
+                    .first::<Page>(conn)
+                    .optional()?;
 
-        Ok(page)
+                Ok(page)
+            })
+            .await
+    }
+
+    async fn get_trashed_page(&self, uuid_val: &Uuid) -> Result<Option<Page>, Error> {
+        use crate::schema::pages::dsl::*;
+
+        let uuid_val = *uuid_val;
+        self.db
+            .run(move |conn| {
+                let page = pages
+                    .filter(uuid.eq(uuid_val))
+                    .filter(deleted_at.is_not_null())
+                    .first::<Page>(conn)
+                    .optional()?;
+
+                Ok(page)
+            })
+            .await
+    }
+
+    async fn get_trashed_pages(&self, workspace_uuid_val: &Uuid) -> Result<Vec<Page>, Error> {
+        use crate::schema::pages::dsl::*;
+
+        let workspace_uuid_val = *workspace_uuid_val;
+        self.db
+            .run(move |conn| {
+                let result = pages
+                    .filter(workspace_uuid.eq(workspace_uuid_val))
+                    .filter(deleted_at.is_not_null())
+                    .order(deleted_at.desc())
+                    .load::<Page>(conn)?;
+
+                Ok(result)
+            })
+            .await
     }
 
     async fn create_page(&self, page: &Page) -> Result<(), Error> {
         use crate::schema::pages::dsl::*;
 
-        let mut conn = self.pool.get()?;
-        diesel::insert_into(pages).values(page).execute(&mut conn)?;
+        let page = page.clone();
+        self.db
+            .run(move |conn| {
+                diesel::insert_into(pages).values(page).execute(conn)?;
 
-        Ok(())
+                Ok(())
+            })
+            .await
     }
 
-    async fn update_page(&self, page: &Page) -> Result<(), Error> {
+    async fn update_page(&self, page: &Page) -> Result<Option<Page>, Error> {
         use crate::schema::pages::dsl::*;
 
-        let mut conn = self.pool.get()?;
-        diesel::update(pages.filter(uuid.eq(page.uuid)))
-            .set(page)
-            .execute(&mut conn)?;
+        let page = page.clone();
+        self.db
+            .run(move |conn| {
+                let result = diesel::update(pages.filter(uuid.eq(page.uuid)))
+                    .filter(version.eq(page.version))
+                    .set((
+                        title.eq(&page.title),
+                        image.eq(&page.image),
+                        version.eq(version + 1),
+                    ))
+                    .get_result::<Page>(conn)
+                    .optional()?;
 
-        Ok(())
+                Ok(result)
+            })
+            .await
     }
 
-    async fn delete_page(&self, uuid: &Uuid) -> Result<(), Error> {
+    async fn get_child_pages(
+        &self,
+        workspace_uuid: &Uuid,
+        parent_page_uuid: Option<&Uuid>,
+    ) -> Result<Vec<Page>, Error> {
+        let workspace_uuid = *workspace_uuid;
+        let parent_page_uuid = parent_page_uuid.copied();
+        self.db
+            .run(move |conn| {
+                let result =
+                    child_pages(&workspace_uuid, parent_page_uuid.as_ref()).load::<Page>(conn)?;
+
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn get_ancestors(&self, uuids: &[Uuid]) -> Result<HashMap<Uuid, Vec<Page>>, Error> {
+        let uuids = uuids.to_vec();
+        self.db
+            .run(move |conn| {
+                // Walks up from every page at once, numbering the ancestors by their
+                // distance to the page.
+                let rows = sql_query(
+                    "WITH RECURSIVE chain (descendant_uuid, uuid, distance) AS ( \
+                         SELECT uuid, parent_page_uuid, 1 FROM pages \
+                         WHERE uuid = ANY($1) AND parent_page_uuid IS NOT NULL \
+                         UNION ALL \
+                         SELECT chain.descendant_uuid, pages.parent_page_uuid, chain.distance + 1 \
+                         FROM chain JOIN pages ON pages.uuid = chain.uuid \
+                         WHERE pages.parent_page_uuid IS NOT NULL \
+                     ) SELECT chain.descendant_uuid, pages.* FROM chain \
+                     JOIN pages ON pages.uuid = chain.uuid \
+                     ORDER BY chain.descendant_uuid, chain.distance DESC",
+                )
+                .bind::<Array<SqlUuid>, _>(&uuids)
+                .load::<Ancestor>(conn)?;
+
+                let mut result = uuids
+                    .iter()
+                    .map(|uuid| (*uuid, Vec::new()))
+                    .collect::<HashMap<_, _>>();
+                for row in rows {
+                    result
+                        .entry(row.descendant_uuid)
+                        .or_default()
+                        .push(row.page);
+                }
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn rebalance_child_pages(
+        &self,
+        workspace_uuid: &Uuid,
+        parent_page_uuid: Option<&Uuid>,
+    ) -> Result<Vec<Page>, Error> {
+        let workspace_uuid = *workspace_uuid;
+        let parent_page_uuid = parent_page_uuid.copied();
+        self.db
+            .run(move |conn| {
+                let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    lock_page_tree(conn, &workspace_uuid)?;
+                    let mut result = child_pages(&workspace_uuid, parent_page_uuid.as_ref())
+                        .load::<Page>(conn)?;
+                    let keys = fractional_index::evenly_spaced(result.len());
+                    for (page, key) in result.iter_mut().zip(keys) {
+                        page.order = key;
+                        diesel::update(pages::table.filter(pages::uuid.eq(&page.uuid)))
+                            .set(pages::order.eq(&page.order))
+                            .execute(conn)?;
+                    }
+                    Ok(result)
+                })?;
+
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn move_page(&self, page: &Page) -> Result<PageMove, Error> {
+        let page = page.clone();
+        self.db
+            .run(move |conn| {
+                let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    lock_page_tree(conn, &page.workspace_uuid)?;
+                    if let Some(parent) = page.parent_page_uuid {
+                        if with_descendants(conn, &[page.uuid])?.contains(&parent) {
+                            return Ok(PageMove::Cycle);
+                        }
+                    }
+                    let moved = diesel::update(pages::table.filter(pages::uuid.eq(&page.uuid)))
+                        .filter(pages::version.eq(page.version))
+                        .filter(pages::deleted_at.is_null())
+                        .set((
+                            pages::parent_page_uuid.eq(&page.parent_page_uuid),
+                            pages::order.eq(&page.order),
+                            pages::version.eq(pages::version + 1),
+                        ))
+                        .get_result::<Page>(conn)
+                        .optional()?;
+                    Ok(moved.map_or(PageMove::Outdated, PageMove::Moved))
+                })?;
+
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn trash_page(&self, uuid_val: &Uuid) -> Result<Vec<Page>, Error> {
+        use crate::schema::pages::dsl::*;
+
+        let uuid_val = *uuid_val;
+        self.db
+            .run(move |conn| {
+                let mut result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    let subtree = subtree_deleted_at(conn, &uuid_val, None)?;
+                    diesel::update(pages.filter(uuid.eq_any(&subtree)))
+                        .set(deleted_at.eq(Utc::now().naive_utc()))
+                        .get_results::<Page>(conn)
+                })?;
+                result.sort_by_key(|page| page.uuid != uuid_val);
+
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn restore_page(&self, uuid_val: &Uuid) -> Result<Vec<Page>, Error> {
+        use crate::schema::pages::dsl::*;
+
+        let uuid_val = *uuid_val;
+        self.db
+            .run(move |conn| {
+                let mut result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    let page = match pages
+                        .filter(uuid.eq(uuid_val))
+                        .filter(deleted_at.is_not_null())
+                        .for_update()
+                        .first::<Page>(conn)
+                        .optional()?
+                    {
+                        Some(page) => page,
+                        None => return Ok(Vec::new()),
+                    };
+                    let subtree = subtree_deleted_at(conn, &uuid_val, page.deleted_at)?;
+                    let mut restored = diesel::update(pages.filter(uuid.eq_any(&subtree)))
+                        .set(deleted_at.eq(None::<NaiveDateTime>))
+                        .get_results::<Page>(conn)?;
+
+                    // The page's parent may have been moved to the trash on its own.
+                    if let Some(parent) = page.parent_page_uuid {
+                        let parent_trashed = pages
+                            .filter(uuid.eq(parent))
+                            .select(deleted_at.is_not_null())
+                            .first::<bool>(conn)?;
+                        if parent_trashed {
+                            let root = diesel::update(pages.filter(uuid.eq(uuid_val)))
+                                .set(parent_page_uuid.eq(None::<Uuid>))
+                                .get_result::<Page>(conn)?;
+                            restored.retain(|page| page.uuid != uuid_val);
+                            restored.push(root);
+                        }
+                    }
+                    Ok(restored)
+                })?;
+                result.sort_by_key(|page| page.uuid != uuid_val);
+
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn purge_page(&self, uuid_val: &Uuid) -> Result<Vec<String>, Error> {
+        let uuid_val = *uuid_val;
+        self.db
+            .run(move |conn| {
+                let images = conn.transaction(|conn| purge_pages(conn, &[uuid_val]))?;
+
+                Ok(images)
+            })
+            .await
+    }
+
+    async fn purge_trashed_pages(
+        &self,
+        deleted_before: NaiveDateTime,
+    ) -> Result<Vec<String>, Error> {
         use crate::schema::pages::dsl::*;
 
-        let mut conn = self.pool.get()?;
-        diesel::delete(pages.filter(uuid.eq(uuid))).execute(&mut conn)?;
+        self.db
+            .run(move |conn| {
+                let images = conn.transaction(|conn| {
+                    let expired = pages
+                        .filter(deleted_at.lt(deleted_before))
+                        .select(uuid)
+                        .load::<Uuid>(conn)?;
+                    purge_pages(conn, &expired)
+                })?;
+
+                Ok(images)
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repos::{
+        test_db::{create_tree, test_db},
+        traits::PageRepo as _,
+    };
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database, see TEST_DATABASE_URL"]
+    async fn test_page_tree() {
+        let db = test_db();
+        let tree = create_tree(&db).await;
+        let repo = PageRepo::new(db);
+        let ws = tree.workspace.uuid;
+        let child = Page::new(
+            ws,
+            Some(tree.page.uuid),
+            "V".to_string(),
+            "Child".to_string(),
+            None,
+        );
+        repo.create_page(&child).await.unwrap();
+        let grandchild = Page::new(
+            ws,
+            Some(child.uuid),
+            "V".to_string(),
+            "Grandchild".to_string(),
+            None,
+        );
+        repo.create_page(&grandchild).await.unwrap();
+
+        let cycle = Page {
+            parent_page_uuid: Some(grandchild.uuid),
+            ..tree.page.clone()
+        };
+        assert!(matches!(
+            repo.move_page(&cycle).await.unwrap(),
+            PageMove::Cycle
+        ));
+
+        // Trashing the child separately keeps it in the trash when the root
+        // page is restored.
+        assert_eq!(repo.trash_page(&child.uuid).await.unwrap().len(), 2);
+        let trashed = repo.trash_page(&tree.page.uuid).await.unwrap();
+        assert_eq!(trashed.len(), 1);
+        let restored = repo.restore_page(&tree.page.uuid).await.unwrap();
+        assert_eq!(restored.len(), 1);
+        assert!(repo.get_page_by_uuid(&child.uuid).await.unwrap().is_none());
+
+        // Restoring the grandchild on its own makes it a root page.
+        let trashed = repo.trash_page(&tree.page.uuid).await.unwrap();
+        assert_eq!(trashed.len(), 1);
+        let restored = repo.restore_page(&grandchild.uuid).await.unwrap();
+        assert_eq!(restored[0].parent_page_uuid, None);
+        let roots = repo.get_child_pages(&ws, None).await.unwrap();
+        assert_eq!(
+            roots.iter().map(|page| page.uuid).collect::<Vec<_>>(),
+            vec![grandchild.uuid]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database, see TEST_DATABASE_URL"]
+    async fn test_get_ancestors() {
+        let db = test_db();
+        let tree = create_tree(&db).await;
+        let repo = PageRepo::new(db);
+        let ws = tree.workspace.uuid;
+        let child = Page::new(
+            ws,
+            Some(tree.page.uuid),
+            "V".to_string(),
+            "Child".to_string(),
+            None,
+        );
+        repo.create_page(&child).await.unwrap();
+        let grandchild = Page::new(
+            ws,
+            Some(child.uuid),
+            "V".to_string(),
+            "Grandchild".to_string(),
+            None,
+        );
+        repo.create_page(&grandchild).await.unwrap();
 
-        Ok(())
+        let ancestors = repo
+            .get_ancestors(&[tree.page.uuid, grandchild.uuid])
+            .await
+            .unwrap();
+        let uuids = |uuid: &Uuid| {
+            ancestors[uuid]
+                .iter()
+                .map(|page| page.uuid)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(uuids(&tree.page.uuid), vec![]);
+        assert_eq!(uuids(&grandchild.uuid), vec![tree.page.uuid, child.uuid]);
     }
 }
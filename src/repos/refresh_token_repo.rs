@@ -0,0 +1,82 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use super::{database::Database, traits::RefreshTokenRepo};
+use crate::models::RefreshToken;
+
+pub struct PostgresqlRefreshTokenRepo {
+    db: Database,
+}
+
+impl PostgresqlRefreshTokenRepo {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl RefreshTokenRepo for PostgresqlRefreshTokenRepo {
+    async fn get_refresh_token_by_hash(&self, hash: &str) -> Result<Option<RefreshToken>> {
+        use crate::schema::refresh_tokens::dsl::*;
+
+        let hash = hash.to_string();
+        self.db
+            .run(move |conn| {
+                let result = refresh_tokens
+                    .filter(token_hash.eq(hash))
+                    .first::<RefreshToken>(conn)
+                    .optional()?;
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn create_refresh_token(&self, refresh_token: &RefreshToken) -> Result<()> {
+        use crate::schema::refresh_tokens::dsl::*;
+
+        let refresh_token = refresh_token.clone();
+        self.db
+            .run(move |conn| {
+                diesel::insert_into(refresh_tokens)
+                    .values(refresh_token)
+                    .execute(conn)?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn revoke_refresh_token(&self, uuid_val: &Uuid) -> Result<bool> {
+        use crate::schema::refresh_tokens::dsl::*;
+
+        let uuid_val = *uuid_val;
+        self.db
+            .run(move |conn| {
+                let updated = diesel::update(refresh_tokens)
+                    .filter(uuid.eq(uuid_val))
+                    .filter(revoked_at.is_null())
+                    .set(revoked_at.eq(Utc::now().naive_utc()))
+                    .execute(conn)?;
+                Ok(updated == 1)
+            })
+            .await
+    }
+
+    async fn revoke_refresh_token_family(&self, family_uuid_val: &Uuid) -> Result<()> {
+        use crate::schema::refresh_tokens::dsl::*;
+
+        let family_uuid_val = *family_uuid_val;
+        self.db
+            .run(move |conn| {
+                diesel::update(refresh_tokens)
+                    .filter(family_uuid.eq(family_uuid_val))
+                    .filter(revoked_at.is_null())
+                    .set(revoked_at.eq(Utc::now().naive_utc()))
+                    .execute(conn)?;
+                Ok(())
+            })
+            .await
+    }
+}
@@ -0,0 +1,377 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use super::{database::Database, traits::RevisionRepo};
+use crate::models::{
+    page_revision::{AtomSnapshot, PageSnapshot, SlotSnapshot},
+    Atom, Page, PageRevision, Slot,
+};
+
+pub struct PostgresqlRevisionRepo {
+    db: Database,
+}
+
+impl PostgresqlRevisionRepo {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+/// Snapshots the content of `page`.
+pub(super) fn snapshot_page(conn: &mut PgConnection, page: &Page) -> QueryResult<PageSnapshot> {
+    use crate::schema::{atoms, slots};
+
+    let page_slots = slots::table
+        .filter(slots::page_uuid.eq(&page.uuid))
+        .order((slots::order.asc(), slots::uuid.asc()))
+        .load::<Slot>(conn)?;
+    let slot_uuids = page_slots.iter().map(|slot| slot.uuid).collect::<Vec<_>>();
+    let mut slot_atoms = HashMap::<Uuid, Vec<AtomSnapshot>>::new();
+    for atom in atoms::table
+        .filter(atoms::slot_uuid.eq_any(&slot_uuids))
+        .order((atoms::slot_uuid.asc(), atoms::idx.asc()))
+        .load::<Atom>(conn)?
+    {
+        slot_atoms
+            .entry(atom.slot_uuid)
+            .or_default()
+            .push(AtomSnapshot {
+                uuid: atom.uuid,
+                typ: atom.typ,
+                data: atom.data,
+            });
+    }
+    Ok(PageSnapshot {
+        title: page.title.clone(),
+        image: page.image.clone(),
+        slots: page_slots
+            .into_iter()
+            .map(|slot| SlotSnapshot {
+                atoms: slot_atoms.remove(&slot.uuid).unwrap_or_default(),
+                uuid: slot.uuid,
+                order: slot.order,
+            })
+            .collect(),
+    })
+}
+
+#[async_trait]
+impl RevisionRepo for PostgresqlRevisionRepo {
+    async fn record_revision(
+        &self,
+        page_uuid_val: &Uuid,
+        user_uuid_val: Option<&Uuid>,
+        coalesce: Option<Duration>,
+    ) -> Result<Option<PageRevision>> {
+        use crate::schema::{page_revisions, pages};
+
+        let page_uuid_val = *page_uuid_val;
+        let user_uuid_val = user_uuid_val.copied();
+        self.db
+            .run(move |conn| {
+                let result = conn.transaction::<_, anyhow::Error, _>(|conn| {
+                    // Locking the page orders concurrent revisions of the same page.
+                    let page = match pages::table
+                        .filter(pages::uuid.eq(page_uuid_val))
+                        .for_update()
+                        .first::<Page>(conn)
+                        .optional()?
+                    {
+                        Some(page) => page,
+                        None => return Ok(None),
+                    };
+                    let snapshot = serde_json::to_string(&snapshot_page(conn, &page)?)?;
+                    let latest = page_revisions::table
+                        .filter(page_revisions::page_uuid.eq(page_uuid_val))
+                        .order(page_revisions::number.desc())
+                        .first::<PageRevision>(conn)
+                        .optional()?;
+                    let now = Utc::now().naive_utc();
+                    match latest {
+                        Some(latest) if latest.snapshot == snapshot => Ok(None),
+                        Some(latest)
+                            if latest.user_uuid == user_uuid_val
+                                && coalesce
+                                    .is_some_and(|window| latest.created_at + window > now) =>
+                        {
+                            let updated = diesel::update(page_revisions::table)
+                                .filter(page_revisions::page_uuid.eq(page_uuid_val))
+                                .filter(page_revisions::number.eq(latest.number))
+                                .set((
+                                    page_revisions::snapshot.eq(&snapshot),
+                                    page_revisions::updated_at.eq(now),
+                                ))
+                                .get_result::<PageRevision>(conn)?;
+                            Ok(Some(updated))
+                        }
+                        latest => {
+                            let revision = PageRevision {
+                                page_uuid: page_uuid_val,
+                                number: latest.map_or(1, |latest| latest.number + 1),
+                                user_uuid: user_uuid_val,
+                                created_at: now,
+                                updated_at: now,
+                                snapshot,
+                            };
+                            diesel::insert_into(page_revisions::table)
+                                .values(&revision)
+                                .execute(conn)?;
+                            Ok(Some(revision))
+                        }
+                    }
+                })?;
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn get_page_revisions(
+        &self,
+        page_uuid_val: &Uuid,
+        newer_than: Option<i32>,
+        older_than: Option<i32>,
+        limit: i64,
+        oldest_first: bool,
+    ) -> Result<Vec<PageRevision>> {
+        use crate::schema::page_revisions::dsl::*;
+
+        let page_uuid_val = *page_uuid_val;
+        self.db
+            .run(move |conn| {
+                let mut query = page_revisions
+                    .filter(page_uuid.eq(page_uuid_val))
+                    .limit(limit)
+                    .into_boxed();
+                if let Some(newer_than) = newer_than {
+                    query = query.filter(number.gt(newer_than));
+                }
+                if let Some(older_than) = older_than {
+                    query = query.filter(number.lt(older_than));
+                }
+                query = if oldest_first {
+                    query.order(number.asc())
+                } else {
+                    query.order(number.desc())
+                };
+                Ok(query.load::<PageRevision>(conn)?)
+            })
+            .await
+    }
+
+    async fn get_revision(
+        &self,
+        page_uuid_val: &Uuid,
+        number_val: i32,
+    ) -> Result<Option<PageRevision>> {
+        use crate::schema::page_revisions::dsl::*;
+
+        let page_uuid_val = *page_uuid_val;
+        self.db
+            .run(move |conn| {
+                let result = page_revisions
+                    .filter(page_uuid.eq(page_uuid_val))
+                    .filter(number.eq(number_val))
+                    .first::<PageRevision>(conn)
+                    .optional()?;
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn restore_snapshot(
+        &self,
+        page_uuid_val: &Uuid,
+        snapshot: &PageSnapshot,
+    ) -> Result<Option<Page>> {
+        use crate::schema::{atoms, pages, slots};
+
+        let page_uuid_val = *page_uuid_val;
+        let snapshot = snapshot.clone();
+        self.db
+            .run(move |conn| {
+                let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    let page =
+                        match diesel::update(pages::table.filter(pages::uuid.eq(page_uuid_val)))
+                            .set((
+                                pages::title.eq(&snapshot.title),
+                                pages::image.eq(&snapshot.image),
+                                pages::version.eq(pages::version + 1),
+                            ))
+                            .get_result::<Page>(conn)
+                            .optional()?
+                        {
+                            Some(page) => page,
+                            None => return Ok(None),
+                        };
+
+                    // Slots and atoms that still exist keep counting their versions,
+                    // so edits based on their current content are rejected.
+                    let page_slots = slots::table
+                        .filter(slots::page_uuid.eq(page_uuid_val))
+                        .select(slots::uuid);
+                    let mut versions = slots::table
+                        .filter(slots::page_uuid.eq(page_uuid_val))
+                        .select((slots::uuid, slots::version))
+                        .load::<(Uuid, i32)>(conn)?
+                        .into_iter()
+                        .collect::<HashMap<_, _>>();
+                    versions.extend(
+                        atoms::table
+                            .filter(atoms::slot_uuid.eq_any(page_slots))
+                            .select((atoms::uuid, atoms::version))
+                            .load::<(Uuid, i32)>(conn)?,
+                    );
+                    let next_version =
+                        |uuid: &Uuid| versions.get(uuid).map_or(0, |version| version + 1);
+
+                    // Atoms cascade.
+                    diesel::delete(slots::table.filter(slots::page_uuid.eq(page_uuid_val)))
+                        .execute(conn)?;
+                    let now = Utc::now().naive_utc();
+                    for slot in &snapshot.slots {
+                        diesel::insert_into(slots::table)
+                            .values(&Slot {
+                                page_uuid: page_uuid_val,
+                                uuid: slot.uuid,
+                                order: slot.order.clone(),
+                                version: next_version(&slot.uuid),
+                                updated_at: now,
+                            })
+                            .execute(conn)?;
+                        let slot_atoms = slot
+                            .atoms
+                            .iter()
+                            .enumerate()
+                            .map(|(idx, atom)| Atom {
+                                slot_uuid: slot.uuid,
+                                idx: idx as i32,
+                                typ: atom.typ,
+                                data: atom.data.clone(),
+                                uuid: atom.uuid,
+                                version: next_version(&atom.uuid),
+                                updated_at: now,
+                            })
+                            .collect::<Vec<_>>();
+                        diesel::insert_into(atoms::table)
+                            .values(&slot_atoms)
+                            .execute(conn)?;
+                    }
+                    Ok(Some(page))
+                })?;
+                Ok(result)
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::AtomType,
+        repos::{
+            atom_repo::PostgresqlAtomRepo,
+            slot_repo::PostgresqlSlotRepo,
+            test_db::{create_tree, test_db},
+            traits::{AtomRepo, SlotRepo},
+        },
+    };
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database, see TEST_DATABASE_URL"]
+    async fn test_record_revision() {
+        let db = test_db();
+        let tree = create_tree(&db).await;
+        let repo = PostgresqlRevisionRepo::new(db.clone());
+        let atom_repo = PostgresqlAtomRepo::new(db.clone());
+        let (page, user) = (&tree.page.uuid, Some(&tree.user.uuid));
+        let window = Some(Duration::minutes(5));
+
+        let first = repo.record_revision(page, user, window).await.unwrap();
+        assert_eq!(first.unwrap().number, 1);
+        assert!(repo
+            .record_revision(page, user, window)
+            .await
+            .unwrap()
+            .is_none());
+
+        // Changes by the same user within the window go into their revision.
+        atom_repo.delete_atom(&tree.slot.uuid, 1).await.unwrap();
+        let coalesced = repo
+            .record_revision(page, user, window)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(coalesced.number, 1);
+        let snapshot = coalesced.parse_snapshot().unwrap();
+        assert_eq!(snapshot.slots[0].atoms.len(), 1);
+
+        // Without a window, or by someone else, a new revision is started.
+        atom_repo.delete_atom(&tree.slot.uuid, 0).await.unwrap();
+        let next = repo.record_revision(page, user, None).await.unwrap();
+        assert_eq!(next.unwrap().number, 2);
+        let text = Some(r#"{"text":"text"}"#.to_string());
+        atom_repo
+            .insert_atom(&Atom::new(tree.slot.uuid, 0, AtomType::Text, text))
+            .await
+            .unwrap();
+        let other = repo
+            .record_revision(page, None, window)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((other.number, other.user_uuid), (3, None));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database, see TEST_DATABASE_URL"]
+    async fn test_restore_snapshot() {
+        let db = test_db();
+        let tree = create_tree(&db).await;
+        let repo = PostgresqlRevisionRepo::new(db.clone());
+        let slot_repo = PostgresqlSlotRepo::new(db.clone());
+        let atom_repo = PostgresqlAtomRepo::new(db.clone());
+        let revision = repo
+            .record_revision(&tree.page.uuid, None, None)
+            .await
+            .unwrap()
+            .unwrap();
+        let snapshot = revision.parse_snapshot().unwrap();
+
+        atom_repo.delete_atom(&tree.slot.uuid, 1).await.unwrap();
+        let extra = Slot::new(tree.page.uuid, "b".to_string());
+        slot_repo.create_slot(&extra).await.unwrap();
+
+        let page = repo
+            .restore_snapshot(&tree.page.uuid, &snapshot)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(page.version, tree.page.version + 1);
+        let slots = slot_repo.get_page_slots(&page.uuid).await.unwrap();
+        let slots = slots
+            .iter()
+            .map(|slot| (slot.uuid, slot.version))
+            .collect::<Vec<_>>();
+        assert_eq!(slots, [(tree.slot.uuid, tree.slot.version + 1)]);
+        // The deleted atom is back with its uuid, counting versions anew.
+        let atoms = atom_repo.get_slot_atoms(&tree.slot.uuid).await.unwrap();
+        let atoms = atoms
+            .iter()
+            .map(|atom| (atom.uuid, atom.version))
+            .collect::<Vec<_>>();
+        let restored = &snapshot.slots[0].atoms;
+        assert_eq!(atoms, [(restored[0].uuid, 1), (restored[1].uuid, 0)]);
+        // The content matches the revision again.
+        assert!(repo
+            .record_revision(&tree.page.uuid, None, None)
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
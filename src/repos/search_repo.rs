@@ -0,0 +1,145 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Float4, Nullable, Text, Uuid as SqlUuid};
+use uuid::Uuid;
+
+use super::{database::Database, traits::SearchRepo};
+use crate::models::search::{parse_snippet, SearchHit, HIGHLIGHT_END, HIGHLIGHT_START};
+
+/// The expressions match those of `pages_search_idx` and `atoms_search_idx`.
+/// Headings get a higher weight than other atoms and titles the highest.
+const SEARCH_QUERY: &str = "\
+    WITH query AS (SELECT websearch_to_tsquery('simple', $2) AS query), \
+    hits AS ( \
+        SELECT pages.uuid AS page_uuid, NULL::uuid AS slot_uuid, NULL::uuid AS atom_uuid, \
+            pages.title AS body, \
+            ts_rank(setweight(to_tsvector('simple', pages.title), 'A'), query.query) AS rank \
+        FROM pages, query \
+        WHERE pages.workspace_uuid = $1 AND pages.deleted_at IS NULL \
+            AND to_tsvector('simple', pages.title) @@ query.query \
+        UNION ALL \
+        SELECT pages.uuid, slots.uuid, atoms.uuid, atom_search_text(atoms.typ, atoms.data), \
+            ts_rank( \
+                setweight( \
+                    to_tsvector('simple', coalesce(atom_search_text(atoms.typ, atoms.data), '')), \
+                    CASE atoms.typ WHEN 'Heading' THEN 'B'::\"char\" ELSE 'D'::\"char\" END \
+                ), \
+                query.query \
+            ) \
+        FROM atoms \
+        JOIN slots ON slots.uuid = atoms.slot_uuid \
+        JOIN pages ON pages.uuid = slots.page_uuid, \
+        query \
+        WHERE pages.workspace_uuid = $1 AND pages.deleted_at IS NULL \
+            AND to_tsvector('simple', coalesce(atom_search_text(atoms.typ, atoms.data), '')) \
+                @@ query.query \
+    ) \
+    SELECT page_uuid, slot_uuid, atom_uuid, rank, ts_headline('simple', body, query.query, $5) AS snippet \
+    FROM hits, query \
+    ORDER BY rank DESC, page_uuid, atom_uuid NULLS FIRST \
+    OFFSET $3 LIMIT $4";
+
+#[derive(QueryableByName)]
+struct SearchRow {
+    #[diesel(sql_type = SqlUuid)]
+    page_uuid: Uuid,
+    #[diesel(sql_type = Nullable<SqlUuid>)]
+    slot_uuid: Option<Uuid>,
+    #[diesel(sql_type = Nullable<SqlUuid>)]
+    atom_uuid: Option<Uuid>,
+    #[diesel(sql_type = Float4)]
+    rank: f32,
+    #[diesel(sql_type = Text)]
+    snippet: String,
+}
+
+pub struct PostgresqlSearchRepo {
+    db: Database,
+}
+
+impl PostgresqlSearchRepo {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl SearchRepo for PostgresqlSearchRepo {
+    async fn search(
+        &self,
+        workspace_uuid: &Uuid,
+        query: &str,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<SearchHit>> {
+        let headline_options = format!(
+            "StartSel={}, StopSel={}, MaxFragments=1, MinWords=10, MaxWords=30",
+            HIGHLIGHT_START, HIGHLIGHT_END
+        );
+        let workspace_uuid = *workspace_uuid;
+        let query = query.to_string();
+        self.db
+            .run(move |conn| {
+                let rows = sql_query(SEARCH_QUERY)
+                    .bind::<SqlUuid, _>(workspace_uuid)
+                    .bind::<Text, _>(query)
+                    .bind::<BigInt, _>(offset)
+                    .bind::<BigInt, _>(limit)
+                    .bind::<Text, _>(headline_options)
+                    .load::<SearchRow>(conn)?;
+                Ok(rows
+                    .into_iter()
+                    .map(|row| {
+                        let (snippet, highlights) = parse_snippet(&row.snippet);
+                        SearchHit {
+                            page_uuid: row.page_uuid,
+                            slot_uuid: row.slot_uuid,
+                            atom_uuid: row.atom_uuid,
+                            rank: row.rank,
+                            snippet,
+                            highlights,
+                        }
+                    })
+                    .collect())
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::search::Highlight;
+    use crate::repos::{
+        page_repo::PageRepo,
+        test_db::{create_tree, test_db},
+        traits::PageRepo as _,
+    };
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database, see TEST_DATABASE_URL"]
+    async fn test_search() {
+        let db = test_db();
+        let tree = create_tree(&db).await;
+        let repo = PostgresqlSearchRepo::new(db.clone());
+        let ws = &tree.workspace.uuid;
+
+        let hits = repo.search(ws, "page or text", 0, 10).await.unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(
+            (hits[0].slot_uuid, hits[0].snippet.as_str()),
+            (None, "Page")
+        );
+        assert_eq!(hits[1].slot_uuid, Some(tree.slot.uuid));
+        assert_eq!(hits[1].highlights, vec![Highlight { start: 0, end: 4 }]);
+        assert_eq!(
+            repo.search(ws, "page or text", 1, 10).await.unwrap().len(),
+            1
+        );
+
+        PageRepo::new(db).trash_page(&tree.page.uuid).await.unwrap();
+        assert!(repo.search(ws, "text", 0, 10).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,161 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use super::{database::Database, traits::SlotRepo};
+use crate::{
+    models::{Atom, Slot},
+    utils::fractional_index,
+};
+
+pub struct PostgresqlSlotRepo {
+    db: Database,
+}
+
+impl PostgresqlSlotRepo {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl SlotRepo for PostgresqlSlotRepo {
+    async fn get_slot_by_uuid(&self, uuid_val: &Uuid) -> Result<Option<Slot>> {
+        use crate::schema::slots::dsl::*;
+
+        let uuid_val = *uuid_val;
+        self.db
+            .run(move |conn| {
+                let result = slots
+                    .filter(uuid.eq(uuid_val))
+                    .first::<Slot>(conn)
+                    .optional()?;
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn get_page_slots(&self, page_uuid_val: &Uuid) -> Result<Vec<Slot>> {
+        use crate::schema::slots::dsl::*;
+
+        let page_uuid_val = *page_uuid_val;
+        self.db
+            .run(move |conn| {
+                let result = slots
+                    .filter(page_uuid.eq(page_uuid_val))
+                    .order((order.asc(), uuid.asc()))
+                    .load::<Slot>(conn)?;
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn create_slot(&self, slot: &Slot) -> Result<()> {
+        use crate::schema::slots::dsl::*;
+
+        let slot = slot.clone();
+        self.db
+            .run(move |conn| {
+                diesel::insert_into(slots).values(slot).execute(conn)?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn update_slot(&self, slot: &Slot) -> Result<Option<Slot>> {
+        use crate::schema::slots::dsl::*;
+
+        let slot = slot.clone();
+        self.db
+            .run(move |conn| {
+                let result = diesel::update(slots)
+                    .filter(uuid.eq(&slot.uuid))
+                    .filter(version.eq(slot.version))
+                    .set((order.eq(&slot.order), version.eq(version + 1)))
+                    .get_result::<Slot>(conn)
+                    .optional()?;
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn rebalance_page_slots(&self, page_uuid_val: &Uuid) -> Result<Vec<Slot>> {
+        use crate::schema::slots::dsl::*;
+
+        let page_uuid_val = *page_uuid_val;
+        self.db
+            .run(move |conn| {
+                let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    let mut result = slots
+                        .filter(page_uuid.eq(page_uuid_val))
+                        .order((order.asc(), uuid.asc()))
+                        .for_update()
+                        .load::<Slot>(conn)?;
+                    let keys = fractional_index::evenly_spaced(result.len());
+                    for (slot, key) in result.iter_mut().zip(keys) {
+                        slot.order = key;
+                        diesel::update(slots)
+                            .filter(uuid.eq(&slot.uuid))
+                            .set(order.eq(&slot.order))
+                            .execute(conn)?;
+                    }
+                    Ok(result)
+                })?;
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn delete_slot(&self, uuid_val: &Uuid) -> Result<Vec<String>> {
+        use crate::schema::{atoms, slots};
+
+        let uuid_val = *uuid_val;
+        self.db
+            .run(move |conn| {
+                let images = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    let images = atoms::table
+                        .filter(atoms::slot_uuid.eq(uuid_val))
+                        .load::<Atom>(conn)?
+                        .iter()
+                        .filter_map(Atom::image_url)
+                        .collect();
+                    // Atoms cascade.
+                    diesel::delete(slots::table.filter(slots::uuid.eq(uuid_val))).execute(conn)?;
+                    Ok(images)
+                })?;
+                Ok(images)
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repos::{
+        atom_repo::PostgresqlAtomRepo,
+        test_db::{create_tree, test_db},
+        traits::AtomRepo,
+    };
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database, see TEST_DATABASE_URL"]
+    async fn test_delete_slot() {
+        let db = test_db();
+        let tree = create_tree(&db).await;
+        let repo = PostgresqlSlotRepo::new(db.clone());
+        let images = repo.delete_slot(&tree.slot.uuid).await.unwrap();
+        assert_eq!(images, vec!["https://s3/atom.png"]);
+        assert!(repo
+            .get_slot_by_uuid(&tree.slot.uuid)
+            .await
+            .unwrap()
+            .is_none());
+        let atoms = PostgresqlAtomRepo::new(db)
+            .get_slot_atoms(&tree.slot.uuid)
+            .await
+            .unwrap();
+        assert!(atoms.is_empty());
+    }
+}
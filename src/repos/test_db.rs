@@ -0,0 +1,114 @@
+//! Helpers for tests against a Postgres database.
+//!
+//! These tests are ignored by default. Point `TEST_DATABASE_URL` to a database
+//! with the migrations applied, e.g. by starting the app with `--migrate`, and
+//! run them with `cargo test -- --ignored`.
+
+use diesel::{
+    r2d2::{ConnectionManager, CustomizeConnection, Error, Pool},
+    Connection, PgConnection,
+};
+use secrecy::Secret;
+use uuid::Uuid;
+
+use crate::models::{Atom, AtomType, Page, Slot, User, Workspace};
+
+use super::{
+    atom_repo::PostgresqlAtomRepo,
+    database::Database,
+    page_repo::PageRepo as PostgresqlPageRepo,
+    slot_repo::PostgresqlSlotRepo,
+    traits::{AtomRepo, PageRepo, SlotRepo, UserRepo, WorkspaceRepo},
+    users_repo::PostgresqlUsersRepo,
+    workspace_repo::PostgresqlWorkspaceRepo,
+};
+
+#[derive(Debug)]
+struct TestTransaction;
+
+impl CustomizeConnection<PgConnection, Error> for TestTransaction {
+    fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), Error> {
+        conn.begin_test_transaction().map_err(Error::QueryError)
+    }
+}
+
+/// Returns a database with a single connection whose changes are rolled back
+/// when the database is dropped.
+pub fn test_db() -> Database {
+    let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set");
+    let pool = Pool::builder()
+        .max_size(1)
+        .connection_customizer(Box::new(TestTransaction))
+        .build(ConnectionManager::new(url))
+        .unwrap();
+    Database::new(pool)
+}
+
+pub fn image_atom(slot: &Slot, idx: i32, url: &str) -> Atom {
+    let data = format!(r#"{{"url":"{}"}}"#, url);
+    Atom::new(slot.uuid, idx, AtomType::Image, Some(data))
+}
+
+/// A workspace owned by `user` with a page holding a slot with an image atom
+/// and a text atom.
+pub struct Tree {
+    pub user: User,
+    pub workspace: Workspace,
+    pub page: Page,
+    pub slot: Slot,
+}
+
+pub async fn create_tree(db: &Database) -> Tree {
+    // Concurrent tests would wait on each other's unique emails and usernames.
+    let name = Uuid::new_v4().simple().to_string();
+    let user = User::new(
+        &format!("{}@example.com", name),
+        &name,
+        &Secret::new("password".to_string()),
+    );
+    PostgresqlUsersRepo::new(db.clone())
+        .create_user(&user)
+        .await
+        .unwrap();
+    let workspace = Workspace::new("Workspace", "https://s3/workspace.png");
+    PostgresqlWorkspaceRepo::new(db.clone())
+        .create_workspace(&workspace, &user.uuid)
+        .await
+        .unwrap();
+    let page = Page::new(
+        workspace.uuid,
+        None,
+        "V".to_string(),
+        "Page".to_string(),
+        Some("https://s3/page.png".to_string()),
+    );
+    PostgresqlPageRepo::new(db.clone())
+        .create_page(&page)
+        .await
+        .unwrap();
+    let slot = Slot::new(page.uuid, "a".to_string());
+    PostgresqlSlotRepo::new(db.clone())
+        .create_slot(&slot)
+        .await
+        .unwrap();
+    let atom_repo = PostgresqlAtomRepo::new(db.clone());
+    atom_repo
+        .insert_atom(&image_atom(&slot, 0, "https://s3/atom.png"))
+        .await
+        .unwrap();
+    atom_repo
+        .insert_atom(&Atom::new(
+            slot.uuid,
+            1,
+            AtomType::Text,
+            Some(r#"{"text":"text"}"#.to_string()),
+        ))
+        .await
+        .unwrap();
+    Tree {
+        user,
+        workspace,
+        page,
+        slot,
+    }
+}
@@ -1,8 +1,16 @@
+use std::collections::HashMap;
+
 use anyhow::Result;
 use async_trait::async_trait;
+use chrono::{Duration, NaiveDateTime};
 use uuid::Uuid;
 
-use crate::models::{Page, User, Workspace};
+use super::unit_of_work::PendingImages;
+use crate::models::{
+    page::PageMove, page_revision::PageSnapshot, search::SearchHit, text_ops::TextEdit,
+    workspace_member::MemberChange, Atom, Page, PageRevision, RefreshToken, Slot, User, Workspace,
+    WorkspaceMember, WorkspaceRole,
+};
 
 #[async_trait]
 pub trait UserRepo: Send + Sync {
@@ -12,22 +20,185 @@ pub trait UserRepo: Send + Sync {
     async fn get_user_by_login(&self, login: &str) -> Result<Option<User>>;
 }
 
+#[async_trait]
+pub trait RefreshTokenRepo: Send + Sync {
+    async fn get_refresh_token_by_hash(&self, token_hash: &str) -> Result<Option<RefreshToken>>;
+    async fn create_refresh_token(&self, refresh_token: &RefreshToken) -> Result<()>;
+    /// Marks a token as used. Returns `false` if it was already revoked.
+    async fn revoke_refresh_token(&self, uuid: &Uuid) -> Result<bool>;
+    async fn revoke_refresh_token_family(&self, family_uuid: &Uuid) -> Result<()>;
+}
+
 #[async_trait]
 pub trait WorkspaceRepo: Send + Sync {
-    async fn get_all_workspaces(&self) -> Result<Vec<Workspace>>;
+    /// Returns the workspaces of a user, except those in the trash.
+    async fn get_user_workspaces(&self, user_uuid: &Uuid) -> Result<Vec<Workspace>>;
+    /// Returns a workspace unless it's in the trash.
     async fn get_workspace_by_uuid(&self, uuid: &Uuid) -> Result<Option<Workspace>>;
-    async fn create_workspace(&self, workspace: &Workspace) -> Result<()>;
-    async fn update_workspace(&self, workspace: &Workspace) -> Result<()>;
-    async fn delete_workspace(&self, uuid: &Uuid) -> Result<()>;
+    /// Returns the trashed workspaces owned by a user.
+    async fn get_trashed_workspaces(&self, owner_uuid: &Uuid) -> Result<Vec<Workspace>>;
+    async fn create_workspace(&self, workspace: &Workspace, owner_uuid: &Uuid) -> Result<()>;
+    /// Updates a workspace unless its version changed since it was loaded.
+    /// Returns the updated workspace, or `None` on a conflict.
+    async fn update_workspace(&self, workspace: &Workspace) -> Result<Option<Workspace>>;
+    /// Moves a workspace to the trash. Returns `None` if it's already there.
+    async fn trash_workspace(&self, uuid: &Uuid) -> Result<Option<Workspace>>;
+    /// Takes a workspace out of the trash. Returns `None` if it isn't there.
+    async fn restore_workspace(&self, uuid: &Uuid) -> Result<Option<Workspace>>;
+    /// Permanently deletes a workspace with its members and pages, and returns
+    /// the urls of the images they referred to.
+    async fn purge_workspace(&self, uuid: &Uuid) -> Result<Vec<String>>;
+    /// Purges the workspaces moved to the trash before `deleted_before`.
+    async fn purge_trashed_workspaces(&self, deleted_before: NaiveDateTime) -> Result<Vec<String>>;
+    /// Returns the pages of a workspace, except those in the trash.
     async fn get_pages(&self, uuid: &Uuid) -> Result<Vec<Page>>;
+    /// Workspaces in the trash grant no roles.
+    async fn get_member_role(
+        &self,
+        workspace_uuid: &Uuid,
+        user_uuid: &Uuid,
+    ) -> Result<Option<WorkspaceRole>>;
+    async fn get_members(&self, workspace_uuid: &Uuid) -> Result<Vec<WorkspaceMember>>;
+    async fn add_member(&self, member: &WorkspaceMember) -> Result<()>;
+    /// Changes the role of a member, unless that leaves the workspace without
+    /// an owner.
+    async fn update_member(&self, member: &WorkspaceMember) -> Result<MemberChange>;
+    /// Removes a member, unless that leaves the workspace without an owner.
+    async fn remove_member(&self, workspace_uuid: &Uuid, user_uuid: &Uuid) -> Result<MemberChange>;
 }
 
 #[async_trait]
 pub trait PageRepo: Send + Sync {
+    /// Returns a page unless it's in the trash.
     async fn get_page_by_uuid(&self, uuid: &Uuid) -> Result<Option<Page>>;
+    async fn get_trashed_page(&self, uuid: &Uuid) -> Result<Option<Page>>;
+    /// Returns the trashed pages of a workspace, most recently deleted first.
+    async fn get_trashed_pages(&self, workspace_uuid: &Uuid) -> Result<Vec<Page>>;
     async fn create_page(&self, page: &Page) -> Result<()>;
-    async fn update_page(&self, page: &Page) -> Result<()>;
-    async fn delete_page(&self, uuid: &Uuid) -> Result<()>;
+    /// Updates a page unless its version changed since it was loaded. Returns
+    /// the updated page, or `None` on a conflict.
+    async fn update_page(&self, page: &Page) -> Result<Option<Page>>;
+    /// Returns the children of a page, or the root pages of a workspace if
+    /// `parent_page_uuid` is `None`, sorted by their order.
+    async fn get_child_pages(
+        &self,
+        workspace_uuid: &Uuid,
+        parent_page_uuid: Option<&Uuid>,
+    ) -> Result<Vec<Page>>;
+    /// Returns the ancestors of each page, from its root page down to its
+    /// parent. Root pages map to an empty list.
+    async fn get_ancestors(&self, uuids: &[Uuid]) -> Result<HashMap<Uuid, Vec<Page>>>;
+    /// Spreads the order keys of the children of a page, or the root pages of
+    /// a workspace, evenly. Returns the children in their order.
+    async fn rebalance_child_pages(
+        &self,
+        workspace_uuid: &Uuid,
+        parent_page_uuid: Option<&Uuid>,
+    ) -> Result<Vec<Page>>;
+    /// Sets a page's parent and order unless its version changed since it was
+    /// loaded or the new parent is nested under the page.
+    async fn move_page(&self, page: &Page) -> Result<PageMove>;
+    /// Moves a page and the pages nested under it to the trash. Returns the
+    /// page followed by the nested pages, or nothing if it's already there.
+    async fn trash_page(&self, uuid: &Uuid) -> Result<Vec<Page>>;
+    /// Takes a page out of the trash together with the pages trashed along
+    /// with it. The page becomes a root page if its parent is gone. Returns
+    /// the page followed by the nested pages, or nothing if it isn't there.
+    async fn restore_page(&self, uuid: &Uuid) -> Result<Vec<Page>>;
+    /// Permanently deletes a page with its content and revisions, and returns
+    /// the urls of the images they referred to.
+    async fn purge_page(&self, uuid: &Uuid) -> Result<Vec<String>>;
+    /// Purges the pages moved to the trash before `deleted_before`.
+    async fn purge_trashed_pages(&self, deleted_before: NaiveDateTime) -> Result<Vec<String>>;
+}
+
+#[async_trait]
+pub trait SlotRepo: Send + Sync {
+    async fn get_slot_by_uuid(&self, uuid: &Uuid) -> Result<Option<Slot>>;
+    /// Returns the slots of a page sorted by their order key.
+    async fn get_page_slots(&self, page_uuid: &Uuid) -> Result<Vec<Slot>>;
+    async fn create_slot(&self, slot: &Slot) -> Result<()>;
+    /// Updates a slot unless its version changed since it was loaded. Returns
+    /// the updated slot, or `None` on a conflict.
+    async fn update_slot(&self, slot: &Slot) -> Result<Option<Slot>>;
+    /// Spreads the order keys of a page's slots evenly, keeping their current
+    /// order, and returns the updated slots.
+    ///
+    /// This doesn't change the slots' positions, so their versions are kept.
+    async fn rebalance_page_slots(&self, page_uuid: &Uuid) -> Result<Vec<Slot>>;
+    /// Deletes a slot together with its atoms, and returns the urls of the
+    /// atoms' images.
+    async fn delete_slot(&self, uuid: &Uuid) -> Result<Vec<String>>;
+}
+
+#[async_trait]
+pub trait AtomRepo: Send + Sync {
+    async fn get_atom(&self, slot_uuid: &Uuid, idx: i32) -> Result<Option<Atom>>;
+    async fn get_atom_by_uuid(&self, uuid: &Uuid) -> Result<Option<Atom>>;
+    /// Returns the atoms of a slot sorted by their index.
+    async fn get_slot_atoms(&self, slot_uuid: &Uuid) -> Result<Vec<Atom>>;
+    /// Inserts an atom at its index, shifting the following atoms back.
+    async fn insert_atom(&self, atom: &Atom) -> Result<()>;
+    /// Updates an atom's type and data unless its version changed since it
+    /// was loaded. Returns the updated atom, or `None` on a conflict.
+    async fn update_atom(&self, atom: &Atom) -> Result<Option<Atom>>;
+    /// Returns the edits of a text atom made after `version`, oldest first.
+    /// Only the most recent edits are kept.
+    async fn get_text_edits(&self, atom_uuid: &Uuid, version: i32) -> Result<Vec<TextEdit>>;
+    /// Stores the data of a text atom together with the edit that produced it.
+    /// Returns `false` without storing anything if the atom's version is no
+    /// longer the one preceding the edit.
+    async fn apply_text_edit(&self, atom: &Atom, edit: &TextEdit) -> Result<bool>;
+    /// Moves the atom at `from` to `to`, shifting the atoms in between.
+    async fn move_atom(&self, slot_uuid: &Uuid, from: i32, to: i32) -> Result<()>;
+    /// Deletes an atom, shifting the following atoms forward.
+    async fn delete_atom(&self, slot_uuid: &Uuid, idx: i32) -> Result<()>;
+}
+
+#[async_trait]
+pub trait RevisionRepo: Send + Sync {
+    /// Snapshots the current content of a page. If the latest revision was
+    /// started by the same user less than `coalesce` ago, it's updated instead
+    /// of adding a new one. Returns `None` if the page is gone or unchanged
+    /// since the latest revision.
+    async fn record_revision(
+        &self,
+        page_uuid: &Uuid,
+        user_uuid: Option<&Uuid>,
+        coalesce: Option<Duration>,
+    ) -> Result<Option<PageRevision>>;
+    /// Returns up to `limit` revisions of a page numbered between
+    /// `newer_than` and `older_than`, newest first unless `oldest_first`.
+    async fn get_page_revisions(
+        &self,
+        page_uuid: &Uuid,
+        newer_than: Option<i32>,
+        older_than: Option<i32>,
+        limit: i64,
+        oldest_first: bool,
+    ) -> Result<Vec<PageRevision>>;
+    async fn get_revision(&self, page_uuid: &Uuid, number: i32) -> Result<Option<PageRevision>>;
+    /// Replaces the content of a page with a snapshot, keeping the uuids of
+    /// its slots and atoms. Returns the updated page, or `None` if it's gone.
+    async fn restore_snapshot(
+        &self,
+        page_uuid: &Uuid,
+        snapshot: &PageSnapshot,
+    ) -> Result<Option<Page>>;
+}
+
+#[async_trait]
+pub trait SearchRepo: Send + Sync {
+    /// Searches the titles and atoms of a workspace's pages outside the trash
+    /// with a web search style query. Returns hits `offset..offset + limit`,
+    /// best first.
+    async fn search(
+        &self,
+        workspace_uuid: &Uuid,
+        query: &str,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<SearchHit>>;
 }
 
 #[async_trait]
@@ -35,3 +206,27 @@ pub trait ImagesRepo: Send + Sync {
     async fn upload_image(&self, path: &str, image: &[u8]) -> Result<String>;
     async fn delete_image(&self, path: &str) -> Result<()>;
 }
+
+/// Starts units of work, see `UnitOfWork`.
+#[async_trait]
+pub trait UnitOfWorkRepo: Send + Sync {
+    /// Returns images to upload to before calling `begin`, so the uploads
+    /// don't hold on to a connection.
+    fn pending_images(&self) -> PendingImages;
+    /// Starts a unit of work that takes over `images`.
+    async fn begin(&self, images: PendingImages) -> Result<Box<dyn UnitOfWork>>;
+}
+
+/// Repositories sharing a single transaction. Their changes are rolled back
+/// unless `commit` is called.
+///
+/// Images uploaded through the `PendingImages` it was begun with are deleted
+/// again when the unit of work is rolled back, and images deleted through them
+/// are only deleted once it's committed.
+#[async_trait]
+pub trait UnitOfWork: Send + Sync {
+    fn workspace_repo(&self) -> &dyn WorkspaceRepo;
+    fn page_repo(&self) -> &dyn PageRepo;
+    async fn commit(self: Box<Self>) -> Result<()>;
+    async fn rollback(self: Box<Self>) -> Result<()>;
+}
@@ -0,0 +1,214 @@
+use std::{
+    mem,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{
+    database::Database,
+    page_repo::PageRepo,
+    traits::{self, ImagesRepo, UnitOfWork, UnitOfWorkRepo, WorkspaceRepo},
+    workspace_repo::PostgresqlWorkspaceRepo,
+};
+use crate::utils::trash::delete_images;
+
+/// Images uploaded and deleted in a unit of work. Uploads are deleted again on
+/// rollback, deletions are held back until commit.
+pub struct PendingImages {
+    images_repo: Arc<dyn ImagesRepo>,
+    uploaded: Mutex<Vec<String>>,
+    deleted: Mutex<Vec<String>>,
+}
+
+impl PendingImages {
+    pub fn new(images_repo: Arc<dyn ImagesRepo>) -> Self {
+        Self {
+            images_repo,
+            uploaded: Mutex::new(Vec::new()),
+            deleted: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub async fn commit(&self) {
+        self.uploaded.lock().unwrap().clear();
+        let deleted = mem::take(&mut *self.deleted.lock().unwrap());
+        delete_images(self.images_repo.as_ref(), &deleted).await;
+    }
+
+    pub async fn rollback(&self) {
+        self.deleted.lock().unwrap().clear();
+        let uploaded = mem::take(&mut *self.uploaded.lock().unwrap());
+        delete_images(self.images_repo.as_ref(), &uploaded).await;
+    }
+}
+
+impl Drop for PendingImages {
+    fn drop(&mut self) {
+        // Neither committed nor rolled back, e.g. because the request failed
+        // before the unit of work began or while the transaction was open.
+        let uploaded = mem::take(self.uploaded.get_mut().unwrap());
+        if !uploaded.is_empty() {
+            let images_repo = Arc::clone(&self.images_repo);
+            tokio::spawn(async move { delete_images(images_repo.as_ref(), &uploaded).await });
+        }
+    }
+}
+
+#[async_trait]
+impl ImagesRepo for PendingImages {
+    async fn upload_image(&self, path: &str, image: &[u8]) -> Result<String> {
+        let url = self.images_repo.upload_image(path, image).await?;
+        self.uploaded.lock().unwrap().push(url.clone());
+        Ok(url)
+    }
+
+    async fn delete_image(&self, path: &str) -> Result<()> {
+        self.deleted.lock().unwrap().push(path.to_string());
+        Ok(())
+    }
+}
+
+pub struct PostgresqlUnitOfWorkRepo {
+    db: Database,
+    images_repo: Arc<dyn ImagesRepo>,
+}
+
+impl PostgresqlUnitOfWorkRepo {
+    pub fn new(db: Database, images_repo: Arc<dyn ImagesRepo>) -> Self {
+        Self { db, images_repo }
+    }
+}
+
+#[async_trait]
+impl UnitOfWorkRepo for PostgresqlUnitOfWorkRepo {
+    fn pending_images(&self) -> PendingImages {
+        PendingImages::new(Arc::clone(&self.images_repo))
+    }
+
+    async fn begin(&self, images: PendingImages) -> Result<Box<dyn UnitOfWork>> {
+        let db = self.db.begin().await?;
+        Ok(Box::new(PostgresqlUnitOfWork {
+            workspace_repo: PostgresqlWorkspaceRepo::new(db.clone()),
+            page_repo: PageRepo::new(db.clone()),
+            images_repo: images,
+            db,
+        }))
+    }
+}
+
+pub struct PostgresqlUnitOfWork {
+    db: Database,
+    workspace_repo: PostgresqlWorkspaceRepo,
+    page_repo: PageRepo,
+    images_repo: PendingImages,
+}
+
+#[async_trait]
+impl UnitOfWork for PostgresqlUnitOfWork {
+    fn workspace_repo(&self) -> &dyn WorkspaceRepo {
+        &self.workspace_repo
+    }
+
+    fn page_repo(&self) -> &dyn traits::PageRepo {
+        &self.page_repo
+    }
+
+    async fn commit(self: Box<Self>) -> Result<()> {
+        self.db.commit().await?;
+        self.images_repo.commit().await;
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) -> Result<()> {
+        let result = self.db.rollback().await;
+        self.images_repo.rollback().await;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::Page,
+        repos::{
+            test_db::{create_tree, test_db, Tree},
+            traits::PageRepo as _,
+        },
+    };
+
+    #[derive(Default)]
+    struct FakeImagesRepo {
+        deleted: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl ImagesRepo for FakeImagesRepo {
+        async fn upload_image(&self, path: &str, _image: &[u8]) -> Result<String> {
+            Ok(format!("https://s3/{}", path))
+        }
+
+        async fn delete_image(&self, path: &str) -> Result<()> {
+            self.deleted.lock().unwrap().push(path.to_string());
+            Ok(())
+        }
+    }
+
+    /// Begins a unit of work replacing the image of the tree's page with a new
+    /// one in a child page.
+    async fn replace_image(
+        repo: &PostgresqlUnitOfWorkRepo,
+        tree: &Tree,
+    ) -> (Box<dyn UnitOfWork>, Page) {
+        let images = repo.pending_images();
+        let image = images.upload_image("new.png", &[]).await.unwrap();
+        images.delete_image("https://s3/page.png").await.unwrap();
+        let unit_of_work = repo.begin(images).await.unwrap();
+        let page = Page::new(
+            tree.workspace.uuid,
+            Some(tree.page.uuid),
+            "V".to_string(),
+            "Child".to_string(),
+            Some(image),
+        );
+        unit_of_work.page_repo().create_page(&page).await.unwrap();
+        (unit_of_work, page)
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database, see TEST_DATABASE_URL"]
+    async fn test_unit_of_work() {
+        let db = test_db();
+        let tree = create_tree(&db).await;
+        let images_repo = Arc::new(FakeImagesRepo::default());
+        let repo = PostgresqlUnitOfWorkRepo::new(db.clone(), images_repo.clone());
+        let page_repo = PageRepo::new(db);
+
+        let (unit_of_work, page) = replace_image(&repo, &tree).await;
+        unit_of_work.rollback().await.unwrap();
+        assert!(page_repo
+            .get_page_by_uuid(&page.uuid)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            *images_repo.deleted.lock().unwrap(),
+            vec!["https://s3/new.png"]
+        );
+
+        images_repo.deleted.lock().unwrap().clear();
+        let (unit_of_work, page) = replace_image(&repo, &tree).await;
+        unit_of_work.commit().await.unwrap();
+        assert!(page_repo
+            .get_page_by_uuid(&page.uuid)
+            .await
+            .unwrap()
+            .is_some());
+        assert_eq!(
+            *images_repo.deleted.lock().unwrap(),
+            vec!["https://s3/page.png"]
+        );
+    }
+}
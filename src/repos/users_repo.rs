@@ -2,18 +2,17 @@ use ::uuid::Uuid as TUuid;
 use anyhow::Result;
 use async_trait::async_trait;
 use diesel::prelude::*;
-use diesel::r2d2::{ConnectionManager, Pool};
 
-use super::traits::UserRepo;
+use super::{database::Database, traits::UserRepo};
 use crate::models::user::User;
 
 pub struct PostgresqlUsersRepo {
-    pool: Pool<ConnectionManager<PgConnection>>,
+    db: Database,
 }
 
 impl PostgresqlUsersRepo {
-    pub fn new(pool: Pool<ConnectionManager<PgConnection>>) -> Self {
-        Self { pool }
+    pub fn new(db: Database) -> Self {
+        Self { db }
     }
 }
 
@@ -22,39 +21,55 @@ impl UserRepo for PostgresqlUsersRepo {
     async fn get_user_by_uuid(&self, uuid_val: &TUuid) -> Result<Option<User>> {
         use crate::schema::users::dsl::*;
 
-        let mut conn = self.pool.get()?;
-        let result = users
-            .filter(uuid.eq(uuid_val))
-            .first::<User>(&mut conn)
-            .optional()?;
-        Ok(result)
+        let uuid_val = *uuid_val;
+        self.db
+            .run(move |conn| {
+                let result = users
+                    .filter(uuid.eq(uuid_val))
+                    .first::<User>(conn)
+                    .optional()?;
+                Ok(result)
+            })
+            .await
     }
 
     async fn create_user(&self, user: &User) -> Result<()> {
         use crate::schema::users::dsl::*;
 
-        let mut conn = self.pool.get()?;
-        diesel::insert_into(users).values(user).execute(&mut conn)?;
-        Ok(())
+        let user = user.clone();
+        self.db
+            .run(move |conn| {
+                diesel::insert_into(users).values(user).execute(conn)?;
+                Ok(())
+            })
+            .await
     }
     async fn update_user(&self, user: &User) -> Result<()> {
         use crate::schema::users::dsl::*;
 
-        let mut conn = self.pool.get()?;
-        diesel::update(users)
-            .filter(uuid.eq(&user.uuid))
-            .set(user)
-            .execute(&mut conn)?;
-        Ok(())
+        let user = user.clone();
+        self.db
+            .run(move |conn| {
+                diesel::update(users)
+                    .filter(uuid.eq(&user.uuid))
+                    .set(&user)
+                    .execute(conn)?;
+                Ok(())
+            })
+            .await
     }
     async fn get_user_by_login(&self, login: &str) -> Result<Option<User>> {
         use crate::schema::users::dsl::*;
 
-        let mut conn = self.pool.get()?;
-        let result = users
-            .filter(email.eq(login).or(username.eq(login)))
-            .first::<User>(&mut conn)
-            .optional()?;
-        Ok(result)
+        let login = login.to_string();
+        self.db
+            .run(move |conn| {
+                let result = users
+                    .filter(email.eq(&login).or(username.eq(&login)))
+                    .first::<User>(conn)
+                    .optional()?;
+                Ok(result)
+            })
+            .await
     }
 }
@@ -1,83 +1,459 @@
 use anyhow::Error;
 use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
 use diesel::{
-    r2d2::ConnectionManager, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl,
+    Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, QueryResult,
     RunQueryDsl,
 };
-use r2d2::Pool;
 use uuid::Uuid;
 
-use crate::models::{Page, Workspace};
+use crate::models::{
+    workspace_member::MemberChange, Page, Workspace, WorkspaceMember, WorkspaceRole,
+};
 
-use super::traits::WorkspaceRepo;
+use super::{database::Database, page_repo::purge_pages, traits::WorkspaceRepo};
 
 pub struct PostgresqlWorkspaceRepo {
-    pool: Pool<ConnectionManager<PgConnection>>,
+    db: Database,
 }
 
 impl PostgresqlWorkspaceRepo {
-    pub fn new(pool: Pool<ConnectionManager<PgConnection>>) -> Self {
-        Self { pool }
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+/// Gives a member the role `new_role`, or removes them if it's `None`, unless
+/// that leaves the workspace without an owner. The workspace's members are
+/// locked first, so concurrent changes can't remove the last owners between
+/// them.
+fn change_member(
+    conn: &mut PgConnection,
+    workspace_uuid_val: &Uuid,
+    user_uuid_val: &Uuid,
+    new_role: Option<WorkspaceRole>,
+) -> QueryResult<MemberChange> {
+    use crate::schema::workspace_members::dsl::*;
+
+    let members = workspace_members
+        .filter(workspace_uuid.eq(workspace_uuid_val))
+        .for_update()
+        .load::<WorkspaceMember>(conn)?;
+    let member = match members.iter().find(|m| &m.user_uuid == user_uuid_val) {
+        Some(member) => member,
+        None => return Ok(MemberChange::NotFound),
+    };
+    let owners = members
+        .iter()
+        .filter(|m| m.role == WorkspaceRole::Owner)
+        .count();
+    if member.role == WorkspaceRole::Owner && new_role != Some(WorkspaceRole::Owner) && owners == 1
+    {
+        return Ok(MemberChange::LastOwner);
     }
+
+    let target = workspace_members
+        .filter(workspace_uuid.eq(workspace_uuid_val))
+        .filter(user_uuid.eq(user_uuid_val));
+    match new_role {
+        Some(new_role) => diesel::update(target)
+            .set(role.eq(new_role))
+            .execute(conn)?,
+        None => diesel::delete(target).execute(conn)?,
+    };
+    Ok(MemberChange::Changed)
+}
+
+/// Deletes workspaces together with their members and pages, and returns the
+/// urls of the images any of them referred to.
+fn purge_workspaces(conn: &mut PgConnection, uuids: &[Uuid]) -> QueryResult<Vec<String>> {
+    use crate::schema::{pages, workspaces};
+
+    let page_uuids = pages::table
+        .filter(pages::workspace_uuid.eq_any(uuids))
+        .select(pages::uuid)
+        .load::<Uuid>(conn)?;
+    let mut images = purge_pages(conn, &page_uuids)?;
+    images.extend(
+        workspaces::table
+            .filter(workspaces::uuid.eq_any(uuids))
+            .select(workspaces::image)
+            .load::<String>(conn)?,
+    );
+    // Members cascade.
+    diesel::delete(workspaces::table.filter(workspaces::uuid.eq_any(uuids))).execute(conn)?;
+    Ok(images)
 }
 
 #[async_trait]
 impl WorkspaceRepo for PostgresqlWorkspaceRepo {
-    async fn get_all_workspaces(&self) -> Result<Vec<Workspace>, Error> {
-        use crate::schema::workspaces::dsl::*;
+    async fn get_user_workspaces(&self, user_uuid_val: &Uuid) -> Result<Vec<Workspace>, Error> {
+        use crate::schema::{workspace_members, workspaces};
 
-        let mut conn = self.pool.get()?;
-        let result = workspaces.load::<Workspace>(&mut conn)?;
-        Ok(result)
+        let user_uuid_val = *user_uuid_val;
+        self.db
+            .run(move |conn| {
+                let result = workspaces::table
+                    .inner_join(workspace_members::table)
+                    .filter(workspace_members::user_uuid.eq(user_uuid_val))
+                    .filter(workspaces::deleted_at.is_null())
+                    .select(workspaces::all_columns)
+                    .load::<Workspace>(conn)?;
+                Ok(result)
+            })
+            .await
     }
 
     async fn get_workspace_by_uuid(&self, uuid_val: &Uuid) -> Result<Option<Workspace>, Error> {
         use crate::schema::workspaces::dsl::*;
 
-        let mut conn = self.pool.get()?;
-        let result = workspaces
-            .filter(uuid.eq(uuid_val))
-            .first::<Workspace>(&mut conn)
-            .optional()?;
-        Ok(result)
+        let uuid_val = *uuid_val;
+        self.db
+            .run(move |conn| {
+                let result = workspaces
+                    .filter(uuid.eq(uuid_val))
+                    .filter(deleted_at.is_null())
+                    .first::<Workspace>(conn)
+                    .optional()?;
+                Ok(result)
+            })
+            .await
     }
 
-    async fn create_workspace(&self, workspace: &Workspace) -> Result<(), Error> {
+    async fn get_trashed_workspaces(&self, owner_uuid: &Uuid) -> Result<Vec<Workspace>, Error> {
+        use crate::schema::{workspace_members, workspaces};
+
+        let owner_uuid = *owner_uuid;
+        self.db
+            .run(move |conn| {
+                let result = workspaces::table
+                    .inner_join(workspace_members::table)
+                    .filter(workspace_members::user_uuid.eq(owner_uuid))
+                    .filter(workspace_members::role.eq(WorkspaceRole::Owner))
+                    .filter(workspaces::deleted_at.is_not_null())
+                    .order(workspaces::deleted_at.desc())
+                    .select(workspaces::all_columns)
+                    .load::<Workspace>(conn)?;
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn create_workspace(
+        &self,
+        workspace: &Workspace,
+        owner_uuid: &Uuid,
+    ) -> Result<(), Error> {
+        use crate::schema::{workspace_members, workspaces};
+
+        let owner = WorkspaceMember::new(*owner_uuid, workspace.uuid, WorkspaceRole::Owner);
+        let workspace = workspace.clone();
+        self.db
+            .run(move |conn| {
+                conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    diesel::insert_into(workspaces::table)
+                        .values(workspace)
+                        .execute(conn)?;
+                    diesel::insert_into(workspace_members::table)
+                        .values(&owner)
+                        .execute(conn)?;
+                    Ok(())
+                })?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn update_workspace(&self, workspace: &Workspace) -> Result<Option<Workspace>, Error> {
         use crate::schema::workspaces::dsl::*;
 
-        let mut conn = self.pool.get()?;
-        diesel::insert_into(workspaces)
-            .values(workspace)
-            .execute(&mut conn)?;
-        Ok(())
+        let workspace = workspace.clone();
+        self.db
+            .run(move |conn| {
+                let result = diesel::update(workspaces)
+                    .filter(uuid.eq(&workspace.uuid))
+                    .filter(version.eq(workspace.version))
+                    .set((
+                        name.eq(&workspace.name),
+                        image.eq(&workspace.image),
+                        version.eq(version + 1),
+                    ))
+                    .get_result::<Workspace>(conn)
+                    .optional()?;
+                Ok(result)
+            })
+            .await
     }
 
-    async fn update_workspace(&self, workspace: &Workspace) -> Result<(), Error> {
+    async fn trash_workspace(&self, uuid_val: &Uuid) -> Result<Option<Workspace>, Error> {
         use crate::schema::workspaces::dsl::*;
 
-        let mut conn = self.pool.get()?;
-        diesel::update(workspaces)
-            .filter(uuid.eq(&workspace.uuid))
-            .set(workspace)
-            .execute(&mut conn)?;
-        Ok(())
+        let uuid_val = *uuid_val;
+        self.db
+            .run(move |conn| {
+                let result = diesel::update(workspaces.filter(uuid.eq(uuid_val)))
+                    .filter(deleted_at.is_null())
+                    .set(deleted_at.eq(Utc::now().naive_utc()))
+                    .get_result::<Workspace>(conn)
+                    .optional()?;
+                Ok(result)
+            })
+            .await
     }
 
-    async fn delete_workspace(&self, uuid_val: &Uuid) -> Result<(), Error> {
+    async fn restore_workspace(&self, uuid_val: &Uuid) -> Result<Option<Workspace>, Error> {
         use crate::schema::workspaces::dsl::*;
 
-        let mut conn = self.pool.get()?;
-        diesel::delete(workspaces.filter(uuid.eq(uuid_val))).execute(&mut conn)?;
-        Ok(())
+        let uuid_val = *uuid_val;
+        self.db
+            .run(move |conn| {
+                let result = diesel::update(workspaces.filter(uuid.eq(uuid_val)))
+                    .filter(deleted_at.is_not_null())
+                    .set(deleted_at.eq(None::<NaiveDateTime>))
+                    .get_result::<Workspace>(conn)
+                    .optional()?;
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn purge_workspace(&self, uuid_val: &Uuid) -> Result<Vec<String>, Error> {
+        let uuid_val = *uuid_val;
+        self.db
+            .run(move |conn| {
+                let images = conn.transaction(|conn| purge_workspaces(conn, &[uuid_val]))?;
+                Ok(images)
+            })
+            .await
+    }
+
+    async fn purge_trashed_workspaces(
+        &self,
+        deleted_before: NaiveDateTime,
+    ) -> Result<Vec<String>, Error> {
+        use crate::schema::workspaces::dsl::*;
+
+        self.db
+            .run(move |conn| {
+                let images = conn.transaction(|conn| {
+                    let expired = workspaces
+                        .filter(deleted_at.lt(deleted_before))
+                        .select(uuid)
+                        .load::<Uuid>(conn)?;
+                    purge_workspaces(conn, &expired)
+                })?;
+                Ok(images)
+            })
+            .await
     }
 
     async fn get_pages(&self, uuid_val: &Uuid) -> Result<Vec<Page>, Error> {
         use crate::schema::pages::dsl::*;
 
-        let mut conn = self.pool.get()?;
-        pages
-            .filter(workspace_uuid.eq(uuid_val))
-            .load::<Page>(&mut conn)
-            .map_err(|e| e.into())
+        let uuid_val = *uuid_val;
+        self.db
+            .run(move |conn| {
+                pages
+                    .filter(workspace_uuid.eq(uuid_val))
+                    .filter(deleted_at.is_null())
+                    .load::<Page>(conn)
+                    .map_err(|e| e.into())
+            })
+            .await
+    }
+
+    async fn get_member_role(
+        &self,
+        workspace_uuid_val: &Uuid,
+        user_uuid_val: &Uuid,
+    ) -> Result<Option<WorkspaceRole>, Error> {
+        use crate::schema::{workspace_members, workspaces};
+
+        let workspace_uuid_val = *workspace_uuid_val;
+        let user_uuid_val = *user_uuid_val;
+        self.db
+            .run(move |conn| {
+                let result = workspace_members::table
+                    .inner_join(workspaces::table)
+                    .filter(workspace_members::workspace_uuid.eq(workspace_uuid_val))
+                    .filter(workspace_members::user_uuid.eq(user_uuid_val))
+                    .filter(workspaces::deleted_at.is_null())
+                    .select(workspace_members::role)
+                    .first::<WorkspaceRole>(conn)
+                    .optional()?;
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn get_members(&self, workspace_uuid_val: &Uuid) -> Result<Vec<WorkspaceMember>, Error> {
+        use crate::schema::workspace_members::dsl::*;
+
+        let workspace_uuid_val = *workspace_uuid_val;
+        self.db
+            .run(move |conn| {
+                let result = workspace_members
+                    .filter(workspace_uuid.eq(workspace_uuid_val))
+                    .load::<WorkspaceMember>(conn)?;
+                Ok(result)
+            })
+            .await
+    }
+
+    async fn add_member(&self, member: &WorkspaceMember) -> Result<(), Error> {
+        use crate::schema::workspace_members::dsl::*;
+
+        let member = member.clone();
+        self.db
+            .run(move |conn| {
+                diesel::insert_into(workspace_members)
+                    .values(member)
+                    .execute(conn)?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn update_member(&self, member: &WorkspaceMember) -> Result<MemberChange, Error> {
+        let member = member.clone();
+        self.db
+            .run(move |conn| {
+                let change = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    change_member(
+                        conn,
+                        &member.workspace_uuid,
+                        &member.user_uuid,
+                        Some(member.role),
+                    )
+                })?;
+                Ok(change)
+            })
+            .await
+    }
+
+    async fn remove_member(
+        &self,
+        workspace_uuid_val: &Uuid,
+        user_uuid_val: &Uuid,
+    ) -> Result<MemberChange, Error> {
+        let workspace_uuid_val = *workspace_uuid_val;
+        let user_uuid_val = *user_uuid_val;
+        self.db
+            .run(move |conn| {
+                let change = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    change_member(conn, &workspace_uuid_val, &user_uuid_val, None)
+                })?;
+                Ok(change)
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::dsl::count_star;
+
+    use super::*;
+    use crate::repos::{
+        atom_repo::PostgresqlAtomRepo,
+        revision_repo::PostgresqlRevisionRepo,
+        test_db::{create_tree, image_atom, test_db},
+        traits::{AtomRepo, RevisionRepo},
+    };
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database, see TEST_DATABASE_URL"]
+    async fn test_purge_workspace() {
+        use crate::schema::{atoms, page_revisions, pages, slots, workspace_members};
+
+        let db = test_db();
+        let tree = create_tree(&db).await;
+        // An image only a revision still refers to.
+        let atom_repo = PostgresqlAtomRepo::new(db.clone());
+        let revision_repo = PostgresqlRevisionRepo::new(db.clone());
+        atom_repo
+            .insert_atom(&image_atom(&tree.slot, 2, "https://s3/old.png"))
+            .await
+            .unwrap();
+        revision_repo
+            .record_revision(&tree.page.uuid, None, None)
+            .await
+            .unwrap();
+        atom_repo.delete_atom(&tree.slot.uuid, 2).await.unwrap();
+
+        let repo = PostgresqlWorkspaceRepo::new(db.clone());
+        let images = repo.purge_workspace(&tree.workspace.uuid).await.unwrap();
+        assert_eq!(
+            images,
+            vec![
+                "https://s3/atom.png",
+                "https://s3/old.png",
+                "https://s3/page.png",
+                "https://s3/workspace.png",
+            ]
+        );
+
+        let (workspace_uuid, page_uuid, slot_uuid) =
+            (tree.workspace.uuid, tree.page.uuid, tree.slot.uuid);
+        let counts = db
+            .run(move |conn| {
+                Ok((
+                    workspace_members::table
+                        .filter(workspace_members::workspace_uuid.eq(workspace_uuid))
+                        .select(count_star())
+                        .first::<i64>(conn)?,
+                    pages::table
+                        .filter(pages::uuid.eq(page_uuid))
+                        .select(count_star())
+                        .first::<i64>(conn)?,
+                    page_revisions::table
+                        .filter(page_revisions::page_uuid.eq(page_uuid))
+                        .select(count_star())
+                        .first::<i64>(conn)?,
+                    slots::table
+                        .filter(slots::uuid.eq(slot_uuid))
+                        .select(count_star())
+                        .first::<i64>(conn)?,
+                    atoms::table
+                        .filter(atoms::slot_uuid.eq(slot_uuid))
+                        .select(count_star())
+                        .first::<i64>(conn)?,
+                ))
+            })
+            .await
+            .unwrap();
+        assert_eq!(counts, (0, 0, 0, 0, 0));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database, see TEST_DATABASE_URL"]
+    async fn test_change_member() {
+        let db = test_db();
+        let tree = create_tree(&db).await;
+        let repo = PostgresqlWorkspaceRepo::new(db.clone());
+        let owner = repo.get_members(&tree.workspace.uuid).await.unwrap()[0].clone();
+        let mut demoted = owner.clone();
+        demoted.role = WorkspaceRole::Editor;
+        assert_eq!(
+            repo.update_member(&demoted).await.unwrap(),
+            MemberChange::LastOwner
+        );
+        assert_eq!(
+            repo.remove_member(&owner.workspace_uuid, &owner.user_uuid)
+                .await
+                .unwrap(),
+            MemberChange::LastOwner
+        );
+        assert_eq!(
+            repo.remove_member(&owner.workspace_uuid, &Uuid::new_v4())
+                .await
+                .unwrap(),
+            MemberChange::NotFound
+        );
+        // Keeping the only owner an owner is fine.
+        assert_eq!(
+            repo.update_member(&owner).await.unwrap(),
+            MemberChange::Changed
+        );
     }
 }
@@ -0,0 +1,390 @@
+use std::sync::Arc;
+
+use async_graphql::{
+    ComplexObject, Context, ErrorExtensions, InputObject, MaybeUndefined, Object, OutputType,
+    Result, Upload,
+};
+use uuid::Uuid;
+
+use super::{
+    page::upload_page_image,
+    revision::record_slot_revision,
+    subscription::{publish_atom_change, publish_text_edit},
+};
+use crate::{
+    models::{
+        text_ops::{apply_ops, check_ops, len_before, rebase, TextEdit, TextOp, TextOpInput},
+        Atom, AtomPayload, AtomType, WorkspaceRole,
+    },
+    repos::traits::{AtomRepo, ImagesRepo, PageRepo, SlotRepo},
+    utils::{
+        events::ChangeKind,
+        guards::SlotRoleGuard,
+        types::{ConflictError, InputError, WithError},
+    },
+};
+
+/// How often a text edit is rebased onto concurrent edits before giving up.
+const MAX_EDIT_ATTEMPTS: usize = 5;
+
+fn atom_error<T: OutputType + Send + Sync>(field: &str, message: &str) -> WithError<T> {
+    WithError {
+        errors: vec![InputError {
+            field: field.to_string(),
+            message: message.to_string(),
+        }],
+        value: None,
+    }
+}
+
+fn atom_not_found() -> WithError<Atom> {
+    atom_error("idx", "Atom not found")
+}
+
+/// Validates the data of an atom of type `typ` in `slot_uuid` and returns it
+/// in its canonical form.
+///
+/// The url of an image atom comes from uploading `image`, or is kept from
+/// `current` when no new image is uploaded.
+async fn atom_data(
+    ctx: &Context<'_>,
+    slot_uuid: &Uuid,
+    typ: AtomType,
+    data: Option<&str>,
+    image: Option<Upload>,
+    current: Option<&Atom>,
+) -> Result<WithError<Option<String>>> {
+    let slot_repo = ctx.data_unchecked::<Arc<dyn SlotRepo>>();
+    let page_repo = ctx.data_unchecked::<Arc<dyn PageRepo>>();
+    let error = |field: &str, message: String| {
+        Ok(WithError {
+            errors: vec![InputError {
+                field: field.to_string(),
+                message,
+            }],
+            value: None,
+        })
+    };
+
+    let mut payload = match AtomPayload::from_json(typ, data) {
+        Ok(payload) => payload,
+        Err(err) => return error("data", err.to_string()),
+    };
+    let page = match slot_repo.get_slot_by_uuid(slot_uuid).await? {
+        Some(slot) => page_repo.get_page_by_uuid(&slot.page_uuid).await?,
+        None => None,
+    };
+    let page = page.ok_or("Slot not found")?;
+
+    match (&mut payload, image) {
+        (Some(AtomPayload::PageLink(link)), _) => {
+            let linked = page_repo.get_page_by_uuid(&link.page_uuid).await?;
+            if linked
+                .filter(|linked| linked.workspace_uuid == page.workspace_uuid)
+                .is_none()
+            {
+                return error("data", "Linked page not found".to_string());
+            }
+        }
+        (Some(AtomPayload::Image(payload)), Some(image)) => {
+            let images_repo = ctx.data_unchecked::<Arc<dyn ImagesRepo>>();
+            let image =
+                upload_page_image(ctx, images_repo.as_ref(), &page.workspace_uuid, image).await?;
+            if !image.errors.is_empty() {
+                return Ok(WithError {
+                    errors: image.errors,
+                    value: None,
+                });
+            }
+            payload.url = image.value.unwrap_or_default();
+        }
+        (Some(AtomPayload::Image(payload)), None) => {
+            payload.url = current.and_then(Atom::image_url).unwrap_or_default();
+        }
+        (_, Some(_)) => return error("image", "Only image atoms take an image".to_string()),
+        _ => {}
+    }
+
+    match payload {
+        Some(mut payload) => match payload.validate() {
+            Ok(()) => {
+                payload.normalize();
+                Ok(Some(payload.to_json()).into())
+            }
+            Err(err) => error("data", err.to_string()),
+        },
+        None => Ok(None.into()),
+    }
+}
+
+#[derive(InputObject)]
+pub struct InsertAtomInput {
+    pub slot_uuid: Uuid,
+    /// The position to insert the atom at. Defaults to the end of the slot.
+    pub idx: Option<i32>,
+    pub typ: AtomType,
+    /// The atom's JSON payload, which must match `typ`.
+    pub data: Option<String>,
+    /// The image of an image atom.
+    pub image: Option<Upload>,
+}
+
+#[derive(InputObject)]
+pub struct UpdateAtomInput {
+    pub slot_uuid: Uuid,
+    pub idx: i32,
+    /// The version of the atom the update is based on.
+    pub version: i32,
+    pub typ: Option<AtomType>,
+    /// The atom's JSON payload, which must match its type.
+    pub data: MaybeUndefined<String>,
+    /// Replaces the image of an image atom.
+    pub image: Option<Upload>,
+}
+
+#[derive(InputObject)]
+pub struct EditTextInput {
+    pub slot_uuid: Uuid,
+    pub atom_uuid: Uuid,
+    /// The version of the atom the ops were made against.
+    pub base_version: i32,
+    /// The ops to apply in order.
+    pub ops: Vec<TextOpInput>,
+}
+
+#[derive(Default)]
+pub struct AtomMutation;
+
+#[Object]
+impl AtomMutation {
+    #[graphql(guard = "SlotRoleGuard::new(atom.slot_uuid, WorkspaceRole::Editor)")]
+    pub async fn insert_atom(
+        &self,
+        ctx: &Context<'_>,
+        atom: InsertAtomInput,
+    ) -> Result<WithError<Atom>> {
+        let atom_repo = ctx.data_unchecked::<Arc<dyn AtomRepo>>();
+
+        let len = atom_repo.get_slot_atoms(&atom.slot_uuid).await?.len() as i32;
+        let idx = atom.idx.unwrap_or(len);
+        if !(0..=len).contains(&idx) {
+            return Ok(atom_error("idx", "Index out of range"));
+        }
+
+        let data = atom_data(
+            ctx,
+            &atom.slot_uuid,
+            atom.typ,
+            atom.data.as_deref(),
+            atom.image,
+            None,
+        )
+        .await?;
+        if !data.errors.is_empty() {
+            return Ok(WithError {
+                errors: data.errors,
+                value: None,
+            });
+        }
+
+        let atom = Atom::new(atom.slot_uuid, idx, atom.typ, data.value.flatten());
+        atom_repo.insert_atom(&atom).await?;
+        record_slot_revision(ctx, &atom.slot_uuid).await?;
+        publish_atom_change(ctx, &atom.slot_uuid, atom.idx, ChangeKind::AtomInserted).await;
+        Ok(atom.into())
+    }
+
+    #[graphql(guard = "SlotRoleGuard::new(atom.slot_uuid, WorkspaceRole::Editor)")]
+    pub async fn update_atom(
+        &self,
+        ctx: &Context<'_>,
+        atom: UpdateAtomInput,
+    ) -> Result<WithError<Atom>> {
+        let atom_repo = ctx.data_unchecked::<Arc<dyn AtomRepo>>();
+        let images_repo = ctx.data_unchecked::<Arc<dyn ImagesRepo>>();
+
+        let mut existing = match atom_repo.get_atom(&atom.slot_uuid, atom.idx).await? {
+            Some(existing) => existing,
+            None => return Ok(atom_not_found()),
+        };
+        if existing.version != atom.version {
+            let version = existing.version;
+            return Err(ConflictError {
+                kind: "atom",
+                version,
+            }
+            .extend());
+        }
+        let typ = atom.typ.unwrap_or(existing.typ);
+        let data = match atom.data {
+            MaybeUndefined::Value(data) => Some(data),
+            MaybeUndefined::Null => None,
+            MaybeUndefined::Undefined => existing.data.clone(),
+        };
+        let data = atom_data(
+            ctx,
+            &atom.slot_uuid,
+            typ,
+            data.as_deref(),
+            atom.image,
+            Some(&existing),
+        )
+        .await?;
+        if !data.errors.is_empty() {
+            return Ok(WithError {
+                errors: data.errors,
+                value: None,
+            });
+        }
+
+        let old_image = existing.image_url();
+        existing.typ = typ;
+        existing.data = data.value.flatten();
+        let new_image = existing
+            .image_url()
+            .filter(|new| Some(new) != old_image.as_ref());
+        let updated = match atom_repo.update_atom(&existing).await? {
+            Some(updated) => updated,
+            None => {
+                // Someone else changed the atom since it was loaded.
+                if let Some(new_image) = new_image {
+                    images_repo.delete_image(&new_image).await?;
+                }
+                return match atom_repo.get_atom_by_uuid(&existing.uuid).await? {
+                    Some(current) => {
+                        let version = current.version;
+                        Err(ConflictError {
+                            kind: "atom",
+                            version,
+                        }
+                        .extend())
+                    }
+                    None => Ok(atom_not_found()),
+                };
+            }
+        };
+        record_slot_revision(ctx, &updated.slot_uuid).await?;
+        publish_atom_change(
+            ctx,
+            &updated.slot_uuid,
+            updated.idx,
+            ChangeKind::AtomUpdated,
+        )
+        .await;
+        Ok(updated.into())
+    }
+
+    /// Edits the text of a text atom. Ops made against an older version are
+    /// rebased onto the edits made since, so concurrent edits are merged. The
+    /// edit is returned and published as applied.
+    #[graphql(guard = "SlotRoleGuard::new(edit.slot_uuid, WorkspaceRole::Editor)")]
+    pub async fn edit_text(
+        &self,
+        ctx: &Context<'_>,
+        edit: EditTextInput,
+    ) -> Result<WithError<TextEdit>> {
+        let atom_repo = ctx.data_unchecked::<Arc<dyn AtomRepo>>();
+
+        let ops = edit.ops.into_iter().map(TextOp::from).collect::<Vec<_>>();
+        for _ in 0..MAX_EDIT_ATTEMPTS {
+            let mut atom = match atom_repo.get_atom_by_uuid(&edit.atom_uuid).await? {
+                Some(atom) if atom.slot_uuid == edit.slot_uuid => atom,
+                _ => return Ok(atom_error("atomUuid", "Atom not found")),
+            };
+            let mut payload = match AtomPayload::parse(atom.typ, atom.data.as_deref())? {
+                Some(AtomPayload::Text(payload)) => payload,
+                _ => return Ok(atom_error("atomUuid", "Only text atoms can be edited")),
+            };
+            if !(0..=atom.version).contains(&edit.base_version) {
+                return Ok(atom_error("baseVersion", "Unknown version"));
+            }
+
+            let mut since = atom_repo
+                .get_text_edits(&atom.uuid, edit.base_version)
+                .await?;
+            // Edits stored after the atom was loaded are picked up on retry.
+            since.retain(|stored| stored.version <= atom.version);
+            let versions = since.iter().map(|stored| stored.version);
+            if !versions.eq(edit.base_version + 1..=atom.version) {
+                return Ok(atom_error(
+                    "baseVersion",
+                    "The atom has changed too much since this version",
+                ));
+            }
+            // Rebasing expects ops that fit the text they were made against.
+            let base_len = len_before(payload.text.chars().count(), &since);
+            if let Err(err) = check_ops(&ops, base_len) {
+                return Ok(atom_error("ops", &err.to_string()));
+            }
+            let ops = rebase(&ops, &since);
+            if let Err(err) = apply_ops(&mut payload, &ops) {
+                return Ok(atom_error("ops", &err.to_string()));
+            }
+
+            let text_edit = TextEdit {
+                atom_uuid: atom.uuid,
+                version: atom.version + 1,
+                ops,
+            };
+            atom.data = Some(AtomPayload::Text(payload).to_json());
+            if atom_repo.apply_text_edit(&atom, &text_edit).await? {
+                record_slot_revision(ctx, &atom.slot_uuid).await?;
+                publish_text_edit(ctx, &atom, text_edit.clone()).await;
+                return Ok(text_edit.into());
+            }
+        }
+        Err("Too many concurrent edits, please try again".into())
+    }
+
+    #[graphql(guard = "SlotRoleGuard::new(slot_uuid, WorkspaceRole::Editor)")]
+    pub async fn move_atom(
+        &self,
+        ctx: &Context<'_>,
+        slot_uuid: Uuid,
+        from: i32,
+        to: i32,
+    ) -> Result<WithError<Atom>> {
+        let atom_repo = ctx.data_unchecked::<Arc<dyn AtomRepo>>();
+
+        let mut atom = match atom_repo.get_atom(&slot_uuid, from).await? {
+            Some(atom) => atom,
+            None => return Ok(atom_not_found()),
+        };
+        let len = atom_repo.get_slot_atoms(&slot_uuid).await?.len() as i32;
+        if !(0..len).contains(&to) {
+            return Ok(atom_error("to", "Index out of range"));
+        }
+        atom_repo.move_atom(&slot_uuid, from, to).await?;
+        atom.idx = to;
+        record_slot_revision(ctx, &slot_uuid).await?;
+        publish_atom_change(ctx, &slot_uuid, to, ChangeKind::AtomMoved).await;
+        Ok(atom.into())
+    }
+
+    #[graphql(guard = "SlotRoleGuard::new(slot_uuid, WorkspaceRole::Editor)")]
+    pub async fn delete_atom(
+        &self,
+        ctx: &Context<'_>,
+        slot_uuid: Uuid,
+        idx: i32,
+    ) -> Result<WithError<Atom>> {
+        let atom_repo = ctx.data_unchecked::<Arc<dyn AtomRepo>>();
+
+        let atom = match atom_repo.get_atom(&slot_uuid, idx).await? {
+            Some(atom) => atom,
+            None => return Ok(atom_not_found()),
+        };
+        atom_repo.delete_atom(&slot_uuid, idx).await?;
+        record_slot_revision(ctx, &slot_uuid).await?;
+        publish_atom_change(ctx, &slot_uuid, idx, ChangeKind::AtomDeleted).await;
+        Ok(atom.into())
+    }
+}
+
+#[ComplexObject]
+impl Atom {
+    /// The atom's data, typed according to its type. Dividers have none.
+    pub async fn payload(&self) -> Result<Option<AtomPayload>> {
+        Ok(AtomPayload::parse(self.typ, self.data.as_deref())?)
+    }
+}
@@ -1,17 +1,85 @@
-use async_graphql::MergedObject;
+use std::sync::Arc;
+
+use async_graphql::{
+    dataloader::{DataLoader, HashMapCache},
+    MergedObject, Schema, SchemaBuilder,
+};
 
 use self::{
-    page::PageMutation,
+    atom::AtomMutation,
+    page::{AncestorsLoader, PageMutation, PageQuery},
+    revision::RevisionMutation,
+    search::SearchQuery,
+    slot::SlotMutation,
+    subscription::SubscriptionRoot,
     user::{UserMutation, UserQuery},
     workspace::{WorkspaceMutation, WorkspaceQuery},
 };
+use crate::{
+    repos::{traits::PageRepo, Repos},
+    utils::{config::Config, events::EventBroker, jwt::KeyStore},
+};
 
+pub mod atom;
 pub mod page;
+pub mod revision;
+pub mod search;
+pub mod slot;
+pub mod subscription;
+#[cfg(test)]
+mod tests;
 pub mod user;
 pub mod workspace;
 
 #[derive(MergedObject, Default)]
-pub struct QueryRoot(UserQuery, WorkspaceQuery);
+pub struct QueryRoot(UserQuery, WorkspaceQuery, PageQuery, SearchQuery);
 
 #[derive(MergedObject, Default)]
-pub struct MutationsRoot(UserMutation, WorkspaceMutation, PageMutation);
+pub struct MutationsRoot(
+    UserMutation,
+    WorkspaceMutation,
+    PageMutation,
+    SlotMutation,
+    AtomMutation,
+    RevisionMutation,
+);
+
+pub type AppSchema = Schema<QueryRoot, MutationsRoot, SubscriptionRoot>;
+
+/// Starts building the schema with the repositories, config, signing keys and
+/// event broker the resolvers take from the context.
+pub fn schema_builder(
+    repos: &Repos,
+    config: Arc<Config>,
+    keys: Arc<KeyStore>,
+    event_broker: Arc<dyn EventBroker>,
+) -> SchemaBuilder<QueryRoot, MutationsRoot, SubscriptionRoot> {
+    Schema::build(
+        QueryRoot::default(),
+        MutationsRoot::default(),
+        SubscriptionRoot,
+    )
+    .data(Arc::clone(&repos.user_repo))
+    .data(config)
+    .data(keys)
+    .data(Arc::clone(&repos.workspace_repo))
+    .data(Arc::clone(&repos.images_repo))
+    .data(Arc::clone(&repos.page_repo))
+    .data(Arc::clone(&repos.refresh_token_repo))
+    .data(Arc::clone(&repos.slot_repo))
+    .data(Arc::clone(&repos.atom_repo))
+    .data(Arc::clone(&repos.revision_repo))
+    .data(Arc::clone(&repos.search_repo))
+    .data(Arc::clone(&repos.unit_of_work_repo))
+    .data(event_broker)
+}
+
+/// Returns a new ancestors loader, to add to the data of a single request so
+/// its cache doesn't outlive the request.
+pub fn ancestors_loader(page_repo: Arc<dyn PageRepo>) -> DataLoader<AncestorsLoader, HashMapCache> {
+    DataLoader::with_cache(
+        AncestorsLoader::new(page_repo),
+        tokio::spawn,
+        HashMapCache::default(),
+    )
+}
@@ -1,15 +1,195 @@
-use std::{path::Path, sync::Arc};
+use std::{collections::HashMap, sync::Arc};
 
-use async_graphql::{Context, InputObject, Object, Result, Upload};
+use async_graphql::{
+    connection::{query, Connection, Edge},
+    dataloader::{DataLoader, HashMapCache, Loader},
+    ComplexObject, Context, ErrorExtensions, Guard, InputObject, Object, Result, Upload,
+};
 use std::io::Read;
 use uuid::Uuid;
 
+use super::{revision::record_revision, subscription::publish_page_change};
 use crate::{
-    models::Page,
-    repos::traits::{ImagesRepo, PageRepo},
-    utils::types::WithError,
+    models::{
+        page::PageMove,
+        page_revision::{PageRevision, RevisionDiff},
+        Page, Slot, WorkspaceRole,
+    },
+    repos::traits::{ImagesRepo, PageRepo, RevisionRepo, SlotRepo, UnitOfWorkRepo},
+    utils::{
+        events::ChangeKind,
+        fractional_index,
+        guards::{GuardError, PageRoleGuard, WorkspaceRoleGuard},
+        img::image_extension,
+        trash::delete_images,
+        types::{ConflictError, InputError, WithError},
+    },
 };
 
+/// Uploads a page image with `images_repo` and returns its url.
+pub(crate) async fn upload_page_image(
+    ctx: &Context<'_>,
+    images_repo: &dyn ImagesRepo,
+    workspace_uuid: &Uuid,
+    image: Upload,
+) -> Result<WithError<String>> {
+    let mut image = image.value(ctx)?;
+    let buf = &mut Vec::new();
+    image.content.read_to_end(buf)?;
+    let image_extension = match image_extension(&image.filename, buf) {
+        Some(ext) => ext,
+        None => {
+            return Ok(WithError {
+                errors: vec![InputError {
+                    field: "image".to_string(),
+                    message: "Only png, jpeg, gif and webp images are accepted".to_string(),
+                }],
+                value: None,
+            })
+        }
+    };
+    let image_name = format!(
+        "images/{}/{}.{}",
+        workspace_uuid,
+        Uuid::new_v4(),
+        image_extension
+    );
+    let image = images_repo.upload_image(&image_name, buf).await?;
+    Ok(image.into())
+}
+
+/// Loads the ancestors of pages, see `PageRepo::get_ancestors`. Added to each
+/// HTTP request by `ancestors_loader`, so ancestor chains are cached for the
+/// length of the request.
+pub struct AncestorsLoader {
+    page_repo: Arc<dyn PageRepo>,
+}
+
+impl AncestorsLoader {
+    pub fn new(page_repo: Arc<dyn PageRepo>) -> Self {
+        Self { page_repo }
+    }
+}
+
+#[async_trait::async_trait]
+impl Loader<Uuid> for AncestorsLoader {
+    type Value = Vec<Page>;
+    type Error = async_graphql::Error;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>> {
+        Ok(self.page_repo.get_ancestors(keys).await?)
+    }
+}
+
+/// Number of revisions returned when neither `first` nor `last` is given.
+const DEFAULT_REVISIONS: usize = 20;
+/// Maximum number of revisions returned at once.
+const MAX_REVISIONS: usize = 100;
+
+fn validate_title(title: &str) -> Option<InputError> {
+    if title.trim().is_empty() {
+        Some(InputError {
+            field: "title".to_string(),
+            message: "Title is required".to_string(),
+        })
+    } else {
+        None
+    }
+}
+
+/// Generates an order key placing a page right after its sibling `after`, or
+/// first among its siblings if it's `None`. `moved` is left out of the
+/// siblings when moving a page. Returns `None` if `after` isn't a sibling.
+async fn order_after(
+    page_repo: &dyn PageRepo,
+    workspace_uuid: &Uuid,
+    parent_page_uuid: Option<&Uuid>,
+    after: Option<Uuid>,
+    moved: Option<Uuid>,
+) -> Result<Option<String>> {
+    let mut pages = page_repo
+        .get_child_pages(workspace_uuid, parent_page_uuid)
+        .await?;
+    for rebalanced in [false, true] {
+        pages.retain(|page| Some(page.uuid) != moved);
+        let next = match after {
+            Some(after) => match pages.iter().position(|page| page.uuid == after) {
+                Some(pos) => pos + 1,
+                None => return Ok(None),
+            },
+            None => 0,
+        };
+        let keys = pages
+            .iter()
+            .map(|page| page.order.as_str())
+            .collect::<Vec<_>>();
+        match fractional_index::key_at(&keys, next, rebalanced) {
+            Some(key) => return Ok(Some(key)),
+            None if !rebalanced => {
+                pages = page_repo
+                    .rebalance_child_pages(workspace_uuid, parent_page_uuid)
+                    .await?
+            }
+            None => {}
+        }
+    }
+    Err("Failed to generate a page order key".into())
+}
+
+/// Checks that `parent_page_uuid` is a page of the workspace outside the trash.
+async fn validate_parent(
+    page_repo: &dyn PageRepo,
+    workspace_uuid: &Uuid,
+    parent_page_uuid: Option<&Uuid>,
+) -> Result<Option<InputError>> {
+    let parent_page_uuid = match parent_page_uuid {
+        Some(parent_page_uuid) => parent_page_uuid,
+        None => return Ok(None),
+    };
+    match page_repo.get_page_by_uuid(parent_page_uuid).await? {
+        Some(parent) if parent.workspace_uuid == *workspace_uuid => Ok(None),
+        _ => Ok(Some(InputError {
+            field: "parentPageUuid".to_string(),
+            message: "Parent page not found in this workspace".to_string(),
+        })),
+    }
+}
+
+fn after_not_found() -> InputError {
+    InputError {
+        field: "after".to_string(),
+        message: "Page not found among the siblings".to_string(),
+    }
+}
+
+fn page_not_found() -> WithError<Page> {
+    WithError {
+        errors: vec![InputError {
+            field: "uuid".to_string(),
+            message: "Page not found".to_string(),
+        }],
+        value: None,
+    }
+}
+
+#[derive(Default)]
+pub struct PageQuery;
+
+#[Object]
+impl PageQuery {
+    #[graphql(guard = "PageRoleGuard::new(uuid, WorkspaceRole::Viewer)")]
+    pub async fn get_page(&self, ctx: &Context<'_>, uuid: Uuid) -> Result<WithError<Page>> {
+        let page_repo = ctx.data_unchecked::<Arc<dyn PageRepo>>();
+        // The guard already loaded the page, so it can only be missing if it
+        // was trashed since. Answer the way the guard would have.
+        let page = page_repo
+            .get_page_by_uuid(&uuid)
+            .await?
+            .ok_or_else(|| GuardError::Forbidden.extend())?;
+        Ok(page.into())
+    }
+}
+
 #[derive(Default)]
 pub struct PageMutation;
 
@@ -18,45 +198,447 @@ pub struct CreatePageInput {
     pub name: String,
     pub workspace_uuid: Uuid,
     pub image: Option<Upload>,
+    /// The page to nest the new page under. The new page is a root page if
+    /// omitted.
+    pub parent_page_uuid: Option<Uuid>,
+    /// The sibling after which the new page is placed. The new page goes
+    /// first if omitted.
+    pub after: Option<Uuid>,
+}
+
+#[derive(InputObject)]
+pub struct UpdatePageInput {
+    pub uuid: Uuid,
+    /// The version of the page the update is based on.
+    pub version: i32,
+    pub title: Option<String>,
+    /// A new image replacing the current one.
+    pub image: Option<Upload>,
+    /// Removes the current image. Ignored if `image` is set.
+    #[graphql(default)]
+    pub remove_image: bool,
 }
 
 #[Object]
 impl PageMutation {
+    #[graphql(guard = "WorkspaceRoleGuard::new(page.workspace_uuid, WorkspaceRole::Editor)")]
     pub async fn create_page(
         &self,
         ctx: &Context<'_>,
         page: CreatePageInput,
     ) -> Result<WithError<Page>> {
         let page_repo = ctx.data_unchecked::<Arc<dyn PageRepo>>();
-        let images_repo = ctx.data_unchecked::<Arc<dyn ImagesRepo>>();
 
+        if let Some(error) = validate_title(&page.name) {
+            return Ok(WithError {
+                errors: vec![error],
+                value: None,
+            });
+        }
+        let parent_page_uuid = page.parent_page_uuid.as_ref();
+        if let Some(error) =
+            validate_parent(&**page_repo, &page.workspace_uuid, parent_page_uuid).await?
+        {
+            return Ok(WithError {
+                errors: vec![error],
+                value: None,
+            });
+        }
+        let order = match order_after(
+            &**page_repo,
+            &page.workspace_uuid,
+            parent_page_uuid,
+            page.after,
+            None,
+        )
+        .await?
+        {
+            Some(order) => order,
+            None => {
+                return Ok(WithError {
+                    errors: vec![after_not_found()],
+                    value: None,
+                })
+            }
+        };
+
+        // Deletes the uploaded image again if the page can't be created.
+        let unit_of_work_repo = ctx.data_unchecked::<Arc<dyn UnitOfWorkRepo>>();
+        let images = unit_of_work_repo.pending_images();
         let image = match page.image {
             Some(image) => {
-                let mut image = image.value(ctx)?;
-                let image_extension = Path::new(&image.filename)
-                    .extension()
-                    .unwrap()
-                    .to_str()
-                    .unwrap();
-                let image_name = format!(
-                    "images/{}/{}.{}",
-                    page.workspace_uuid,
-                    Uuid::new_v4(),
-                    image_extension
-                );
-                let mut buf = &mut Vec::new();
-                image.content.read_to_end(&mut buf)?;
-                let image = images_repo.upload_image(&image_name, buf).await?;
-                Some(image)
+                let image = upload_page_image(ctx, &images, &page.workspace_uuid, image).await?;
+                if !image.errors.is_empty() {
+                    return Ok(WithError {
+                        errors: image.errors,
+                        value: None,
+                    });
+                }
+                image.value
             }
             None => None,
         };
 
-        let page = Page::new(page.workspace_uuid, page.name, image);
-        page_repo.create_page(&page).await?;
+        let page = Page::new(
+            page.workspace_uuid,
+            page.parent_page_uuid,
+            order,
+            page.name,
+            image,
+        );
+        let unit_of_work = unit_of_work_repo.begin(images).await?;
+        if let Err(err) = unit_of_work.page_repo().create_page(&page).await {
+            unit_of_work.rollback().await?;
+            return Err(err.into());
+        }
+        unit_of_work.commit().await?;
+        record_revision(ctx, &page.uuid, true).await?;
+        publish_page_change(ctx, &page, ChangeKind::PageCreated, None, None).await;
         Ok(WithError {
             errors: vec![],
             value: Some(page),
         })
     }
+
+    #[graphql(guard = "PageRoleGuard::new(page.uuid, WorkspaceRole::Editor)")]
+    pub async fn update_page(
+        &self,
+        ctx: &Context<'_>,
+        page: UpdatePageInput,
+    ) -> Result<WithError<Page>> {
+        let page_repo = ctx.data_unchecked::<Arc<dyn PageRepo>>();
+        let images_repo = ctx.data_unchecked::<Arc<dyn ImagesRepo>>();
+
+        let mut existing = match page_repo.get_page_by_uuid(&page.uuid).await? {
+            Some(existing) => existing,
+            None => return Ok(page_not_found()),
+        };
+        if existing.version != page.version {
+            let version = existing.version;
+            return Err(ConflictError {
+                kind: "page",
+                version,
+            }
+            .extend());
+        }
+
+        if let Some(title) = page.title {
+            if let Some(error) = validate_title(&title) {
+                return Ok(WithError {
+                    errors: vec![error],
+                    value: None,
+                });
+            }
+            existing.title = title;
+        }
+
+        let old_image = existing.image.clone();
+        match page.image {
+            Some(image) => {
+                let image =
+                    upload_page_image(ctx, images_repo.as_ref(), &existing.workspace_uuid, image)
+                        .await?;
+                if !image.errors.is_empty() {
+                    return Ok(WithError {
+                        errors: image.errors,
+                        value: None,
+                    });
+                }
+                existing.image = image.value;
+            }
+            None if page.remove_image => existing.image = None,
+            None => {}
+        }
+
+        let updated = match page_repo.update_page(&existing).await? {
+            Some(updated) => updated,
+            None => {
+                // Someone else updated the page since it was loaded.
+                if let Some(new_image) =
+                    existing.image.filter(|new| Some(new) != old_image.as_ref())
+                {
+                    images_repo.delete_image(&new_image).await?;
+                }
+                return match page_repo.get_page_by_uuid(&page.uuid).await? {
+                    Some(current) => {
+                        let version = current.version;
+                        Err(ConflictError {
+                            kind: "page",
+                            version,
+                        }
+                        .extend())
+                    }
+                    None => Ok(page_not_found()),
+                };
+            }
+        };
+        record_revision(ctx, &updated.uuid, true).await?;
+        publish_page_change(ctx, &updated, ChangeKind::PageUpdated, None, None).await;
+        Ok(updated.into())
+    }
+
+    /// Nests a page under `parent_page_uuid`, or makes it a root page if
+    /// omitted, right after its new sibling `after`, or first if omitted.
+    ///
+    /// If `version` is given, the move fails with a conflict when the page
+    /// was changed by someone else since.
+    #[graphql(guard = "PageRoleGuard::new(uuid, WorkspaceRole::Editor)")]
+    pub async fn move_page(
+        &self,
+        ctx: &Context<'_>,
+        uuid: Uuid,
+        parent_page_uuid: Option<Uuid>,
+        after: Option<Uuid>,
+        version: Option<i32>,
+    ) -> Result<WithError<Page>> {
+        let page_repo = ctx.data_unchecked::<Arc<dyn PageRepo>>();
+
+        let mut page = match page_repo.get_page_by_uuid(&uuid).await? {
+            Some(page) => page,
+            None => return Ok(page_not_found()),
+        };
+        if version.is_some_and(|version| version != page.version) {
+            let version = page.version;
+            return Err(ConflictError {
+                kind: "page",
+                version,
+            }
+            .extend());
+        }
+        let parent = parent_page_uuid.as_ref();
+        if let Some(error) = validate_parent(&**page_repo, &page.workspace_uuid, parent).await? {
+            return Ok(WithError {
+                errors: vec![error],
+                value: None,
+            });
+        }
+        let order = match order_after(
+            &**page_repo,
+            &page.workspace_uuid,
+            parent,
+            after,
+            Some(uuid),
+        )
+        .await?
+        {
+            Some(order) => order,
+            None => {
+                return Ok(WithError {
+                    errors: vec![after_not_found()],
+                    value: None,
+                })
+            }
+        };
+        page.parent_page_uuid = parent_page_uuid;
+        page.order = order;
+        let page = match page_repo.move_page(&page).await? {
+            PageMove::Moved(page) => page,
+            PageMove::Cycle => {
+                return Ok(WithError {
+                    errors: vec![InputError {
+                        field: "parentPageUuid".to_string(),
+                        message: "A page can't be moved under itself".to_string(),
+                    }],
+                    value: None,
+                })
+            }
+            PageMove::Outdated => {
+                return match page_repo.get_page_by_uuid(&uuid).await? {
+                    Some(current) => {
+                        let version = current.version;
+                        Err(ConflictError {
+                            kind: "page",
+                            version,
+                        }
+                        .extend())
+                    }
+                    None => Ok(page_not_found()),
+                }
+            }
+        };
+        publish_page_change(ctx, &page, ChangeKind::PageMoved, None, None).await;
+        Ok(page.into())
+    }
+
+    /// Moves a page and the pages nested under it to the trash, from where
+    /// they can be restored until they're purged.
+    #[graphql(guard = "PageRoleGuard::new(uuid, WorkspaceRole::Editor)")]
+    pub async fn delete_page(&self, ctx: &Context<'_>, uuid: Uuid) -> Result<WithError<Page>> {
+        let page_repo = ctx.data_unchecked::<Arc<dyn PageRepo>>();
+
+        let pages = page_repo.trash_page(&uuid).await?;
+        for page in &pages {
+            publish_page_change(ctx, page, ChangeKind::PageDeleted, None, None).await;
+        }
+        match pages.into_iter().next() {
+            Some(page) => Ok(page.into()),
+            None => Ok(page_not_found()),
+        }
+    }
+
+    /// Takes a page out of the trash, together with the pages nested under
+    /// it that were deleted along with it. The page becomes a root page if
+    /// its parent is still in the trash.
+    pub async fn restore_page(&self, ctx: &Context<'_>, uuid: Uuid) -> Result<WithError<Page>> {
+        let page_repo = ctx.data_unchecked::<Arc<dyn PageRepo>>();
+
+        let page = match page_repo.get_trashed_page(&uuid).await? {
+            Some(page) => page,
+            None => return Ok(page_not_found()),
+        };
+        WorkspaceRoleGuard::new(page.workspace_uuid, WorkspaceRole::Editor)
+            .check(ctx)
+            .await?;
+        let pages = page_repo.restore_page(&uuid).await?;
+        for page in &pages {
+            publish_page_change(ctx, page, ChangeKind::PageCreated, None, None).await;
+        }
+        match pages.into_iter().next() {
+            Some(page) => Ok(page.into()),
+            None => Ok(page_not_found()),
+        }
+    }
+
+    /// Permanently deletes a page in the trash, together with the pages nested
+    /// under it and their content, revisions and images.
+    pub async fn purge_page(&self, ctx: &Context<'_>, uuid: Uuid) -> Result<WithError<Page>> {
+        let page_repo = ctx.data_unchecked::<Arc<dyn PageRepo>>();
+        let images_repo = ctx.data_unchecked::<Arc<dyn ImagesRepo>>();
+
+        let page = match page_repo.get_trashed_page(&uuid).await? {
+            Some(page) => page,
+            None => return Ok(page_not_found()),
+        };
+        WorkspaceRoleGuard::new(page.workspace_uuid, WorkspaceRole::Editor)
+            .check(ctx)
+            .await?;
+        let images = page_repo.purge_page(&uuid).await?;
+        delete_images(images_repo.as_ref(), &images).await;
+        Ok(page.into())
+    }
+}
+
+#[ComplexObject]
+impl Page {
+    /// The pages this page is nested under, from its root page down to its
+    /// parent.
+    #[graphql(guard = "WorkspaceRoleGuard::new(self.workspace_uuid, WorkspaceRole::Viewer)")]
+    pub async fn ancestors(&self, ctx: &Context<'_>) -> Result<Vec<Page>> {
+        // Requests over websockets share their connection's data, where a
+        // cache would go stale, so they load ancestors directly.
+        let loader = match ctx.data_opt::<DataLoader<AncestorsLoader, HashMapCache>>() {
+            Some(loader) => loader,
+            None => {
+                let page_repo = ctx.data_unchecked::<Arc<dyn PageRepo>>();
+                let mut ancestors = page_repo.get_ancestors(&[self.uuid]).await?;
+                return Ok(ancestors.remove(&self.uuid).unwrap_or_default());
+            }
+        };
+        let ancestors = loader.load_one(self.uuid).await?.unwrap_or_default();
+        // The ancestors' own chains are prefixes of this one.
+        loader
+            .feed_many(
+                ancestors
+                    .iter()
+                    .enumerate()
+                    .map(|(depth, page)| (page.uuid, ancestors[..depth].to_vec())),
+            )
+            .await;
+        Ok(ancestors)
+    }
+
+    /// How deeply this page is nested, 0 for root pages.
+    #[graphql(guard = "WorkspaceRoleGuard::new(self.workspace_uuid, WorkspaceRole::Viewer)")]
+    pub async fn depth(&self, ctx: &Context<'_>) -> Result<usize> {
+        Ok(self.ancestors(ctx).await?.len())
+    }
+
+    /// The pages nested directly under this page, sorted by their order.
+    #[graphql(guard = "WorkspaceRoleGuard::new(self.workspace_uuid, WorkspaceRole::Viewer)")]
+    pub async fn children(&self, ctx: &Context<'_>) -> Result<Vec<Page>> {
+        let page_repo = ctx.data_unchecked::<Arc<dyn PageRepo>>();
+        page_repo
+            .get_child_pages(&self.workspace_uuid, Some(&self.uuid))
+            .await
+            .map_err(|err| err.into())
+    }
+
+    /// The page's slots, sorted by their order.
+    #[graphql(guard = "WorkspaceRoleGuard::new(self.workspace_uuid, WorkspaceRole::Viewer)")]
+    pub async fn slots(&self, ctx: &Context<'_>) -> Result<Vec<Slot>> {
+        let slot_repo = ctx.data_unchecked::<Arc<dyn SlotRepo>>();
+        slot_repo
+            .get_page_slots(&self.uuid)
+            .await
+            .map_err(|err| err.into())
+    }
+
+    /// The page's revisions, newest first.
+    #[graphql(guard = "WorkspaceRoleGuard::new(self.workspace_uuid, WorkspaceRole::Viewer)")]
+    pub async fn revisions(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<Connection<i32, PageRevision>> {
+        let revision_repo = ctx.data_unchecked::<Arc<dyn RevisionRepo>>();
+        query(
+            after,
+            before,
+            first,
+            last,
+            |after: Option<i32>, before: Option<i32>, first, last| async move {
+                // Revisions after a cursor are older, those before it newer.
+                let (limit, from_end) = match (first, last) {
+                    (Some(_), Some(_)) => return Err("Pass either first or last".into()),
+                    (None, Some(last)) => (last.min(MAX_REVISIONS), true),
+                    (first, None) => (first.unwrap_or(DEFAULT_REVISIONS).min(MAX_REVISIONS), false),
+                };
+                let mut revisions = revision_repo
+                    .get_page_revisions(&self.uuid, before, after, limit as i64 + 1, from_end)
+                    .await?;
+                let has_more = revisions.len() > limit;
+                revisions.truncate(limit);
+                let mut connection = if from_end {
+                    revisions.reverse();
+                    Connection::new(has_more, before.is_some())
+                } else {
+                    Connection::new(after.is_some(), has_more)
+                };
+                connection.edges.extend(
+                    revisions
+                        .into_iter()
+                        .map(|revision| Edge::new(revision.number, revision)),
+                );
+                Ok::<_, async_graphql::Error>(connection)
+            },
+        )
+        .await
+    }
+
+    #[graphql(guard = "WorkspaceRoleGuard::new(self.workspace_uuid, WorkspaceRole::Viewer)")]
+    pub async fn revision(&self, ctx: &Context<'_>, number: i32) -> Result<Option<PageRevision>> {
+        let revision_repo = ctx.data_unchecked::<Arc<dyn RevisionRepo>>();
+        Ok(revision_repo.get_revision(&self.uuid, number).await?)
+    }
+
+    /// The changes from revision `from` to revision `to`, or `None` if either
+    /// doesn't exist.
+    #[graphql(guard = "WorkspaceRoleGuard::new(self.workspace_uuid, WorkspaceRole::Viewer)")]
+    pub async fn revision_diff(
+        &self,
+        ctx: &Context<'_>,
+        from: i32,
+        to: i32,
+    ) -> Result<Option<RevisionDiff>> {
+        let revision_repo = ctx.data_unchecked::<Arc<dyn RevisionRepo>>();
+        let from = revision_repo.get_revision(&self.uuid, from).await?;
+        let to = revision_repo.get_revision(&self.uuid, to).await?;
+        match (from, to) {
+            (Some(from), Some(to)) => Ok(Some(from.parse_snapshot()?.diff(&to.parse_snapshot()?))),
+            _ => Ok(None),
+        }
+    }
 }
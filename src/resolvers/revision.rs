@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use async_graphql::{ComplexObject, Context, Object, Result};
+use chrono::Duration;
+use uuid::Uuid;
+
+use super::subscription::publish_page_change;
+use crate::{
+    models::{page_revision::PageSnapshot, Page, PageRevision, User, WorkspaceRole},
+    repos::traits::{RevisionRepo, SlotRepo, UserRepo},
+    utils::{
+        events::ChangeKind,
+        guards::{current_user, PageRoleGuard},
+        types::{InputError, WithError},
+    },
+};
+
+/// Changes a user makes to a page within this many minutes of starting a
+/// revision are added to it.
+const REVISION_WINDOW_MINUTES: i64 = 5;
+
+/// Records a revision of the page `page_uuid` made by the current user. With
+/// `coalesce`, recent changes by the same user are added to their revision.
+pub(crate) async fn record_revision(
+    ctx: &Context<'_>,
+    page_uuid: &Uuid,
+    coalesce: bool,
+) -> Result<Option<PageRevision>> {
+    let revision_repo = ctx.data_unchecked::<Arc<dyn RevisionRepo>>();
+    let user_uuid = current_user(ctx).ok().map(|user| user.uuid);
+    let window = coalesce.then(|| Duration::minutes(REVISION_WINDOW_MINUTES));
+    Ok(revision_repo
+        .record_revision(page_uuid, user_uuid.as_ref(), window)
+        .await?)
+}
+
+/// Records a revision of the page holding the slot `slot_uuid`, coalescing
+/// recent changes by the current user.
+pub(crate) async fn record_slot_revision(ctx: &Context<'_>, slot_uuid: &Uuid) -> Result<()> {
+    let slot_repo = ctx.data_unchecked::<Arc<dyn SlotRepo>>();
+    if let Some(slot) = slot_repo.get_slot_by_uuid(slot_uuid).await? {
+        record_revision(ctx, &slot.page_uuid, true).await?;
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct RevisionMutation;
+
+#[Object]
+impl RevisionMutation {
+    /// Restores the content of a page from one of its revisions. This adds a
+    /// new revision, so the restore can be undone in turn.
+    #[graphql(guard = "PageRoleGuard::new(page_uuid, WorkspaceRole::Editor)")]
+    pub async fn restore_page_revision(
+        &self,
+        ctx: &Context<'_>,
+        page_uuid: Uuid,
+        number: i32,
+    ) -> Result<WithError<Page>> {
+        let revision_repo = ctx.data_unchecked::<Arc<dyn RevisionRepo>>();
+
+        let revision = match revision_repo.get_revision(&page_uuid, number).await? {
+            Some(revision) => revision,
+            None => {
+                return Ok(WithError {
+                    errors: vec![InputError {
+                        field: "number".to_string(),
+                        message: "Revision not found".to_string(),
+                    }],
+                    value: None,
+                })
+            }
+        };
+        let page = match revision_repo
+            .restore_snapshot(&page_uuid, &revision.parse_snapshot()?)
+            .await?
+        {
+            Some(page) => page,
+            None => {
+                return Ok(WithError {
+                    errors: vec![InputError {
+                        field: "pageUuid".to_string(),
+                        message: "Page not found".to_string(),
+                    }],
+                    value: None,
+                })
+            }
+        };
+        record_revision(ctx, &page.uuid, false).await?;
+        publish_page_change(ctx, &page, ChangeKind::PageUpdated, None, None).await;
+        Ok(page.into())
+    }
+}
+
+#[ComplexObject]
+impl PageRevision {
+    /// The user who made the changes, unless they were deleted.
+    pub async fn user(&self, ctx: &Context<'_>) -> Result<Option<User>> {
+        let user_repo = ctx.data_unchecked::<Arc<dyn UserRepo>>();
+        match &self.user_uuid {
+            Some(user_uuid) => Ok(user_repo.get_user_by_uuid(user_uuid).await?),
+            None => Ok(None),
+        }
+    }
+
+    /// The page's content in this revision.
+    pub async fn content(&self) -> Result<PageSnapshot> {
+        Ok(self.parse_snapshot()?)
+    }
+}
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use async_graphql::{
+    connection::{self, Connection, Edge},
+    ComplexObject, Context, Object, Result,
+};
+use uuid::Uuid;
+
+use crate::{
+    models::{search::SearchHit, Page, Slot, WorkspaceRole},
+    repos::traits::{PageRepo, SearchRepo, SlotRepo},
+    utils::guards::WorkspaceRoleGuard,
+};
+
+/// Number of hits returned when `first` isn't given.
+const DEFAULT_HITS: usize = 20;
+/// Maximum number of hits returned at once.
+const MAX_HITS: usize = 50;
+
+#[derive(Default)]
+pub struct SearchQuery;
+
+#[Object]
+impl SearchQuery {
+    /// Searches the titles and content of a workspace's pages, best matches
+    /// first. The query supports quoted phrases, `or` and `-` to exclude
+    /// words.
+    #[graphql(guard = "WorkspaceRoleGuard::new(workspace_uuid, WorkspaceRole::Viewer)")]
+    pub async fn search(
+        &self,
+        ctx: &Context<'_>,
+        workspace_uuid: Uuid,
+        query: String,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Connection<usize, SearchHit>> {
+        let search_repo = ctx.data_unchecked::<Arc<dyn SearchRepo>>();
+        connection::query(
+            after,
+            None,
+            first,
+            None,
+            |after: Option<usize>, _: Option<usize>, first, _| async move {
+                let offset = after.map_or(0, |after| after + 1);
+                let limit = first.unwrap_or(DEFAULT_HITS).min(MAX_HITS);
+                let mut hits = if query.trim().is_empty() {
+                    Vec::new()
+                } else {
+                    search_repo
+                        .search(&workspace_uuid, &query, offset as i64, limit as i64 + 1)
+                        .await?
+                };
+                let has_more = hits.len() > limit;
+                hits.truncate(limit);
+                let mut connection = Connection::new(offset > 0, has_more);
+                connection.edges.extend(
+                    hits.into_iter()
+                        .enumerate()
+                        .map(|(i, hit)| Edge::new(offset + i, hit)),
+                );
+                Ok::<_, async_graphql::Error>(connection)
+            },
+        )
+        .await
+    }
+}
+
+#[ComplexObject]
+impl SearchHit {
+    pub async fn page(&self, ctx: &Context<'_>) -> Result<Option<Page>> {
+        let page_repo = ctx.data_unchecked::<Arc<dyn PageRepo>>();
+        Ok(page_repo.get_page_by_uuid(&self.page_uuid).await?)
+    }
+
+    pub async fn slot(&self, ctx: &Context<'_>) -> Result<Option<Slot>> {
+        let slot_repo = ctx.data_unchecked::<Arc<dyn SlotRepo>>();
+        match self.slot_uuid {
+            Some(slot_uuid) => Ok(slot_repo.get_slot_by_uuid(&slot_uuid).await?),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use std::sync::Arc;
+
+use async_graphql::{ComplexObject, Context, ErrorExtensions, InputObject, Object, Result};
+use log::warn;
+use uuid::Uuid;
+
+use super::{revision::record_revision, subscription::publish_slot_change};
+use crate::{
+    models::{Atom, Slot, WorkspaceRole},
+    repos::traits::{AtomRepo, SlotRepo},
+    utils::{
+        events::ChangeKind,
+        fractional_index,
+        guards::{PageRoleGuard, SlotRoleGuard},
+        types::{ConflictError, InputError, RetryError, WithError},
+    },
+};
+
+/// Returns the position right after `after` among `slots`, or the first one
+/// if it's `None`. Returns `None` if `after` isn't one of `slots`.
+fn position_after(slots: &[Slot], after: Option<Uuid>) -> Option<usize> {
+    match after {
+        Some(after) => slots
+            .iter()
+            .position(|slot| slot.uuid == after)
+            .map(|pos| pos + 1),
+        None => Some(0),
+    }
+}
+
+fn order_keys(slots: &[Slot]) -> Vec<&str> {
+    slots.iter().map(|slot| slot.order.as_str()).collect()
+}
+
+/// Generates an order key placing a new slot right after `after`, or first on
+/// the page if it's `None`, rebalancing the page's keys first if needed.
+/// Returns `None` if `after` isn't a slot of the page.
+async fn order_after(
+    slot_repo: &dyn SlotRepo,
+    page_uuid: &Uuid,
+    after: Option<Uuid>,
+) -> Result<Option<String>> {
+    let mut slots = slot_repo.get_page_slots(page_uuid).await?;
+    for rebalanced in [false, true] {
+        let next = match position_after(&slots, after) {
+            Some(next) => next,
+            None => return Ok(None),
+        };
+        match fractional_index::key_at(&order_keys(&slots), next, rebalanced) {
+            Some(key) => return Ok(Some(key)),
+            None if !rebalanced => slots = slot_repo.rebalance_page_slots(page_uuid).await?,
+            None => {}
+        }
+    }
+    Err("Failed to generate a slot order key".into())
+}
+
+/// Generates an order key moving `moved` right after `after`, or first on the
+/// page if it's `None`, without touching the page's other slots. Returns
+/// `Ok(None)` if `after` isn't a slot of the page, and a `RetryError` if the
+/// keys around that spot are too long, after scheduling a rebalance.
+async fn move_order_after(
+    slot_repo: &Arc<dyn SlotRepo>,
+    page_uuid: &Uuid,
+    after: Option<Uuid>,
+    moved: Uuid,
+) -> Result<Option<String>> {
+    let mut slots = slot_repo.get_page_slots(page_uuid).await?;
+    slots.retain(|slot| slot.uuid != moved);
+    let next = match position_after(&slots, after) {
+        Some(next) => next,
+        None => return Ok(None),
+    };
+    match fractional_index::key_at(&order_keys(&slots), next, true) {
+        Some(key) => Ok(Some(key)),
+        None => {
+            rebalance_later(slot_repo, *page_uuid);
+            Err(RetryError("The slots of this page are being reordered").extend())
+        }
+    }
+}
+
+/// Rebalances the order keys of a page's slots in the background, so moves
+/// don't have to rewrite the other slots of the page.
+fn rebalance_later(slot_repo: &Arc<dyn SlotRepo>, page_uuid: Uuid) {
+    let slot_repo = Arc::clone(slot_repo);
+    tokio::spawn(async move {
+        if let Err(err) = slot_repo.rebalance_page_slots(&page_uuid).await {
+            warn!(
+                "Failed to rebalance the slots of page {}: {}",
+                page_uuid, err
+            );
+        }
+    });
+}
+
+fn after_not_found() -> InputError {
+    InputError {
+        field: "after".to_string(),
+        message: "Slot not found on this page".to_string(),
+    }
+}
+
+fn slot_not_found() -> WithError<Slot> {
+    WithError {
+        errors: vec![InputError {
+            field: "uuid".to_string(),
+            message: "Slot not found".to_string(),
+        }],
+        value: None,
+    }
+}
+
+#[derive(InputObject)]
+pub struct InsertSlotInput {
+    pub page_uuid: Uuid,
+    /// The slot after which the new slot is placed. The new slot goes first
+    /// if omitted.
+    pub after: Option<Uuid>,
+}
+
+#[derive(Default)]
+pub struct SlotMutation;
+
+#[Object]
+impl SlotMutation {
+    #[graphql(guard = "PageRoleGuard::new(slot.page_uuid, WorkspaceRole::Editor)")]
+    pub async fn insert_slot(
+        &self,
+        ctx: &Context<'_>,
+        slot: InsertSlotInput,
+    ) -> Result<WithError<Slot>> {
+        let slot_repo = ctx.data_unchecked::<Arc<dyn SlotRepo>>();
+
+        let order = match order_after(&**slot_repo, &slot.page_uuid, slot.after).await? {
+            Some(order) => order,
+            None => {
+                return Ok(WithError {
+                    errors: vec![after_not_found()],
+                    value: None,
+                })
+            }
+        };
+
+        let slot = Slot::new(slot.page_uuid, order);
+        slot_repo.create_slot(&slot).await?;
+        record_revision(ctx, &slot.page_uuid, true).await?;
+        publish_slot_change(
+            ctx,
+            &slot.page_uuid,
+            &slot.uuid,
+            ChangeKind::SlotInserted,
+            None,
+        )
+        .await;
+        Ok(slot.into())
+    }
+
+    /// Moves a slot right after `after`, or first on its page if omitted.
+    /// Only the moved slot is written; once the page's keys get long they're
+    /// rebalanced in the background, and a move that finds no room fails
+    /// with a retryable error until then.
+    ///
+    /// If `version` is given, the move fails with a conflict when the slot
+    /// was moved by someone else since.
+    #[graphql(guard = "SlotRoleGuard::new(uuid, WorkspaceRole::Editor)")]
+    pub async fn move_slot(
+        &self,
+        ctx: &Context<'_>,
+        uuid: Uuid,
+        after: Option<Uuid>,
+        version: Option<i32>,
+    ) -> Result<WithError<Slot>> {
+        let slot_repo = ctx.data_unchecked::<Arc<dyn SlotRepo>>();
+
+        let mut slot = match slot_repo.get_slot_by_uuid(&uuid).await? {
+            Some(slot) => slot,
+            None => return Ok(slot_not_found()),
+        };
+        if version.is_some_and(|version| version != slot.version) {
+            let version = slot.version;
+            return Err(ConflictError {
+                kind: "slot",
+                version,
+            }
+            .extend());
+        }
+        let order = match move_order_after(slot_repo, &slot.page_uuid, after, uuid).await? {
+            Some(order) => order,
+            None => {
+                return Ok(WithError {
+                    errors: vec![after_not_found()],
+                    value: None,
+                })
+            }
+        };
+        slot.order = order;
+        let slot = match slot_repo.update_slot(&slot).await? {
+            Some(slot) => slot,
+            None => {
+                return match slot_repo.get_slot_by_uuid(&uuid).await? {
+                    Some(current) => {
+                        let version = current.version;
+                        Err(ConflictError {
+                            kind: "slot",
+                            version,
+                        }
+                        .extend())
+                    }
+                    None => Ok(slot_not_found()),
+                }
+            }
+        };
+        // Rebalancing only after the write keeps it from being placed among
+        // keys generated before the rebalance.
+        if slot.order.len() > fractional_index::REBALANCE_LEN {
+            rebalance_later(slot_repo, slot.page_uuid);
+        }
+        record_revision(ctx, &slot.page_uuid, true).await?;
+        publish_slot_change(
+            ctx,
+            &slot.page_uuid,
+            &slot.uuid,
+            ChangeKind::SlotMoved,
+            None,
+        )
+        .await;
+        Ok(slot.into())
+    }
+
+    #[graphql(guard = "SlotRoleGuard::new(uuid, WorkspaceRole::Editor)")]
+    pub async fn delete_slot(&self, ctx: &Context<'_>, uuid: Uuid) -> Result<WithError<Slot>> {
+        let slot_repo = ctx.data_unchecked::<Arc<dyn SlotRepo>>();
+
+        let slot = match slot_repo.get_slot_by_uuid(&uuid).await? {
+            Some(slot) => slot,
+            None => return Ok(slot_not_found()),
+        };
+        slot_repo.delete_slot(&slot.uuid).await?;
+        record_revision(ctx, &slot.page_uuid, true).await?;
+        publish_slot_change(
+            ctx,
+            &slot.page_uuid,
+            &slot.uuid,
+            ChangeKind::SlotDeleted,
+            None,
+        )
+        .await;
+        Ok(slot.into())
+    }
+}
+
+#[ComplexObject]
+impl Slot {
+    pub async fn atoms(&self, ctx: &Context<'_>) -> Result<Vec<Atom>> {
+        let atom_repo = ctx.data_unchecked::<Arc<dyn AtomRepo>>();
+        atom_repo
+            .get_slot_atoms(&self.uuid)
+            .await
+            .map_err(|err| err.into())
+    }
+}
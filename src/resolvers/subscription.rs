@@ -0,0 +1,197 @@
+use std::sync::Arc;
+
+use async_graphql::{
+    futures_util::{stream, Stream},
+    Context, ErrorExtensions, FieldError, Result, Subscription,
+};
+use log::warn;
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+use crate::{
+    models::{text_ops::TextEdit, Atom, Page, WorkspaceRole},
+    repos::traits::{PageRepo, SlotRepo, WorkspaceRepo},
+    utils::{
+        events::{ChangeEvent, ChangeKind, EventBroker},
+        guards::{current_user, GuardError, PageRoleGuard, WorkspaceRoleGuard},
+    },
+};
+
+#[derive(Debug, Error)]
+pub enum SubscriptionError {
+    #[error("{0} changes were missed, refetch the data")]
+    Lagged(u64),
+}
+
+impl ErrorExtensions for SubscriptionError {
+    fn extend(&self) -> FieldError {
+        self.extend_with(|err, e| match err {
+            SubscriptionError::Lagged(_) => e.set("code", 409),
+        })
+    }
+}
+
+fn page_event(
+    ctx: &Context<'_>,
+    page: &Page,
+    kind: ChangeKind,
+    slot_uuid: Option<Uuid>,
+    atom_idx: Option<i32>,
+) -> ChangeEvent {
+    ChangeEvent {
+        kind,
+        workspace_uuid: page.workspace_uuid,
+        page_uuid: page.uuid,
+        slot_uuid,
+        atom_idx,
+        user_uuid: current_user(ctx).ok().map(|user| user.uuid),
+        edit: None,
+    }
+}
+
+/// Publishes the event. Failures are logged rather than returned, since the
+/// change itself has already been saved.
+async fn publish(ctx: &Context<'_>, event: ChangeEvent) {
+    let broker = ctx.data_unchecked::<Arc<dyn EventBroker>>();
+    let kind = event.kind;
+    if let Err(err) = broker.publish(event).await {
+        warn!("Failed to publish {:?} event: {}", kind, err);
+    }
+}
+
+/// Publishes a change to `page`.
+pub(crate) async fn publish_page_change(
+    ctx: &Context<'_>,
+    page: &Page,
+    kind: ChangeKind,
+    slot_uuid: Option<Uuid>,
+    atom_idx: Option<i32>,
+) {
+    publish(ctx, page_event(ctx, page, kind, slot_uuid, atom_idx)).await;
+}
+
+/// Publishes a change to a slot of the page `page_uuid`.
+pub(crate) async fn publish_slot_change(
+    ctx: &Context<'_>,
+    page_uuid: &Uuid,
+    slot_uuid: &Uuid,
+    kind: ChangeKind,
+    atom_idx: Option<i32>,
+) {
+    let page_repo = ctx.data_unchecked::<Arc<dyn PageRepo>>();
+    match page_repo.get_page_by_uuid(page_uuid).await {
+        Ok(Some(page)) => publish_page_change(ctx, &page, kind, Some(*slot_uuid), atom_idx).await,
+        Ok(None) => {}
+        Err(err) => warn!("Failed to publish {:?} event: {}", kind, err),
+    }
+}
+
+/// Publishes a change to the atom `idx` of the slot `slot_uuid`.
+pub(crate) async fn publish_atom_change(
+    ctx: &Context<'_>,
+    slot_uuid: &Uuid,
+    idx: i32,
+    kind: ChangeKind,
+) {
+    let slot_repo = ctx.data_unchecked::<Arc<dyn SlotRepo>>();
+    match slot_repo.get_slot_by_uuid(slot_uuid).await {
+        Ok(Some(slot)) => {
+            publish_slot_change(ctx, &slot.page_uuid, slot_uuid, kind, Some(idx)).await
+        }
+        Ok(None) => {}
+        Err(err) => warn!("Failed to publish {:?} event: {}", kind, err),
+    }
+}
+
+/// Publishes an edit of the text atom `atom`.
+pub(crate) async fn publish_text_edit(ctx: &Context<'_>, atom: &Atom, edit: TextEdit) {
+    let slot_repo = ctx.data_unchecked::<Arc<dyn SlotRepo>>();
+    let page_repo = ctx.data_unchecked::<Arc<dyn PageRepo>>();
+    let page = match slot_repo.get_slot_by_uuid(&atom.slot_uuid).await {
+        Ok(Some(slot)) => page_repo.get_page_by_uuid(&slot.page_uuid).await,
+        Ok(None) => Ok(None),
+        Err(err) => Err(err),
+    };
+    let kind = ChangeKind::TextEdited;
+    match page {
+        Ok(Some(page)) => {
+            let event = page_event(ctx, &page, kind, Some(atom.slot_uuid), Some(atom.idx));
+            let event = ChangeEvent {
+                edit: Some(edit),
+                ..event
+            };
+            publish(ctx, event).await
+        }
+        Ok(None) => {}
+        Err(err) => warn!("Failed to publish {:?} event: {}", kind, err),
+    }
+}
+
+/// Streams the events accepted by `filter` while the current user is a member
+/// of their workspace. Membership is checked again before each event, and the
+/// stream ends with an error once it's lost. Missed events yield an error
+/// telling the client to refetch.
+fn change_events(
+    ctx: &Context<'_>,
+    filter: impl Fn(&ChangeEvent) -> bool + Send + Sync + 'static,
+) -> Result<impl Stream<Item = Result<ChangeEvent>>> {
+    let broker = ctx.data_unchecked::<Arc<dyn EventBroker>>();
+    let workspace_repo = Arc::clone(ctx.data_unchecked::<Arc<dyn WorkspaceRepo>>());
+    let user_uuid = current_user(ctx)?.uuid;
+    let filter = Arc::new(filter);
+    Ok(stream::unfold(Some(broker.subscribe()), move |receiver| {
+        let workspace_repo = Arc::clone(&workspace_repo);
+        let filter = Arc::clone(&filter);
+        async move {
+            let mut receiver = receiver?;
+            let event = loop {
+                match receiver.recv().await {
+                    Ok(event) if filter(&event) => break event,
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("Subscriber lagged behind, {} events were dropped", missed);
+                        let err = SubscriptionError::Lagged(missed).extend();
+                        return Some((Err(err), Some(receiver)));
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            };
+            // Any role may view the changes.
+            match workspace_repo
+                .get_member_role(&event.workspace_uuid, &user_uuid)
+                .await
+            {
+                Ok(Some(_)) => Some((Ok(event), Some(receiver))),
+                Ok(None) => Some((Err(GuardError::Forbidden.extend()), None)),
+                Err(err) => Some((Err(GuardError::from(err).extend()), None)),
+            }
+        }
+    }))
+}
+
+#[derive(Default)]
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// Changes to a page and its slots and atoms.
+    #[graphql(guard = "PageRoleGuard::new(page_uuid, WorkspaceRole::Viewer)")]
+    async fn page_changed(
+        &self,
+        ctx: &Context<'_>,
+        page_uuid: Uuid,
+    ) -> Result<impl Stream<Item = Result<ChangeEvent>>> {
+        change_events(ctx, move |event| event.page_uuid == page_uuid)
+    }
+
+    /// Changes to any page of a workspace.
+    #[graphql(guard = "WorkspaceRoleGuard::new(workspace_uuid, WorkspaceRole::Viewer)")]
+    async fn workspace_changed(
+        &self,
+        ctx: &Context<'_>,
+        workspace_uuid: Uuid,
+    ) -> Result<impl Stream<Item = Result<ChangeEvent>>> {
+        change_events(ctx, move |event| event.workspace_uuid == workspace_uuid)
+    }
+}
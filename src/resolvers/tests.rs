@@ -0,0 +1,751 @@
+//! Tests running requests against the schema with in-memory repositories.
+
+use std::sync::Arc;
+
+use async_graphql::{futures_util::StreamExt, Request, Response, UploadValue, Variables};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use super::{ancestors_loader, schema_builder, AppSchema};
+use crate::{
+    repos::{
+        images_repo::ImagesStorageKind, memory::images_repo::MemoryImagesRepo, test_db::test_db,
+        Repos, StorageKind,
+    },
+    utils::{
+        auth::authenticate,
+        config::{BaseConfig, Config},
+        events::{EventBrokerKind, LocalEventBroker},
+        jwt::{KeyStore, SigningKeys},
+    },
+};
+
+struct TestApp {
+    schema: AppSchema,
+    repos: Repos,
+    keys: Arc<KeyStore>,
+}
+
+impl TestApp {
+    fn new() -> Self {
+        Self::with_repos(Repos::memory())
+    }
+
+    fn with_repos(repos: Repos) -> Self {
+        let config = Arc::new(Config {
+            base: BaseConfig {
+                bind_addr: "127.0.0.1:0".to_string(),
+                storage: StorageKind::Memory,
+                database_url: String::new(),
+                auto_migrate: false,
+            },
+            jwt_keys: SigningKeys::generate(),
+            access_token_ttl: 900,
+            refresh_token_ttl: 2592000,
+            trash_retention_days: 30,
+            images_storage: ImagesStorageKind::S3,
+            s3_bucket: String::new(),
+            s3_endpoint: String::new(),
+            images_dir: String::new(),
+            images_url: String::new(),
+            event_broker: EventBrokerKind::Local,
+        });
+        let keys = Arc::new(KeyStore::new(config.jwt_keys.clone(), None));
+        let schema = schema_builder(
+            &repos,
+            config,
+            Arc::clone(&keys),
+            Arc::new(LocalEventBroker::new()),
+        )
+        .finish();
+        Self {
+            schema,
+            repos,
+            keys,
+        }
+    }
+
+    /// Builds a request authenticated with `token` if given.
+    async fn request(&self, token: Option<&str>, query: &str, variables: Value) -> Request {
+        let header = token.map(|token| format!("Bearer {}", token));
+        let user = authenticate(self.repos.user_repo.as_ref(), &self.keys, header.as_deref())
+            .await
+            .unwrap();
+        Request::new(query)
+            .variables(Variables::from_json(variables))
+            .data(user)
+    }
+
+    /// Runs a request like `index` does, authenticated with `token` if given.
+    async fn execute(&self, token: Option<&str>, query: &str, variables: Value) -> Response {
+        let request = self.request(token, query, variables).await;
+        let loader = ancestors_loader(Arc::clone(&self.repos.page_repo));
+        self.schema.execute(request.data(loader)).await
+    }
+
+    /// Runs a request that must succeed and returns its data.
+    async fn data(&self, token: Option<&str>, query: &str, variables: Value) -> Value {
+        let response = self.execute(token, query, variables).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        response.data.into_json().unwrap()
+    }
+
+    /// Creates a user and returns an access token for them.
+    async fn sign_up(&self, name: &str) -> String {
+        let user = json!({
+            "email": format!("{}@example.com", name),
+            "username": name,
+            "password": "password",
+        });
+        self.data(
+            None,
+            "mutation($user: CreateUserInput!) { createUser(user: $user) { uuid } }",
+            json!({ "user": user }),
+        )
+        .await;
+        let login = json!({ "email": name, "password": "password" });
+        let data = self
+            .data(
+                None,
+                "mutation($login: LoginUserInput!) { loginUser(login: $login) { accessToken } }",
+                json!({ "login": login }),
+            )
+            .await;
+        data["loginUser"]["accessToken"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    }
+
+    async fn user_uuid(&self, token: &str) -> Value {
+        let data = self
+            .data(Some(token), "{ currentUser { uuid } }", json!({}))
+            .await;
+        data["currentUser"]["uuid"].clone()
+    }
+
+    async fn add_member(&self, token: &str, workspace: &Value, user: &Value, role: &str) {
+        self.data(
+            Some(token),
+            "mutation($workspace: UUID!, $user: UUID!, $role: WorkspaceRole!) { \
+                addWorkspaceMember(workspaceUuid: $workspace, userUuid: $user, role: $role) { role } }",
+            json!({ "workspace": workspace, "user": user, "role": role }),
+        )
+        .await;
+    }
+
+    async fn create_workspace(&self, token: &str) -> Value {
+        let data = self
+            .data(
+                Some(token),
+                r#"mutation { createWorkspace(workspace: { name: "Notes" }) { value { uuid } } }"#,
+                json!({}),
+            )
+            .await;
+        data["createWorkspace"]["value"]["uuid"].clone()
+    }
+
+    async fn create_page(&self, token: &str, workspace: &Value, parent: Option<&Value>) -> Value {
+        let page = json!({
+            "name": "Kafka notes",
+            "workspaceUuid": workspace,
+            "parentPageUuid": parent,
+        });
+        let data = self
+            .data(
+                Some(token),
+                "mutation($page: CreatePageInput!) { createPage(page: $page) { value { uuid } } }",
+                json!({ "page": page }),
+            )
+            .await;
+        data["createPage"]["value"]["uuid"].clone()
+    }
+}
+
+#[tokio::test]
+async fn test_sign_in() {
+    let app = TestApp::new();
+    let token = app.sign_up("ada").await;
+    let data = app
+        .data(Some(&token), "{ currentUser { username } }", json!({}))
+        .await;
+    assert_eq!(data, json!({ "currentUser": { "username": "ada" } }));
+
+    let user = json!({ "email": "other@example.com", "username": "ada", "password": "pw" });
+    let response = app
+        .execute(
+            None,
+            "mutation($user: CreateUserInput!) { createUser(user: $user) { uuid } }",
+            json!({ "user": user }),
+        )
+        .await;
+    assert_eq!(
+        response.errors[0].message,
+        "A user with this username already exists"
+    );
+
+    // Refresh tokens can only be used once.
+    let login = json!({ "email": "ada@example.com", "password": "password" });
+    let data = app
+        .data(
+            None,
+            "mutation($login: LoginUserInput!) { loginUser(login: $login) { refreshToken } }",
+            json!({ "login": login }),
+        )
+        .await;
+    let refresh = json!({ "token": data["loginUser"]["refreshToken"] });
+    let query = "mutation($token: String!) { refreshToken(refreshToken: $token) { accessToken } }";
+    assert!(app.execute(None, query, refresh.clone()).await.is_ok());
+    assert_eq!(
+        app.execute(None, query, refresh).await.errors[0].message,
+        "Refresh token was already used"
+    );
+}
+
+#[tokio::test]
+async fn test_pages() {
+    let app = TestApp::new();
+    let token = app.sign_up("ada").await;
+    let workspace = app.create_workspace(&token).await;
+    let root = app.create_page(&token, &workspace, None).await;
+    let child = app.create_page(&token, &workspace, Some(&root)).await;
+
+    let query = "query($uuid: UUID!) { getPage(uuid: $uuid) { value { ancestors { uuid } } } }";
+    let data = app
+        .data(Some(&token), query, json!({ "uuid": child }))
+        .await;
+    assert_eq!(
+        data["getPage"]["value"]["ancestors"],
+        json!([{ "uuid": root }])
+    );
+    // Requests over websockets come without the per-request loaders.
+    let request = app
+        .request(Some(&token), query, json!({ "uuid": child }))
+        .await;
+    let data = app.schema.execute(request).await.data.into_json().unwrap();
+    assert_eq!(
+        data["getPage"]["value"]["ancestors"],
+        json!([{ "uuid": root }])
+    );
+
+    // Other users can't see the workspace's pages.
+    let other = app.sign_up("bob").await;
+    let response = app
+        .execute(Some(&other), query, json!({ "uuid": child }))
+        .await;
+    assert!(response.is_err());
+
+    // Trashing a page takes its children along.
+    app.data(
+        Some(&token),
+        "mutation($uuid: UUID!) { deletePage(uuid: $uuid) { value { uuid } } }",
+        json!({ "uuid": root }),
+    )
+    .await;
+    let response = app
+        .execute(Some(&token), query, json!({ "uuid": child }))
+        .await;
+    assert!(response.is_err());
+    let data = app
+        .data(
+            Some(&token),
+            "mutation($uuid: UUID!) { restorePage(uuid: $uuid) { value { children { uuid } } } }",
+            json!({ "uuid": root }),
+        )
+        .await;
+    assert_eq!(
+        data["restorePage"]["value"]["children"],
+        json!([{ "uuid": child }])
+    );
+}
+
+#[tokio::test]
+async fn test_members() {
+    let app = TestApp::new();
+    let token = app.sign_up("ada").await;
+    let owner = app.user_uuid(&token).await;
+    let workspace = app.create_workspace(&token).await;
+    let other = app.sign_up("bob").await;
+    let member = app.user_uuid(&other).await;
+    let members = "query($uuid: UUID!) { getWorkspace(uuid: $uuid) { members { role } } }";
+    let change_role = "mutation($workspace: UUID!, $user: UUID!, $role: WorkspaceRole!) { \
+        changeWorkspaceMemberRole(workspaceUuid: $workspace, userUuid: $user, role: $role) { role } }";
+    let remove = "mutation($workspace: UUID!, $user: UUID!) { \
+        removeWorkspaceMember(workspaceUuid: $workspace, userUuid: $user) }";
+
+    app.add_member(&token, &workspace, &member, "VIEWER").await;
+    let response = app
+        .execute(
+            Some(&token),
+            "mutation($workspace: UUID!, $user: UUID!) { \
+                addWorkspaceMember(workspaceUuid: $workspace, userUuid: $user, role: EDITOR) { role } }",
+            json!({ "workspace": workspace, "user": member }),
+        )
+        .await;
+    assert_eq!(
+        response.errors[0].message,
+        "User is already a member of this workspace"
+    );
+
+    // Only owners manage members.
+    let vars = json!({ "workspace": workspace, "user": owner, "role": "VIEWER" });
+    let response = app.execute(Some(&other), change_role, vars.clone()).await;
+    assert_eq!(
+        response.errors[0].message,
+        "You don't have access to this workspace"
+    );
+
+    // The last owner can neither step down nor leave.
+    let response = app.execute(Some(&token), change_role, vars.clone()).await;
+    assert_eq!(
+        response.errors[0].message,
+        "A workspace must have at least one owner"
+    );
+    let response = app.execute(Some(&token), remove, vars.clone()).await;
+    assert_eq!(
+        response.errors[0].message,
+        "A workspace must have at least one owner"
+    );
+
+    // Once there's another owner, they can.
+    let promote = json!({ "workspace": workspace, "user": member, "role": "OWNER" });
+    app.data(Some(&token), change_role, promote).await;
+    app.data(Some(&token), change_role, vars).await;
+    let data = app
+        .data(Some(&other), members, json!({ "uuid": workspace }))
+        .await;
+    let mut roles = data["getWorkspace"]["members"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|member| member["role"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    roles.sort();
+    assert_eq!(roles, ["OWNER", "VIEWER"]);
+
+    let vars = json!({ "workspace": workspace, "user": owner });
+    assert_eq!(
+        app.data(Some(&other), remove, vars.clone()).await,
+        json!({ "removeWorkspaceMember": true })
+    );
+    let response = app.execute(Some(&other), remove, vars).await;
+    assert_eq!(
+        response.errors[0].message,
+        "User is not a member of this workspace"
+    );
+}
+
+#[tokio::test]
+async fn test_subscriptions() {
+    let app = TestApp::new();
+    let token = app.sign_up("ada").await;
+    let workspace = app.create_workspace(&token).await;
+    let other = app.sign_up("bob").await;
+    let member = app.user_uuid(&other).await;
+    app.add_member(&token, &workspace, &member, "VIEWER").await;
+
+    let request = app
+        .request(
+            Some(&other),
+            "subscription($uuid: UUID!) { workspaceChanged(workspaceUuid: $uuid) { kind } }",
+            json!({ "uuid": workspace }),
+        )
+        .await;
+    let mut stream = app.schema.execute_stream(request);
+    // Polling the stream first subscribes it before the change is published.
+    let (response, _) = tokio::join!(stream.next(), app.create_page(&token, &workspace, None));
+    assert_eq!(
+        response.unwrap().data.into_json().unwrap(),
+        json!({ "workspaceChanged": { "kind": "PAGE_CREATED" } })
+    );
+
+    // Removed members stop receiving changes.
+    app.data(
+        Some(&token),
+        "mutation($workspace: UUID!, $user: UUID!) { \
+            removeWorkspaceMember(workspaceUuid: $workspace, userUuid: $user) }",
+        json!({ "workspace": workspace, "user": member }),
+    )
+    .await;
+    let (response, _) = tokio::join!(stream.next(), app.create_page(&token, &workspace, None));
+    assert_eq!(
+        response.unwrap().errors[0].message,
+        "You don't have access to this workspace"
+    );
+    assert!(stream.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_content() {
+    let app = TestApp::new();
+    let token = app.sign_up("ada").await;
+    let workspace = app.create_workspace(&token).await;
+    let page = app.create_page(&token, &workspace, None).await;
+    let data = app
+        .data(
+            Some(&token),
+            "mutation($page: UUID!) { insertSlot(slot: { pageUuid: $page }) { value { uuid } } }",
+            json!({ "page": page }),
+        )
+        .await;
+    let slot = &data["insertSlot"]["value"]["uuid"];
+    let insert_atom = "mutation($slot: UUID!, $data: String) { \
+        insertAtom(atom: { slotUuid: $slot, typ: TEXT, data: $data }) { errors { message } } }";
+    for text in ["Tune the consumer lag alerts", "Add a third broker"] {
+        let data = json!({ "text": text, "marks": [] }).to_string();
+        app.data(
+            Some(&token),
+            insert_atom,
+            json!({ "slot": slot, "data": data }),
+        )
+        .await;
+    }
+
+    let search = "query($workspace: UUID!, $query: String!) { \
+        search(workspaceUuid: $workspace, query: $query) { \
+            edges { node { snippet highlights { start end } slot { uuid } } } } }";
+    let data = app
+        .data(
+            Some(&token),
+            search,
+            json!({ "workspace": workspace, "query": "broker or kafka" }),
+        )
+        .await;
+    assert_eq!(
+        data["search"]["edges"],
+        json!([
+            { "node": { "snippet": "Kafka notes", "highlights": [{ "start": 0, "end": 5 }], "slot": null } },
+            { "node": { "snippet": "Add a third broker", "highlights": [{ "start": 12, "end": 18 }], "slot": { "uuid": slot } } },
+        ])
+    );
+
+    // Changes by another user start a new revision, which can be undone by
+    // restoring the previous one.
+    let other = app.sign_up("bob").await;
+    let other_uuid = app.user_uuid(&other).await;
+    app.add_member(&token, &workspace, &other_uuid, "EDITOR")
+        .await;
+    app.data(
+        Some(&other),
+        "mutation($slot: UUID!) { deleteAtom(slotUuid: $slot, idx: 0) { errors { message } } }",
+        json!({ "slot": slot }),
+    )
+    .await;
+    app.data(
+        Some(&token),
+        "mutation($page: UUID!) { restorePageRevision(pageUuid: $page, number: 1) { errors { message } } }",
+        json!({ "page": page }),
+    )
+    .await;
+    let revisions = "query($page: UUID!) { getPage(uuid: $page) { value { \
+        revisions { edges { node { number content { slots { atoms { uuid } } } } } } } } }";
+    let data = app
+        .data(Some(&token), revisions, json!({ "page": page }))
+        .await;
+    let atom_counts = data["getPage"]["value"]["revisions"]["edges"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|edge| {
+            let node = &edge["node"];
+            let atoms = node["content"]["slots"][0]["atoms"].as_array().unwrap();
+            (node["number"].as_i64().unwrap(), atoms.len())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(atom_counts, [(3, 2), (2, 1), (1, 2)]);
+}
+
+#[tokio::test]
+async fn test_edit_text() {
+    let app = TestApp::new();
+    let token = app.sign_up("ada").await;
+    let workspace = app.create_workspace(&token).await;
+    let page = app.create_page(&token, &workspace, None).await;
+    let data = app
+        .data(
+            Some(&token),
+            "mutation($page: UUID!) { insertSlot(slot: { pageUuid: $page }) { value { uuid } } }",
+            json!({ "page": page }),
+        )
+        .await;
+    let slot = data["insertSlot"]["value"]["uuid"].clone();
+    let data = app
+        .data(
+            Some(&token),
+            "mutation($slot: UUID!, $data: String) { \
+                insertAtom(atom: { slotUuid: $slot, typ: TEXT, data: $data }) { value { uuid } } }",
+            json!({ "slot": slot, "data": json!({ "text": "abc", "marks": [] }).to_string() }),
+        )
+        .await;
+    let atom = data["insertAtom"]["value"]["uuid"].clone();
+    let edit_text = "mutation($edit: EditTextInput!) { \
+        editText(edit: $edit) { errors { message } value { version } } }";
+    let edit = |base: i32, pos: u64, len: u64| {
+        json!({ "edit": {
+            "slotUuid": slot,
+            "atomUuid": atom,
+            "baseVersion": base,
+            "ops": [{ "delete": { "pos": pos, "len": len } }],
+        } })
+    };
+
+    // GraphQL accepts integers up to `i64::MAX`.
+    let data = app
+        .data(Some(&token), edit_text, edit(0, 1, i64::MAX as u64))
+        .await;
+    assert_eq!(
+        data["editText"]["errors"],
+        json!([{ "message": format!("Edit at {} is outside of the text", 1u64 << 63) }])
+    );
+    let data = app.data(Some(&token), edit_text, edit(0, 0, 2)).await;
+    assert_eq!(data["editText"]["value"], json!({ "version": 1 }));
+    // Ops are checked against the text they were made against.
+    let data = app.data(Some(&token), edit_text, edit(0, 2, 1)).await;
+    assert_eq!(data["editText"]["value"], json!({ "version": 2 }));
+    let data = app.data(Some(&token), edit_text, edit(2, 0, 1)).await;
+    assert_eq!(
+        data["editText"]["errors"],
+        json!([{ "message": "Edit at 1 is outside of the text" }])
+    );
+}
+
+#[tokio::test]
+async fn test_move_slots() {
+    let app = TestApp::new();
+    let token = app.sign_up("ada").await;
+    let workspace = app.create_workspace(&token).await;
+    let page = app.create_page(&token, &workspace, None).await;
+    let mut slots = vec![];
+    for _ in 0..3 {
+        let data = app
+            .data(
+                Some(&token),
+                "mutation($page: UUID!, $after: UUID) { \
+                    insertSlot(slot: { pageUuid: $page, after: $after }) { value { uuid } } }",
+                json!({ "page": page, "after": slots.last() }),
+            )
+            .await;
+        slots.push(data["insertSlot"]["value"]["uuid"].clone());
+    }
+
+    // Moving the last slot right after the first one over and over halves the
+    // room left between them, until the keys have to be rebalanced.
+    let move_slot = "mutation($uuid: UUID!, $after: UUID) { \
+        moveSlot(uuid: $uuid, after: $after) { value { order } } }";
+    let mut retries = 0;
+    for _ in 0..100 {
+        let variables = json!({ "uuid": slots[2], "after": slots[0] });
+        let mut response = app
+            .execute(Some(&token), move_slot, variables.clone())
+            .await;
+        if let Some(error) = response.errors.first() {
+            assert_eq!(
+                error.extensions.as_ref().unwrap().get("code"),
+                Some(&503.into())
+            );
+            retries += 1;
+            tokio::task::yield_now().await;
+            response = app.execute(Some(&token), move_slot, variables).await;
+        }
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        slots.swap(1, 2);
+    }
+    assert!(retries <= 1);
+
+    let data = app
+        .data(
+            Some(&token),
+            "query($page: UUID!) { getPage(uuid: $page) { value { slots { uuid order } } } }",
+            json!({ "page": page }),
+        )
+        .await;
+    let page_slots = data["getPage"]["value"]["slots"].as_array().unwrap();
+    let uuids = page_slots
+        .iter()
+        .map(|slot| &slot["uuid"])
+        .collect::<Vec<_>>();
+    assert_eq!(uuids, slots.iter().collect::<Vec<_>>());
+    assert!(page_slots
+        .iter()
+        .all(|slot| slot["order"].as_str().unwrap().len() <= 16));
+}
+
+#[tokio::test]
+async fn test_update_workspace() {
+    let app = TestApp::new();
+    let token = app.sign_up("ada").await;
+    let workspace = app.create_workspace(&token).await;
+    let update = "mutation($uuid: UUID!, $version: Int!, $name: String) { \
+        updateWorkspace(workspace: { uuid: $uuid, version: $version, name: $name }) { \
+            errors { message } value { name version } } }";
+    let data = app
+        .data(
+            Some(&token),
+            update,
+            json!({ "uuid": workspace, "version": 0, "name": "Kafka" }),
+        )
+        .await;
+    assert_eq!(
+        data["updateWorkspace"]["value"],
+        json!({ "name": "Kafka", "version": 1 })
+    );
+
+    // An update based on the old version conflicts with the rename.
+    let response = app
+        .execute(
+            Some(&token),
+            update,
+            json!({ "uuid": workspace, "version": 0, "name": "Notes" }),
+        )
+        .await;
+    let extensions = response.errors[0].extensions.as_ref().unwrap();
+    assert_eq!(extensions.get("code"), Some(&409.into()));
+    assert_eq!(extensions.get("version"), Some(&1.into()));
+
+    let data = app
+        .data(
+            Some(&token),
+            update,
+            json!({ "uuid": workspace, "version": 1, "name": "" }),
+        )
+        .await;
+    assert_eq!(
+        data["updateWorkspace"]["errors"],
+        json!([{ "message": "Name is required" }])
+    );
+
+    // Only images are stored, other files could run scripts where they're served.
+    let update_image = "mutation($uuid: UUID!, $image: Upload) { \
+        updateWorkspace(workspace: { uuid: $uuid, version: 1, image: $image }) { \
+            value { version } } }";
+    let dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+    std::fs::create_dir_all(&dir).unwrap();
+    for (filename, content) in [
+        ("x.html", b"<script></script>".as_slice()),
+        ("x.png", b"<script></script>".as_slice()),
+        ("x.png", b"\x89PNG\r\n\x1a\n".as_slice()),
+    ] {
+        let path = dir.join(filename);
+        std::fs::write(&path, content).unwrap();
+        let mut request = app
+            .request(
+                Some(&token),
+                update_image,
+                json!({ "uuid": workspace, "image": null }),
+            )
+            .await;
+        request.set_upload(
+            "variables.image",
+            UploadValue {
+                filename: filename.to_string(),
+                content_type: None,
+                content: std::fs::File::open(&path).unwrap(),
+            },
+        );
+        let response = app.schema.execute(request).await;
+        if content.starts_with(b"<") {
+            assert_eq!(
+                response.errors[0].message,
+                format!(
+                    "Invalid image {}, only png, jpeg, gif and webp images are accepted",
+                    filename
+                )
+            );
+        } else {
+            assert!(response.errors.is_empty(), "{:?}", response.errors);
+        }
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// Pages through the revisions of `page` with the connection arguments
+/// `args`, and returns the revision numbers with the page info.
+async fn revisions(app: &TestApp, token: &str, page: &Value, args: Value) -> (Vec<i64>, Value) {
+    let query = "query($page: UUID!, $first: Int, $last: Int, $after: String, $before: String) { \
+        getPage(uuid: $page) { value { \
+            revisions(first: $first, last: $last, after: $after, before: $before) { \
+                edges { node { number } } \
+                pageInfo { hasPreviousPage hasNextPage startCursor endCursor } } } } }";
+    let mut variables = args;
+    variables["page"] = page.clone();
+    let data = app.data(Some(token), query, variables).await;
+    let connection = &data["getPage"]["value"]["revisions"];
+    let numbers = connection["edges"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|edge| edge["node"]["number"].as_i64().unwrap())
+        .collect();
+    (numbers, connection["pageInfo"].clone())
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres database, see TEST_DATABASE_URL"]
+async fn test_revisions_connection() {
+    let app = TestApp::with_repos(Repos::postgresql(
+        test_db(),
+        Arc::new(MemoryImagesRepo::new()),
+    ));
+    // Concurrent tests would wait on each other's unique emails and usernames.
+    let name = || Uuid::new_v4().simple().to_string();
+    let (ada, bob) = (app.sign_up(&name()).await, app.sign_up(&name()).await);
+    let workspace = app.create_workspace(&ada).await;
+    let bob_uuid = app.user_uuid(&bob).await;
+    app.add_member(&ada, &workspace, &bob_uuid, "EDITOR").await;
+    let page = app.create_page(&ada, &workspace, None).await;
+    // Alternating users start a new revision with every change.
+    for token in [&bob, &ada, &bob, &ada] {
+        app.data(
+            Some(token),
+            "mutation($page: UUID!) { insertSlot(slot: { pageUuid: $page }) { value { uuid } } }",
+            json!({ "page": page }),
+        )
+        .await;
+    }
+
+    let (numbers, info) = revisions(&app, &ada, &page, json!({ "first": 2 })).await;
+    assert_eq!(numbers, [5, 4]);
+    assert_eq!(
+        (&info["hasPreviousPage"], &info["hasNextPage"]),
+        (&json!(false), &json!(true))
+    );
+    let (numbers, info) = revisions(
+        &app,
+        &ada,
+        &page,
+        json!({ "first": 2, "after": info["endCursor"] }),
+    )
+    .await;
+    assert_eq!(numbers, [3, 2]);
+    let (numbers, info) = revisions(
+        &app,
+        &ada,
+        &page,
+        json!({ "first": 2, "after": info["endCursor"] }),
+    )
+    .await;
+    assert_eq!(numbers, [1]);
+    assert_eq!(
+        (&info["hasPreviousPage"], &info["hasNextPage"]),
+        (&json!(true), &json!(false))
+    );
+
+    let (numbers, info) = revisions(&app, &ada, &page, json!({ "last": 2 })).await;
+    assert_eq!(numbers, [2, 1]);
+    assert_eq!(
+        (&info["hasPreviousPage"], &info["hasNextPage"]),
+        (&json!(true), &json!(false))
+    );
+    let (numbers, info) = revisions(
+        &app,
+        &ada,
+        &page,
+        json!({ "last": 2, "before": info["startCursor"] }),
+    )
+    .await;
+    assert_eq!(numbers, [4, 3]);
+    assert_eq!(
+        (&info["hasPreviousPage"], &info["hasNextPage"]),
+        (&json!(true), &json!(true))
+    );
+}
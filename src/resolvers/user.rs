@@ -1,14 +1,21 @@
 use std::sync::Arc;
 
-use async_graphql::{Context, ErrorExtensions, FieldError, InputObject, Object, Result, ResultExt};
+use async_graphql::{
+    Context, ErrorExtensions, FieldError, InputObject, Object, Result, ResultExt, SimpleObject,
+};
+use chrono::Duration;
 use secrecy::Secret;
 use thiserror::Error;
 use uuid::Uuid;
 
 use crate::{
-    models::user::User,
-    repos::traits::UserRepo,
-    utils::{config::Config, jwt::generate_jwt},
+    models::{user::User, RefreshToken},
+    repos::traits::{RefreshTokenRepo, UserRepo},
+    utils::{
+        config::Config,
+        guards::LoggedInGuard,
+        jwt::{generate_jwt, KeyStore},
+    },
 };
 
 #[derive(Debug, Error)]
@@ -21,6 +28,12 @@ pub enum UserMutationError {
     UserNotFound,
     #[error("Invalid password")]
     InvalidPassword,
+    #[error("Invalid refresh token")]
+    InvalidRefreshToken,
+    #[error("Refresh token expired")]
+    RefreshTokenExpired,
+    #[error("Refresh token was already used")]
+    RefreshTokenReused,
 }
 
 impl ErrorExtensions for UserMutationError {
@@ -33,6 +46,9 @@ impl ErrorExtensions for UserMutationError {
             }
             UserMutationError::UserNotFound => e.set("code", 404),
             UserMutationError::InvalidPassword => e.set("code", 400),
+            UserMutationError::InvalidRefreshToken
+            | UserMutationError::RefreshTokenExpired
+            | UserMutationError::RefreshTokenReused => e.set("code", 401),
         })
     }
 }
@@ -42,13 +58,14 @@ pub struct UserQuery;
 
 #[Object]
 impl UserQuery {
+    #[graphql(guard = "LoggedInGuard")]
     pub async fn get_user(&self, ctx: &Context<'_>, uuid: Uuid) -> Option<User> {
         let user_repo = ctx.data::<Arc<dyn UserRepo>>().unwrap();
         user_repo.get_user_by_uuid(&uuid).await.unwrap()
     }
 
     pub async fn current_user(&self, ctx: &Context<'_>) -> Option<User> {
-        ctx.data::<Option<User>>().unwrap().clone()
+        ctx.data_opt::<Option<User>>().cloned().flatten()
     }
 }
 
@@ -67,6 +84,45 @@ pub struct LoginUserInput {
     password: String,
 }
 
+#[derive(SimpleObject)]
+pub struct AuthTokens {
+    /// A short-lived token to send in the `Authorization` header.
+    access_token: String,
+    /// Lifetime of the access token, in seconds.
+    expires_in: i64,
+    /// A long-lived, single-use token to obtain new access tokens.
+    refresh_token: String,
+}
+
+/// Signs a new access token for `user` and rotates a refresh token within
+/// `family_uuid`.
+async fn issue_tokens(ctx: &Context<'_>, user: &User, family_uuid: Uuid) -> Result<AuthTokens> {
+    let refresh_token_repo = ctx.data_unchecked::<Arc<dyn RefreshTokenRepo>>();
+    let config = ctx.data_unchecked::<Arc<Config>>();
+    let keys = ctx.data_unchecked::<Arc<KeyStore>>();
+
+    let access_token = generate_jwt(
+        &keys.keys(),
+        user,
+        Duration::seconds(config.access_token_ttl),
+    )
+    .map_err(|e| UserMutationError::from(anyhow::Error::from(e)).extend())?;
+    let (stored, refresh_token) = RefreshToken::new(
+        user.uuid,
+        family_uuid,
+        Duration::seconds(config.refresh_token_ttl),
+    );
+    refresh_token_repo
+        .create_refresh_token(&stored)
+        .await
+        .map_err(|e| UserMutationError::from(e).extend())?;
+    Ok(AuthTokens {
+        access_token,
+        expires_in: config.access_token_ttl,
+        refresh_token,
+    })
+}
+
 #[derive(Default)]
 pub struct UserMutation;
 
@@ -95,9 +151,8 @@ impl UserMutation {
         Ok(user)
     }
 
-    pub async fn login_user(&self, ctx: &Context<'_>, login: LoginUserInput) -> Result<String> {
+    pub async fn login_user(&self, ctx: &Context<'_>, login: LoginUserInput) -> Result<AuthTokens> {
         let user_repo = ctx.data::<Arc<dyn UserRepo>>().unwrap();
-        let config = ctx.data::<Config>().unwrap();
         let user = user_repo
             .get_user_by_login(&login.email)
             .await
@@ -107,8 +162,67 @@ impl UserMutation {
             return Err(UserMutationError::InvalidPassword.extend());
         }
 
-        let token = generate_jwt(&config.jwt_secret, &user)
+        issue_tokens(ctx, &user, Uuid::new_v4()).await
+    }
+
+    pub async fn refresh_token(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(secret)] refresh_token: String,
+    ) -> Result<AuthTokens> {
+        let user_repo = ctx.data_unchecked::<Arc<dyn UserRepo>>();
+        let refresh_token_repo = ctx.data_unchecked::<Arc<dyn RefreshTokenRepo>>();
+        let stored = refresh_token_repo
+            .get_refresh_token_by_hash(&RefreshToken::hash(&refresh_token))
+            .await
+            .map_err(|e| UserMutationError::from(e).extend())?
+            .ok_or_else(|| UserMutationError::InvalidRefreshToken.extend())?;
+
+        // A token that was already rotated is being replayed, so the whole
+        // session is considered compromised.
+        if stored.revoked_at.is_some()
+            || !refresh_token_repo
+                .revoke_refresh_token(&stored.uuid)
+                .await
+                .map_err(|e| UserMutationError::from(e).extend())?
+        {
+            refresh_token_repo
+                .revoke_refresh_token_family(&stored.family_uuid)
+                .await
+                .map_err(|e| UserMutationError::from(e).extend())?;
+            return Err(UserMutationError::RefreshTokenReused.extend());
+        }
+        if stored.is_expired() {
+            return Err(UserMutationError::RefreshTokenExpired.extend());
+        }
+
+        let user = user_repo
+            .get_user_by_uuid(&stored.user_uuid)
+            .await
+            .map_err(|e| UserMutationError::from(e).extend())?
+            .ok_or_else(|| UserMutationError::UserNotFound.extend())?;
+        issue_tokens(ctx, &user, stored.family_uuid).await
+    }
+
+    pub async fn logout(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(secret)] refresh_token: String,
+    ) -> Result<bool> {
+        let refresh_token_repo = ctx.data_unchecked::<Arc<dyn RefreshTokenRepo>>();
+        let stored = refresh_token_repo
+            .get_refresh_token_by_hash(&RefreshToken::hash(&refresh_token))
+            .await
             .map_err(|e| UserMutationError::from(e).extend())?;
-        Ok(token)
+        match stored {
+            Some(stored) => {
+                refresh_token_repo
+                    .revoke_refresh_token_family(&stored.family_uuid)
+                    .await
+                    .map_err(|e| UserMutationError::from(e).extend())?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
     }
 }
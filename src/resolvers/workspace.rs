@@ -1,4 +1,4 @@
-use std::{io::Read, path::Path, sync::Arc};
+use std::{io::Read, sync::Arc};
 use thiserror::Error;
 
 use async_graphql::{
@@ -7,11 +7,16 @@ use async_graphql::{
 use uuid::Uuid;
 
 use crate::{
-    models::{Page, Workspace},
-    repos::traits::{ImagesRepo, WorkspaceRepo},
+    models::{
+        page::sort_tree, workspace_member::MemberChange, Page, User, Workspace, WorkspaceMember,
+        WorkspaceRole,
+    },
+    repos::traits::{ImagesRepo, PageRepo, UnitOfWorkRepo, UserRepo, WorkspaceRepo},
     utils::{
-        img::generate_image,
-        types::{InputError, WithError},
+        guards::{current_user, GuardError, LoggedInGuard, WorkspaceRoleGuard},
+        img::{generate_image, image_extension},
+        trash::delete_images,
+        types::{ConflictError, InputError, WithError},
     },
 };
 
@@ -19,8 +24,18 @@ use crate::{
 pub enum WorkspaceMutationError {
     #[error("generic error: {0}")]
     DefaultError(#[from] anyhow::Error),
-    #[error("Invalid image extension: {0}")]
-    InvalidImageExtension(String),
+    #[error("Invalid image {0}, only png, jpeg, gif and webp images are accepted")]
+    InvalidImage(String),
+    #[error("User not found")]
+    UserNotFound,
+    #[error("User is already a member of this workspace")]
+    MemberAlreadyExists,
+    #[error("User is not a member of this workspace")]
+    MemberNotFound,
+    #[error("Workspace not found in the trash")]
+    NotInTrash,
+    #[error("A workspace must have at least one owner")]
+    LastOwner,
 }
 
 impl ErrorExtensions for WorkspaceMutationError {
@@ -30,6 +45,7 @@ impl ErrorExtensions for WorkspaceMutationError {
                 e.set("code", 500);
                 e.set("message", err.to_string());
             }
+            Self::UserNotFound | Self::MemberNotFound | Self::NotInTrash => e.set("code", 404),
             _ => e.set("code", 400),
         })
     }
@@ -40,15 +56,31 @@ pub struct WorkspaceQuery;
 
 #[Object]
 impl WorkspaceQuery {
-    pub async fn get_all_workspaces(&self, ctx: &Context<'_>) -> Vec<Workspace> {
+    #[graphql(guard = "LoggedInGuard")]
+    pub async fn get_all_workspaces(&self, ctx: &Context<'_>) -> Result<Vec<Workspace>> {
         let repo = ctx.data_unchecked::<Arc<dyn WorkspaceRepo>>();
-        repo.get_all_workspaces().await.unwrap()
+        let user = current_user(ctx)?;
+        repo.get_user_workspaces(&user.uuid)
+            .await
+            .map_err(|e| WorkspaceMutationError::from(e).extend())
     }
 
+    #[graphql(guard = "WorkspaceRoleGuard::new(uuid, WorkspaceRole::Viewer)")]
     pub async fn get_workspace(&self, ctx: &Context<'_>, uuid: Uuid) -> Option<Workspace> {
         let workspace_repo = ctx.data_unchecked::<Arc<dyn WorkspaceRepo>>();
         workspace_repo.get_workspace_by_uuid(&uuid).await.unwrap()
     }
+
+    /// The workspaces in the trash owned by the current user.
+    #[graphql(guard = "LoggedInGuard")]
+    pub async fn get_trashed_workspaces(&self, ctx: &Context<'_>) -> Result<Vec<Workspace>> {
+        let workspace_repo = ctx.data_unchecked::<Arc<dyn WorkspaceRepo>>();
+        let user = current_user(ctx)?;
+        workspace_repo
+            .get_trashed_workspaces(&user.uuid)
+            .await
+            .map_err(|e| WorkspaceMutationError::from(e).extend())
+    }
 }
 
 #[derive(InputObject)]
@@ -57,83 +89,311 @@ pub struct CreateWorkspaceInput {
     image: Option<Upload>,
 }
 
+#[derive(InputObject)]
+pub struct UpdateWorkspaceInput {
+    uuid: Uuid,
+    /// The version of the workspace the update is based on.
+    version: i32,
+    name: Option<String>,
+    /// A new image replacing the current one.
+    image: Option<Upload>,
+}
+
+fn name_required() -> WithError<Workspace> {
+    WithError {
+        errors: vec![InputError {
+            field: "name".to_string(),
+            message: "Name is required".to_string(),
+        }],
+        value: None,
+    }
+}
+
+/// Uploads a workspace image with `images_repo` and returns its url. Every
+/// upload gets a new name, so a replaced image is never overwritten while
+/// it's still in use.
+async fn upload_workspace_image(
+    ctx: &Context<'_>,
+    images_repo: &dyn ImagesRepo,
+    workspace_uuid: &Uuid,
+    image: Upload,
+) -> Result<String> {
+    let mut image = image.value(ctx)?;
+    let buf = &mut Vec::new();
+    image.content.read_to_end(buf)?;
+    let image_extension = image_extension(&image.filename, buf)
+        .ok_or_else(|| WorkspaceMutationError::InvalidImage(image.filename.clone()).extend())?;
+    let image_name = format!(
+        "images/workspaces/{}/{}.{}",
+        workspace_uuid,
+        Uuid::new_v4(),
+        image_extension
+    );
+    Ok(images_repo.upload_image(&image_name, buf).await?)
+}
+
 #[derive(Default)]
 pub struct WorkspaceMutation;
 
 #[Object]
 impl WorkspaceMutation {
     // TODO: collapse multiple spaces into one
+    #[graphql(guard = "LoggedInGuard")]
     pub async fn create_workspace(
         &self,
         ctx: &Context<'_>,
         workspace: CreateWorkspaceInput,
     ) -> Result<WithError<Workspace>> {
-        let workspace_repo = ctx.data_unchecked::<Arc<dyn WorkspaceRepo>>();
-        let s3_images_repo = ctx.data_unchecked::<Arc<dyn ImagesRepo>>();
+        let user = current_user(ctx)?;
         let workspace_uuid = Uuid::new_v4();
 
         if workspace.name.is_empty() {
-            return Ok(WithError {
-                errors: vec![InputError {
-                    field: "name".to_string(),
-                    message: "Name is required".to_string(),
-                }],
-                value: None,
-            });
+            return Ok(name_required());
         }
 
+        // Deletes the uploaded image again if the workspace can't be created.
+        let unit_of_work_repo = ctx.data_unchecked::<Arc<dyn UnitOfWorkRepo>>();
+        let images = unit_of_work_repo.pending_images();
         let workspace_image: String = match workspace.image {
-            Some(image) => {
-                let mut image = image.value(ctx)?;
-                let image_extension = Path::new(&image.filename)
-                    .extension()
-                    .ok_or(WorkspaceMutationError::InvalidImageExtension(
-                        "".to_string(),
-                    ))?
-                    .to_str()
-                    .unwrap();
-
-                // TODO: check if image_extension is valid
-
-                // Upload image to S3
-                let image_name =
-                    format!("images/workspaces/{}.{}", workspace_uuid, image_extension);
-                let mut buf = &mut Vec::new();
-                image.content.read_to_end(&mut buf)?;
-
-                s3_images_repo.upload_image(&image_name, &buf).await?
-            }
+            Some(image) => upload_workspace_image(ctx, &images, &workspace_uuid, image).await?,
             None => {
                 let image_name = format!("images/workspaces/{}.png", workspace_uuid);
                 let data = generate_image(&workspace.name).await;
-                s3_images_repo.upload_image(&image_name, &data).await?
+                images.upload_image(&image_name, &data).await?
             }
         };
         let workspace = Workspace::new(&workspace.name, &workspace_image);
-        workspace_repo.create_workspace(&workspace).await?;
+        let unit_of_work = unit_of_work_repo.begin(images).await?;
+        if let Err(err) = unit_of_work
+            .workspace_repo()
+            .create_workspace(&workspace, &user.uuid)
+            .await
+        {
+            unit_of_work.rollback().await?;
+            return Err(WorkspaceMutationError::from(err).extend());
+        }
+        unit_of_work.commit().await?;
         Ok(workspace.into())
     }
 
+    /// Renames a workspace or replaces its image. Fails with a conflict when
+    /// the workspace was changed by someone else since `version`.
+    #[graphql(guard = "WorkspaceRoleGuard::new(workspace.uuid, WorkspaceRole::Owner)")]
+    pub async fn update_workspace(
+        &self,
+        ctx: &Context<'_>,
+        workspace: UpdateWorkspaceInput,
+    ) -> Result<WithError<Workspace>> {
+        let workspace_repo = ctx.data_unchecked::<Arc<dyn WorkspaceRepo>>();
+        let images_repo = ctx.data_unchecked::<Arc<dyn ImagesRepo>>();
+
+        // The guard already found the workspace, so it can only be missing if
+        // it was trashed since. Answer the way the guard would have.
+        let mut existing = workspace_repo
+            .get_workspace_by_uuid(&workspace.uuid)
+            .await?
+            .ok_or_else(|| GuardError::Forbidden.extend())?;
+        if existing.version != workspace.version {
+            let version = existing.version;
+            return Err(ConflictError {
+                kind: "workspace",
+                version,
+            }
+            .extend());
+        }
+
+        if let Some(name) = workspace.name {
+            if name.is_empty() {
+                return Ok(name_required());
+            }
+            existing.name = name;
+        }
+        let old_image = existing.image.clone();
+        if let Some(image) = workspace.image {
+            existing.image =
+                upload_workspace_image(ctx, images_repo.as_ref(), &existing.uuid, image).await?;
+        }
+
+        let updated = match workspace_repo.update_workspace(&existing).await? {
+            Some(updated) => updated,
+            None => {
+                // Someone else updated the workspace since it was loaded.
+                if existing.image != old_image {
+                    images_repo.delete_image(&existing.image).await?;
+                }
+                let current = workspace_repo
+                    .get_workspace_by_uuid(&workspace.uuid)
+                    .await?
+                    .ok_or_else(|| GuardError::Forbidden.extend())?;
+                return Err(ConflictError {
+                    kind: "workspace",
+                    version: current.version,
+                }
+                .extend());
+            }
+        };
+        if updated.image != old_image {
+            delete_images(images_repo.as_ref(), &[old_image]).await;
+        }
+        Ok(updated.into())
+    }
+
+    /// Moves a workspace to the trash, from where its owners can restore it
+    /// until it's purged.
+    #[graphql(guard = "WorkspaceRoleGuard::new(uuid, WorkspaceRole::Owner)")]
     pub async fn delete_workspace(&self, ctx: &Context<'_>, uuid: Uuid) -> Result<bool> {
         let workspace_repo = ctx.data_unchecked::<Arc<dyn WorkspaceRepo>>();
-        let s3_images_repo = ctx.data_unchecked::<Arc<dyn ImagesRepo>>();
-        let workspace = workspace_repo.get_workspace_by_uuid(&uuid).await?;
-        if let Some(workspace) = workspace {
-            s3_images_repo.delete_image(&workspace.image).await?;
-            workspace_repo.delete_workspace(&workspace.uuid).await?;
-            Ok(true)
-        } else {
-            Ok(false)
+        Ok(workspace_repo.trash_workspace(&uuid).await?.is_some())
+    }
+
+    #[graphql(guard = "LoggedInGuard")]
+    pub async fn restore_workspace(&self, ctx: &Context<'_>, uuid: Uuid) -> Result<Workspace> {
+        let workspace_repo = ctx.data_unchecked::<Arc<dyn WorkspaceRepo>>();
+        find_trashed_workspace(ctx, &uuid).await?;
+        workspace_repo
+            .restore_workspace(&uuid)
+            .await?
+            .ok_or_else(|| WorkspaceMutationError::NotInTrash.extend())
+    }
+
+    /// Permanently deletes a workspace in the trash, together with its pages
+    /// and images.
+    #[graphql(guard = "LoggedInGuard")]
+    pub async fn purge_workspace(&self, ctx: &Context<'_>, uuid: Uuid) -> Result<bool> {
+        let workspace_repo = ctx.data_unchecked::<Arc<dyn WorkspaceRepo>>();
+        let images_repo = ctx.data_unchecked::<Arc<dyn ImagesRepo>>();
+        find_trashed_workspace(ctx, &uuid).await?;
+        let images = workspace_repo.purge_workspace(&uuid).await?;
+        delete_images(images_repo.as_ref(), &images).await;
+        Ok(true)
+    }
+
+    #[graphql(guard = "WorkspaceRoleGuard::new(workspace_uuid, WorkspaceRole::Owner)")]
+    pub async fn add_workspace_member(
+        &self,
+        ctx: &Context<'_>,
+        workspace_uuid: Uuid,
+        user_uuid: Uuid,
+        role: WorkspaceRole,
+    ) -> Result<WorkspaceMember> {
+        let workspace_repo = ctx.data_unchecked::<Arc<dyn WorkspaceRepo>>();
+        let user_repo = ctx.data_unchecked::<Arc<dyn UserRepo>>();
+        if user_repo.get_user_by_uuid(&user_uuid).await?.is_none() {
+            return Err(WorkspaceMutationError::UserNotFound.extend());
         }
+        if workspace_repo
+            .get_member_role(&workspace_uuid, &user_uuid)
+            .await?
+            .is_some()
+        {
+            return Err(WorkspaceMutationError::MemberAlreadyExists.extend());
+        }
+        let member = WorkspaceMember::new(user_uuid, workspace_uuid, role);
+        workspace_repo.add_member(&member).await?;
+        Ok(member)
+    }
+
+    #[graphql(guard = "WorkspaceRoleGuard::new(workspace_uuid, WorkspaceRole::Owner)")]
+    pub async fn change_workspace_member_role(
+        &self,
+        ctx: &Context<'_>,
+        workspace_uuid: Uuid,
+        user_uuid: Uuid,
+        role: WorkspaceRole,
+    ) -> Result<WorkspaceMember> {
+        let workspace_repo = ctx.data_unchecked::<Arc<dyn WorkspaceRepo>>();
+        let member = WorkspaceMember::new(user_uuid, workspace_uuid, role);
+        member_changed(workspace_repo.update_member(&member).await)?;
+        Ok(member)
+    }
+
+    #[graphql(guard = "WorkspaceRoleGuard::new(workspace_uuid, WorkspaceRole::Owner)")]
+    pub async fn remove_workspace_member(
+        &self,
+        ctx: &Context<'_>,
+        workspace_uuid: Uuid,
+        user_uuid: Uuid,
+    ) -> Result<bool> {
+        let workspace_repo = ctx.data_unchecked::<Arc<dyn WorkspaceRepo>>();
+        member_changed(
+            workspace_repo
+                .remove_member(&workspace_uuid, &user_uuid)
+                .await,
+        )?;
+        Ok(true)
+    }
+}
+
+/// Returns a workspace in the trash owned by the current user.
+async fn find_trashed_workspace(ctx: &Context<'_>, uuid: &Uuid) -> Result<Workspace> {
+    let workspace_repo = ctx.data_unchecked::<Arc<dyn WorkspaceRepo>>();
+    let user = current_user(ctx)?;
+    workspace_repo
+        .get_trashed_workspaces(&user.uuid)
+        .await
+        .map_err(|e| WorkspaceMutationError::from(e).extend())?
+        .into_iter()
+        .find(|workspace| &workspace.uuid == uuid)
+        .ok_or_else(|| WorkspaceMutationError::NotInTrash.extend())
+}
+
+/// Turns the outcome of a member change into the matching error.
+fn member_changed(change: anyhow::Result<MemberChange>) -> Result<()> {
+    match change.map_err(|e| WorkspaceMutationError::from(e).extend())? {
+        MemberChange::Changed => Ok(()),
+        MemberChange::NotFound => Err(WorkspaceMutationError::MemberNotFound.extend()),
+        MemberChange::LastOwner => Err(WorkspaceMutationError::LastOwner.extend()),
     }
 }
 
 #[ComplexObject]
 impl Workspace {
-    pub async fn pages(&self, ctx: &Context<'_>) -> Result<Vec<Page>> {
+    /// The workspace's root pages sorted by their order, or with `all` every
+    /// page depth-first, each followed by the pages nested under it.
+    #[graphql(guard = "WorkspaceRoleGuard::new(self.uuid, WorkspaceRole::Viewer)")]
+    pub async fn pages(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] all: bool,
+    ) -> Result<Vec<Page>> {
+        if all {
+            let workspace_repo = ctx.data_unchecked::<Arc<dyn WorkspaceRepo>>();
+            Ok(sort_tree(workspace_repo.get_pages(&self.uuid).await?))
+        } else {
+            let page_repo = ctx.data_unchecked::<Arc<dyn PageRepo>>();
+            page_repo
+                .get_child_pages(&self.uuid, None)
+                .await
+                .map_err(|err| err.into())
+        }
+    }
+
+    /// The workspace's pages in the trash, most recently deleted first.
+    #[graphql(guard = "WorkspaceRoleGuard::new(self.uuid, WorkspaceRole::Viewer)")]
+    pub async fn trash(&self, ctx: &Context<'_>) -> Result<Vec<Page>> {
+        let page_repo = ctx.data_unchecked::<Arc<dyn PageRepo>>();
+        page_repo
+            .get_trashed_pages(&self.uuid)
+            .await
+            .map_err(|err| err.into())
+    }
+
+    #[graphql(guard = "WorkspaceRoleGuard::new(self.uuid, WorkspaceRole::Viewer)")]
+    pub async fn members(&self, ctx: &Context<'_>) -> Result<Vec<WorkspaceMember>> {
         let workspace_repo = ctx.data_unchecked::<Arc<dyn WorkspaceRepo>>();
         workspace_repo
-            .get_pages(&self.uuid)
+            .get_members(&self.uuid)
+            .await
+            .map_err(|err| err.into())
+    }
+}
+
+#[ComplexObject]
+impl WorkspaceMember {
+    pub async fn user(&self, ctx: &Context<'_>) -> Result<Option<User>> {
+        let user_repo = ctx.data_unchecked::<Arc<dyn UserRepo>>();
+        user_repo
+            .get_user_by_uuid(&self.user_uuid)
             .await
             .map_err(|err| err.into())
     }
@@ -6,6 +6,17 @@ diesel::table! {
         idx -> Int4,
         typ -> Varchar,
         data -> Nullable<Text>,
+        uuid -> Uuid,
+        version -> Int4,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    change_events (id) {
+        id -> Int8,
+        payload -> Text,
+        created_at -> Timestamp,
     }
 }
 
@@ -16,12 +27,40 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    page_revisions (page_uuid, number) {
+        page_uuid -> Uuid,
+        number -> Int4,
+        user_uuid -> Nullable<Uuid>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        snapshot -> Text,
+    }
+}
+
 diesel::table! {
     pages (uuid) {
         workspace_uuid -> Uuid,
         uuid -> Uuid,
         title -> Varchar,
         image -> Nullable<Varchar>,
+        version -> Int4,
+        updated_at -> Timestamp,
+        deleted_at -> Nullable<Timestamp>,
+        parent_page_uuid -> Nullable<Uuid>,
+        order -> Varchar,
+    }
+}
+
+diesel::table! {
+    refresh_tokens (uuid) {
+        uuid -> Uuid,
+        user_uuid -> Uuid,
+        family_uuid -> Uuid,
+        token_hash -> Bpchar,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
     }
 }
 
@@ -29,7 +68,17 @@ diesel::table! {
     slots (uuid) {
         page_uuid -> Uuid,
         uuid -> Uuid,
-        order -> Bpchar,
+        order -> Varchar,
+        version -> Int4,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    text_edits (atom_uuid, version) {
+        atom_uuid -> Uuid,
+        version -> Int4,
+        operations -> Text,
     }
 }
 
@@ -42,23 +91,44 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    workspace_members (user_uuid, workspace_uuid) {
+        user_uuid -> Uuid,
+        workspace_uuid -> Uuid,
+        role -> Varchar,
+    }
+}
+
 diesel::table! {
     workspaces (uuid) {
         uuid -> Uuid,
         name -> Varchar,
         image -> Varchar,
+        version -> Int4,
+        updated_at -> Timestamp,
+        deleted_at -> Nullable<Timestamp>,
     }
 }
 
 diesel::joinable!(atoms -> slots (slot_uuid));
+diesel::joinable!(page_revisions -> pages (page_uuid));
+diesel::joinable!(page_revisions -> users (user_uuid));
 diesel::joinable!(pages -> workspaces (workspace_uuid));
+diesel::joinable!(refresh_tokens -> users (user_uuid));
 diesel::joinable!(slots -> pages (page_uuid));
+diesel::joinable!(workspace_members -> users (user_uuid));
+diesel::joinable!(workspace_members -> workspaces (workspace_uuid));
 
 diesel::allow_tables_to_appear_in_same_query!(
     atoms,
+    change_events,
     data_source,
+    page_revisions,
     pages,
+    refresh_tokens,
     slots,
+    text_edits,
     users,
+    workspace_members,
     workspaces,
 );
@@ -0,0 +1,152 @@
+use async_graphql::{ErrorExtensions, FieldError};
+use thiserror::Error;
+
+use crate::{
+    models::User,
+    repos::traits::UserRepo,
+    utils::jwt::{KeyStore, TokenError},
+};
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("Malformed authorization header")]
+    MalformedHeader,
+    #[error("Invalid token")]
+    InvalidToken,
+    #[error("Token expired")]
+    TokenExpired,
+    #[error("User not found")]
+    UserNotFound,
+    #[error("unknown data store error: {0}")]
+    Default(#[from] anyhow::Error),
+}
+
+impl ErrorExtensions for AuthError {
+    fn extend(&self) -> FieldError {
+        self.extend_with(|err, e| match err {
+            AuthError::Default(_) => e.set("code", 500),
+            _ => e.set("code", 401),
+        })
+    }
+}
+
+/// Resolves the user identified by the value of an `Authorization` header.
+///
+/// A missing header means an anonymous request and yields `None`.
+pub async fn authenticate(
+    user_repo: &dyn UserRepo,
+    keys: &KeyStore,
+    header: Option<&str>,
+) -> Result<Option<User>, AuthError> {
+    let header = match header {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+    let token = header
+        .strip_prefix("Bearer ")
+        .ok_or(AuthError::MalformedHeader)?;
+    let uuid = keys.verify(token).await.map_err(|e| match e {
+        TokenError::Expired => AuthError::TokenExpired,
+        _ => AuthError::InvalidToken,
+    })?;
+    let user = user_repo
+        .get_user_by_uuid(&uuid)
+        .await?
+        .ok_or(AuthError::UserNotFound)?;
+    Ok(Some(user))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use chrono::Duration;
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    use crate::utils::jwt::{generate_jwt, SigningKeys};
+
+    struct MockUserRepo {
+        user: User,
+    }
+
+    #[async_trait]
+    impl UserRepo for MockUserRepo {
+        async fn get_user_by_uuid(&self, uuid: &Uuid) -> Result<Option<User>> {
+            Ok(Some(self.user.clone()).filter(|u| &u.uuid == uuid))
+        }
+        async fn create_user(&self, _user: &User) -> Result<()> {
+            Ok(())
+        }
+        async fn update_user(&self, _user: &User) -> Result<()> {
+            Ok(())
+        }
+        async fn get_user_by_login(&self, _login: &str) -> Result<Option<User>> {
+            Ok(None)
+        }
+    }
+
+    fn keys() -> SigningKeys {
+        "kid:secret".parse().unwrap()
+    }
+
+    fn store() -> KeyStore {
+        KeyStore::new(keys(), None)
+    }
+
+    fn repo() -> MockUserRepo {
+        MockUserRepo {
+            user: User::new(
+                "test@example.com",
+                "test",
+                &Secret::new("password".to_string()),
+            ),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_without_header() {
+        let repo = repo();
+        let user = authenticate(&repo, &store(), None).await.unwrap();
+        assert!(user.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_with_valid_token() {
+        let repo = repo();
+        let token = generate_jwt(&keys(), &repo.user, Duration::minutes(5)).unwrap();
+        let header = format!("Bearer {}", token);
+        let user = authenticate(&repo, &store(), Some(&header))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.uuid, repo.user.uuid);
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_with_invalid_token() {
+        let repo = repo();
+        let token = generate_jwt(
+            &"kid:other".parse().unwrap(),
+            &repo.user,
+            Duration::minutes(5),
+        )
+        .unwrap();
+        let header = format!("Bearer {}", token);
+        let res = authenticate(&repo, &store(), Some(&header)).await;
+        assert!(matches!(res, Err(AuthError::InvalidToken)));
+
+        let res = authenticate(&repo, &store(), Some(&token)).await;
+        assert!(matches!(res, Err(AuthError::MalformedHeader)));
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_with_expired_token() {
+        let repo = repo();
+        let token = generate_jwt(&keys(), &repo.user, Duration::minutes(-5)).unwrap();
+        let header = format!("Bearer {}", token);
+        let res = authenticate(&repo, &store(), Some(&header)).await;
+        assert!(matches!(res, Err(AuthError::TokenExpired)));
+    }
+}
@@ -1,24 +1,63 @@
 use appconfig_derive::*;
 
-/// Used to generate a jwt secret when the app is first loaded
-fn generate_jwt_secret() -> String {
-    // TODO: Implement random generation
-    "token".to_string()
+use super::{events::EventBrokerKind, jwt::SigningKeys};
+use crate::repos::{images_repo::ImagesStorageKind, StorageKind};
+
+/// Used to generate the jwt signing keys when the app is first loaded
+fn generate_jwt_keys() -> SigningKeys {
+    SigningKeys::generate()
 }
 
 #[derive(AppConfig)]
 pub struct BaseConfig {
     #[appconfig(default = "0.0.0.0:8000")]
     pub bind_addr: String,
+    /// Use `memory` to run without Postgres and S3, e.g. for local
+    /// development. Can also be given as `--storage=memory`.
+    #[appconfig(default = "postgresql")]
+    pub storage: StorageKind,
+    /// Required with the `postgresql` storage.
+    #[appconfig(default = "")]
     pub database_url: String,
+    /// Applies pending migrations on startup. Without it, the app refuses to
+    /// start until they're applied with `--migrate`.
+    #[appconfig(default = "false")]
+    pub auto_migrate: bool,
 }
 
 #[derive(AppConfig)]
 pub struct Config {
     #[appconfig(skip)]
     pub base: BaseConfig,
-    #[appconfig(default_fn = generate_jwt_secret)]
-    pub jwt_secret: String,
+    /// Only read on startup, the running instance uses a `KeyStore`.
+    #[appconfig(default_fn = generate_jwt_keys)]
+    pub jwt_keys: SigningKeys,
+    /// Lifetime of access tokens, in seconds.
+    #[appconfig(default = 900)]
+    pub access_token_ttl: i64,
+    /// Lifetime of refresh tokens, in seconds.
+    #[appconfig(default = 2592000)]
+    pub refresh_token_ttl: i64,
+    /// Days after which pages and workspaces in the trash are purged.
+    #[appconfig(default = 30)]
+    pub trash_retention_days: i64,
+    /// Use `local` to keep images in `images_dir` instead of S3. Ignored with
+    /// the `memory` storage.
+    #[appconfig(default = "s3")]
+    pub images_storage: ImagesStorageKind,
+    /// Required with the `s3` images storage.
+    #[appconfig(default = "")]
     pub s3_bucket: String,
+    #[appconfig(default = "")]
     pub s3_endpoint: String,
+    /// The directory the `local` images storage writes to.
+    #[appconfig(default = "uploads")]
+    pub images_dir: String,
+    /// The url the `local` images storage's files are reachable at, i.e. the
+    /// app's `/files` route as seen by clients.
+    #[appconfig(default = "http://localhost:8000/files")]
+    pub images_url: String,
+    /// Use `postgresql` to share change events between instances.
+    #[appconfig(default = "local")]
+    pub event_broker: EventBrokerKind,
 }
@@ -0,0 +1,119 @@
+use anyhow::Result;
+use async_graphql::{Enum, SimpleObject};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::models::text_ops::TextEdit;
+
+/// How many events a subscriber may fall behind before it misses some.
+const EVENT_BUFFER: usize = 1024;
+
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ChangeKind {
+    PageCreated,
+    PageUpdated,
+    PageDeleted,
+    PageMoved,
+    SlotInserted,
+    SlotMoved,
+    SlotDeleted,
+    AtomInserted,
+    AtomUpdated,
+    AtomMoved,
+    AtomDeleted,
+    TextEdited,
+}
+
+/// A change made to a page or its content.
+#[derive(SimpleObject, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeEvent {
+    pub kind: ChangeKind,
+    pub workspace_uuid: Uuid,
+    pub page_uuid: Uuid,
+    /// The changed slot, for slot and atom changes.
+    pub slot_uuid: Option<Uuid>,
+    /// The index of the changed atom, for atom changes.
+    pub atom_idx: Option<i32>,
+    /// The user who made the change.
+    pub user_uuid: Option<Uuid>,
+    /// The edit as applied, for text edits.
+    pub edit: Option<TextEdit>,
+}
+
+/// Delivers change events to the subscribers of this instance.
+#[async_trait]
+pub trait EventBroker: Send + Sync {
+    async fn publish(&self, event: ChangeEvent) -> Result<()>;
+    fn subscribe(&self) -> broadcast::Receiver<ChangeEvent>;
+}
+
+/// Selects the event broker in `Config`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, EnumString, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum EventBrokerKind {
+    /// Delivers events within a single instance.
+    Local,
+    /// Delivers events to every instance using the same database.
+    Postgresql,
+}
+
+/// A broker that only reaches subscribers within the same process.
+pub struct LocalEventBroker {
+    sender: broadcast::Sender<ChangeEvent>,
+}
+
+impl LocalEventBroker {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        Self { sender }
+    }
+
+    pub fn sender(&self) -> broadcast::Sender<ChangeEvent> {
+        self.sender.clone()
+    }
+}
+
+impl Default for LocalEventBroker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl EventBroker for LocalEventBroker {
+    async fn publish(&self, event: ChangeEvent) -> Result<()> {
+        // Sending only fails if nobody is subscribed, which is fine.
+        let _ = self.sender.send(event);
+        Ok(())
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<ChangeEvent> {
+        self.sender.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_local_event_broker() {
+        let broker = LocalEventBroker::new();
+        let event = ChangeEvent {
+            kind: ChangeKind::PageCreated,
+            workspace_uuid: Uuid::new_v4(),
+            page_uuid: Uuid::new_v4(),
+            slot_uuid: None,
+            atom_idx: None,
+            user_uuid: None,
+            edit: None,
+        };
+        broker.publish(event.clone()).await.unwrap();
+        let mut receiver = broker.subscribe();
+        broker.publish(event.clone()).await.unwrap();
+        assert_eq!(receiver.recv().await.unwrap(), event);
+    }
+}
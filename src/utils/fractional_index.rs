@@ -0,0 +1,186 @@
+//! Fractional index keys used to order slots within a page and pages among
+//! their siblings.
+//!
+//! A key is a base-62 fraction written without its leading `0.`, so keys sort
+//! by plain byte comparison and a new key can always be generated between any
+//! two others. Keys never end with the zero digit, since nothing could be
+//! placed between `a` and `a0`.
+
+/// Length of the `order` columns holding keys.
+pub const KEY_LEN: usize = 16;
+
+/// Keys longer than this call for a rebalance of the list, leaving room for
+/// keys generated concurrently.
+pub const REBALANCE_LEN: usize = KEY_LEN - 4;
+
+const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const BASE: usize = DIGITS.len();
+
+fn digit(c: u8) -> Option<usize> {
+    match c {
+        b'0'..=b'9' => Some((c - b'0') as usize),
+        b'A'..=b'Z' => Some((c - b'A') as usize + 10),
+        b'a'..=b'z' => Some((c - b'a') as usize + 36),
+        _ => None,
+    }
+}
+
+/// Checks that `key` only uses base-62 digits and doesn't end with zero.
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && key.bytes().all(|c| digit(c).is_some()) && !key.ends_with('0')
+}
+
+/// Returns a key between `a` and `b`, where `None` stands for the start or the
+/// end of the list respectively.
+///
+/// Returns `None` if either key is invalid or `a` doesn't sort before `b`.
+pub fn key_between(a: Option<&str>, b: Option<&str>) -> Option<String> {
+    if !a.is_none_or(is_valid_key) || !b.is_none_or(is_valid_key) {
+        return None;
+    }
+    if let (Some(a), Some(b)) = (a, b) {
+        if a >= b {
+            return None;
+        }
+    }
+    let key = midpoint(a.unwrap_or("").as_bytes(), b.map(str::as_bytes));
+    Some(String::from_utf8(key).expect("keys are ASCII"))
+}
+
+/// Returns the shortest key strictly between `a` and `b`, given `a < b`.
+fn midpoint(a: &[u8], b: Option<&[u8]>) -> Vec<u8> {
+    if let Some(b) = b {
+        // Skip the common prefix, reading missing digits of `a` as zeros.
+        let n = b
+            .iter()
+            .enumerate()
+            .take_while(|(i, c)| a.get(*i).copied().unwrap_or(b'0') == **c)
+            .count();
+        if n > 0 {
+            let mut key = b[..n].to_vec();
+            key.extend(midpoint(a.get(n..).unwrap_or(&[]), Some(&b[n..])));
+            return key;
+        }
+    }
+
+    let digit_a = a.first().and_then(|c| digit(*c)).unwrap_or(0);
+    let digit_b = b
+        .and_then(|b| b.first())
+        .and_then(|c| digit(*c))
+        .unwrap_or(BASE);
+    if digit_b - digit_a > 1 {
+        vec![DIGITS[(digit_a + digit_b).div_ceil(2)]]
+    } else if let Some(b) = b.filter(|b| b.len() > 1) {
+        // `b` continues past its first digit, so that digit alone is enough.
+        vec![b[0]]
+    } else {
+        let mut key = vec![DIGITS[digit_a]];
+        key.extend(midpoint(a.get(1..).unwrap_or(&[]), None));
+        key
+    }
+}
+
+/// Returns a key placing an item at `pos` in a list with the given sorted
+/// keys, or `None` if the list needs to be rebalanced first. Once `rebalanced`,
+/// keys may use the full column length.
+pub fn key_at(keys: &[&str], pos: usize, rebalanced: bool) -> Option<String> {
+    let prev = pos.checked_sub(1).map(|pos| keys[pos]);
+    // Items inserted concurrently between the same neighbours share a key,
+    // which also leaves no room between them until the list is rebalanced.
+    key_between(prev, keys.get(pos).copied())
+        .filter(|key| key.len() <= REBALANCE_LEN || (rebalanced && key.len() <= KEY_LEN))
+}
+
+/// Returns `n` sorted keys spread evenly over the key space, using as few
+/// digits as possible while leaving room between neighbours.
+pub fn evenly_spaced(n: usize) -> Vec<String> {
+    let mut len = 1;
+    let mut space = BASE as u128;
+    while space <= (n as u128 + 1) * BASE as u128 {
+        len += 1;
+        space *= BASE as u128;
+    }
+    (1..=n as u128)
+        .map(|i| {
+            let mut value = i * space / (n as u128 + 1);
+            let mut key = vec![b'0'; len];
+            for c in key.iter_mut().rev() {
+                *c = DIGITS[(value % BASE as u128) as usize];
+                value /= BASE as u128;
+            }
+            let key = String::from_utf8(key).expect("keys are ASCII");
+            key.trim_end_matches('0').to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_between() {
+        assert_eq!(key_between(None, None).unwrap(), "V");
+        let first = key_between(None, Some("V")).unwrap();
+        assert!(first.as_str() < "V");
+        let last = key_between(Some("V"), None).unwrap();
+        assert!(last.as_str() > "V");
+        let mid = key_between(Some("V"), Some("W")).unwrap();
+        assert!("V" < mid.as_str() && mid.as_str() < "W");
+        assert_eq!(key_between(Some("a"), Some("b1")).unwrap(), "b");
+    }
+
+    #[test]
+    fn test_key_between_rejects_invalid_keys() {
+        assert!(key_between(Some("b"), Some("a")).is_none());
+        assert!(key_between(Some("a"), Some("a")).is_none());
+        assert!(key_between(Some("a0"), None).is_none());
+        assert!(key_between(None, Some("a b")).is_none());
+    }
+
+    #[test]
+    fn test_repeated_inserts_stay_ordered() {
+        // Always inserting right after the first key is the worst case for
+        // key length.
+        let mut keys = vec![key_between(None, None).unwrap()];
+        for _ in 0..50 {
+            let key = key_between(Some(&keys[0]), keys.get(1).map(|k| k.as_str())).unwrap();
+            assert!(is_valid_key(&key));
+            keys.insert(1, key);
+        }
+        for _ in 0..50 {
+            let key = key_between(None, Some(&keys[0])).unwrap();
+            keys.insert(0, key);
+        }
+        for _ in 0..50 {
+            let key = key_between(keys.last().map(|k| k.as_str()), None).unwrap();
+            keys.push(key);
+        }
+        let mut sorted = keys.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(keys, sorted);
+    }
+
+    #[test]
+    fn test_evenly_spaced() {
+        assert!(evenly_spaced(0).is_empty());
+        for n in [1, 5, 61, 62, 1000] {
+            let keys = evenly_spaced(n);
+            assert_eq!(keys.len(), n);
+            assert!(keys.iter().all(|k| is_valid_key(k) && k.len() <= 3));
+            assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        }
+    }
+
+    #[test]
+    fn test_key_at() {
+        assert_eq!(key_at(&[], 0, false).as_deref(), Some("V"));
+        assert_eq!(key_at(&["a", "b"], 1, false).as_deref(), Some("aV"));
+        assert_eq!(key_at(&["a", "a"], 1, true), None);
+        let a = "a".repeat(REBALANCE_LEN);
+        let b = format!("{}1", a);
+        assert_eq!(key_at(&[&a, &b], 1, false), None);
+        assert_eq!(key_at(&[&a, &b], 1, true), Some(format!("{}0V", a)));
+    }
+}
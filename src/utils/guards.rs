@@ -0,0 +1,309 @@
+use std::sync::Arc;
+
+use async_graphql::{Context, ErrorExtensions, FieldError, Guard, Result};
+use async_trait::async_trait;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{
+    models::{User, WorkspaceRole},
+    repos::traits::{PageRepo, SlotRepo, WorkspaceRepo},
+};
+
+#[derive(Debug, Error)]
+pub enum GuardError {
+    #[error("You must be logged in")]
+    Unauthorized,
+    #[error("You don't have access to this workspace")]
+    Forbidden,
+    #[error("unknown data store error: {0}")]
+    Default(#[from] anyhow::Error),
+}
+
+impl ErrorExtensions for GuardError {
+    fn extend(&self) -> FieldError {
+        self.extend_with(|err, e| match err {
+            GuardError::Unauthorized => e.set("code", 401),
+            GuardError::Forbidden => e.set("code", 403),
+            GuardError::Default(_) => e.set("code", 500),
+        })
+    }
+}
+
+/// Returns the user attached to the request, or an `Unauthorized` error.
+pub fn current_user<'a>(ctx: &'a Context<'_>) -> Result<&'a User> {
+    ctx.data_opt::<Option<User>>()
+        .and_then(|user| user.as_ref())
+        .ok_or_else(|| GuardError::Unauthorized.extend())
+}
+
+/// Allows access to logged in users only.
+pub struct LoggedInGuard;
+
+#[async_trait]
+impl Guard for LoggedInGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        current_user(ctx).map(|_| ())
+    }
+}
+
+/// Allows access to members of a workspace holding at least the given role.
+pub struct WorkspaceRoleGuard {
+    workspace_uuid: Uuid,
+    role: WorkspaceRole,
+}
+
+impl WorkspaceRoleGuard {
+    pub fn new(workspace_uuid: Uuid, role: WorkspaceRole) -> Self {
+        Self {
+            workspace_uuid,
+            role,
+        }
+    }
+}
+
+#[async_trait]
+impl Guard for WorkspaceRoleGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        let user = current_user(ctx)?;
+        let workspace_repo = ctx.data_unchecked::<Arc<dyn WorkspaceRepo>>();
+        let role = workspace_repo
+            .get_member_role(&self.workspace_uuid, &user.uuid)
+            .await
+            .map_err(|e| GuardError::from(e).extend())?;
+        match role {
+            Some(role) if role >= self.role => Ok(()),
+            _ => Err(GuardError::Forbidden.extend()),
+        }
+    }
+}
+
+/// Allows access to members of the page's workspace holding at least the
+/// given role.
+pub struct PageRoleGuard {
+    page_uuid: Uuid,
+    role: WorkspaceRole,
+}
+
+impl PageRoleGuard {
+    pub fn new(page_uuid: Uuid, role: WorkspaceRole) -> Self {
+        Self { page_uuid, role }
+    }
+}
+
+#[async_trait]
+impl Guard for PageRoleGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        current_user(ctx)?;
+        let page_repo = ctx.data_unchecked::<Arc<dyn PageRepo>>();
+        let page = page_repo
+            .get_page_by_uuid(&self.page_uuid)
+            .await
+            .map_err(|e| GuardError::from(e).extend())?
+            .ok_or_else(|| GuardError::Forbidden.extend())?;
+        WorkspaceRoleGuard::new(page.workspace_uuid, self.role)
+            .check(ctx)
+            .await
+    }
+}
+
+/// Allows access to members of the slot's workspace holding at least the
+/// given role.
+pub struct SlotRoleGuard {
+    slot_uuid: Uuid,
+    role: WorkspaceRole,
+}
+
+impl SlotRoleGuard {
+    pub fn new(slot_uuid: Uuid, role: WorkspaceRole) -> Self {
+        Self { slot_uuid, role }
+    }
+}
+
+#[async_trait]
+impl Guard for SlotRoleGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        current_user(ctx)?;
+        let slot_repo = ctx.data_unchecked::<Arc<dyn SlotRepo>>();
+        let slot = slot_repo
+            .get_slot_by_uuid(&self.slot_uuid)
+            .await
+            .map_err(|e| GuardError::from(e).extend())?
+            .ok_or_else(|| GuardError::Forbidden.extend())?;
+        PageRoleGuard::new(slot.page_uuid, self.role)
+            .check(ctx)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result as AResult;
+    use async_graphql::{EmptyMutation, EmptySubscription, Object, Request, Schema};
+    use chrono::NaiveDateTime;
+    use secrecy::Secret;
+
+    use crate::models::{workspace_member::MemberChange, Page, Workspace, WorkspaceMember};
+
+    struct MockWorkspaceRepo {
+        workspace_uuid: Uuid,
+        user_uuid: Uuid,
+        role: WorkspaceRole,
+    }
+
+    #[async_trait]
+    impl WorkspaceRepo for MockWorkspaceRepo {
+        async fn get_user_workspaces(&self, _user_uuid: &Uuid) -> AResult<Vec<Workspace>> {
+            Ok(vec![])
+        }
+        async fn get_workspace_by_uuid(&self, _uuid: &Uuid) -> AResult<Option<Workspace>> {
+            Ok(None)
+        }
+        async fn get_trashed_workspaces(&self, _owner_uuid: &Uuid) -> AResult<Vec<Workspace>> {
+            Ok(vec![])
+        }
+        async fn create_workspace(
+            &self,
+            _workspace: &Workspace,
+            _owner_uuid: &Uuid,
+        ) -> AResult<()> {
+            Ok(())
+        }
+        async fn update_workspace(&self, _workspace: &Workspace) -> AResult<Option<Workspace>> {
+            Ok(None)
+        }
+        async fn trash_workspace(&self, _uuid: &Uuid) -> AResult<Option<Workspace>> {
+            Ok(None)
+        }
+        async fn restore_workspace(&self, _uuid: &Uuid) -> AResult<Option<Workspace>> {
+            Ok(None)
+        }
+        async fn purge_workspace(&self, _uuid: &Uuid) -> AResult<Vec<String>> {
+            Ok(vec![])
+        }
+        async fn purge_trashed_workspaces(
+            &self,
+            _deleted_before: NaiveDateTime,
+        ) -> AResult<Vec<String>> {
+            Ok(vec![])
+        }
+        async fn get_pages(&self, _uuid: &Uuid) -> AResult<Vec<Page>> {
+            Ok(vec![])
+        }
+        async fn get_member_role(
+            &self,
+            workspace_uuid: &Uuid,
+            user_uuid: &Uuid,
+        ) -> AResult<Option<WorkspaceRole>> {
+            Ok(Some(self.role)
+                .filter(|_| workspace_uuid == &self.workspace_uuid && user_uuid == &self.user_uuid))
+        }
+        async fn get_members(&self, _workspace_uuid: &Uuid) -> AResult<Vec<WorkspaceMember>> {
+            Ok(vec![])
+        }
+        async fn add_member(&self, _member: &WorkspaceMember) -> AResult<()> {
+            Ok(())
+        }
+        async fn update_member(&self, _member: &WorkspaceMember) -> AResult<MemberChange> {
+            Ok(MemberChange::NotFound)
+        }
+        async fn remove_member(
+            &self,
+            _workspace_uuid: &Uuid,
+            _user_uuid: &Uuid,
+        ) -> AResult<MemberChange> {
+            Ok(MemberChange::NotFound)
+        }
+    }
+
+    struct Query;
+
+    #[Object]
+    impl Query {
+        #[graphql(guard = "LoggedInGuard")]
+        async fn logged_in(&self) -> bool {
+            true
+        }
+
+        #[graphql(guard = "WorkspaceRoleGuard::new(uuid, WorkspaceRole::Editor)")]
+        async fn edit(&self, uuid: Uuid) -> Uuid {
+            uuid
+        }
+    }
+
+    fn build_schema(
+        role: WorkspaceRole,
+        user: &User,
+    ) -> (Schema<Query, EmptyMutation, EmptySubscription>, Uuid) {
+        let workspace_uuid = Uuid::new_v4();
+        let repo: Arc<dyn WorkspaceRepo> = Arc::new(MockWorkspaceRepo {
+            workspace_uuid,
+            user_uuid: user.uuid,
+            role,
+        });
+        let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
+            .data(repo)
+            .finish();
+        (schema, workspace_uuid)
+    }
+
+    fn user() -> User {
+        User::new(
+            "test@example.com",
+            "test",
+            &Secret::new("password".to_string()),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_logged_in_guard() {
+        let user = user();
+        let (schema, _) = build_schema(WorkspaceRole::Viewer, &user);
+
+        let res = schema
+            .execute(Request::new("{ loggedIn }").data(None::<User>))
+            .await;
+        assert_eq!(res.errors.len(), 1);
+        assert_eq!(res.errors[0].message, "You must be logged in");
+
+        let res = schema
+            .execute(Request::new("{ loggedIn }").data(Some(user)))
+            .await;
+        assert!(res.errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_workspace_role_guard() {
+        let user = user();
+        let query = |uuid: Uuid| format!("{{ edit(uuid: \"{}\") }}", uuid);
+
+        let (schema, workspace_uuid) = build_schema(WorkspaceRole::Editor, &user);
+        let res = schema
+            .execute(Request::new(query(workspace_uuid)).data(Some(user.clone())))
+            .await;
+        assert!(res.errors.is_empty());
+
+        let res = schema
+            .execute(Request::new(query(Uuid::new_v4())).data(Some(user.clone())))
+            .await;
+        assert_eq!(
+            res.errors[0].message,
+            "You don't have access to this workspace"
+        );
+
+        let res = schema
+            .execute(Request::new(query(workspace_uuid)).data(None::<User>))
+            .await;
+        assert_eq!(res.errors[0].message, "You must be logged in");
+
+        let (schema, workspace_uuid) = build_schema(WorkspaceRole::Viewer, &user);
+        let res = schema
+            .execute(Request::new(query(workspace_uuid)).data(Some(user)))
+            .await;
+        assert_eq!(
+            res.errors[0].message,
+            "You don't have access to this workspace"
+        );
+    }
+}
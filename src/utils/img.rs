@@ -1,6 +1,7 @@
 use std::collections::hash_map::DefaultHasher;
 use std::hash::Hash;
 use std::hash::Hasher;
+use std::path::Path;
 
 use log::debug;
 use phf::phf_map;
@@ -48,10 +49,33 @@ static SVG_OPTIONS: phf::Map<u32, SvgData> = phf_map! {
     },
 };
 
-const SVG_COLORS: &'static [&'static str] = &[
+const SVG_COLORS: &[&str] = &[
     "3F3B6C", "624F82", "624F82", "FD841F", "3E6D9C", "FFACC7", "B3FFAE", "82CD47",
 ];
 
+/// Returns the extension to store an uploaded image with, if `filename` has
+/// the extension of a png, jpeg, gif or webp image and `data` starts like an
+/// image of that type. Other files, e.g. html or svg, could run scripts when
+/// served from the app's own origin.
+pub fn image_extension(filename: &str, data: &[u8]) -> Option<&'static str> {
+    let extension = Path::new(filename).extension()?.to_str()?;
+    let (extension, valid) = match extension.to_ascii_lowercase().as_str() {
+        "png" => ("png", data.starts_with(b"\x89PNG\r\n\x1a\n")),
+        "jpg" => ("jpg", data.starts_with(b"\xff\xd8\xff")),
+        "jpeg" => ("jpeg", data.starts_with(b"\xff\xd8\xff")),
+        "gif" => (
+            "gif",
+            data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a"),
+        ),
+        "webp" => (
+            "webp",
+            data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP"),
+        ),
+        _ => return None,
+    };
+    valid.then_some(extension)
+}
+
 pub async fn generate_image(name: &str) -> Vec<u8> {
     let mut s = DefaultHasher::new();
     name.hash(&mut s);
@@ -86,3 +110,24 @@ pub async fn generate_image(name: &str) -> Vec<u8> {
     .unwrap();
     pixmap.encode_png().unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_extension() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        assert_eq!(image_extension("a.PNG", png), Some("png"));
+        assert_eq!(image_extension("a.jpeg", b"\xff\xd8\xff\xe0"), Some("jpeg"));
+        assert_eq!(image_extension("a.gif", b"GIF89a"), Some("gif"));
+        assert_eq!(
+            image_extension("a.webp", b"RIFF\0\0\0\0WEBPVP8 "),
+            Some("webp")
+        );
+        assert_eq!(image_extension("a.jpg", png), None);
+        assert_eq!(image_extension("a.html", png), None);
+        assert_eq!(image_extension("a.svg", b"<svg></svg>"), None);
+        assert_eq!(image_extension("png", png), None);
+    }
+}
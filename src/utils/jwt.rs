@@ -1,24 +1,428 @@
-use anyhow::Result;
-use hmac::{Hmac, Mac};
-use jwt::{SignWithKey, VerifyWithKey};
+use std::{
+    fmt::{self, Debug, Display},
+    str::FromStr,
+    sync::RwLock,
+    time::Instant,
+};
+
+use appconfig_derive::DataSource;
+use chrono::{Duration, Utc};
+use hmac::{digest::InvalidLength, Hmac, Mac};
+use jwt::{AlgorithmType, Header, SignWithKey, Token, VerifyWithKey};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
 use sha2::Sha256;
-use std::collections::BTreeMap;
+use thiserror::Error;
+use tokio::sync::Mutex;
 use uuid::Uuid;
 
-use crate::models::user::User;
+use crate::{models::user::User, utils::random::random_hex};
+
+#[derive(Debug, Error)]
+pub enum TokenError {
+    #[error("Invalid token: {0}")]
+    Invalid(#[from] jwt::Error),
+    #[error("Token expired")]
+    Expired,
+    #[error("Invalid token subject: {0}")]
+    InvalidSubject(#[from] uuid::Error),
+    #[error("Invalid signing key")]
+    InvalidKey(#[from] InvalidLength),
+    #[error("Unknown signing key: {0:?}")]
+    UnknownKey(Option<String>),
+    #[error("Malformed signing keys")]
+    MalformedKeys,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct SigningKey {
+    /// The key id sent in the `kid` header of tokens signed with this key.
+    pub kid: String,
+    secret: String,
+    /// Unix timestamp at which the key stopped signing new tokens.
+    retired_at: Option<i64>,
+}
+
+impl SigningKey {
+    fn generate() -> Self {
+        Self {
+            kid: random_hex(8),
+            secret: random_hex(32),
+            retired_at: None,
+        }
+    }
+
+    fn hmac(&self) -> Result<Hmac<Sha256>, TokenError> {
+        Ok(Hmac::new_from_slice(self.secret.as_bytes())?)
+    }
+}
+
+impl Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SigningKey")
+            .field("kid", &self.kid)
+            .field("secret", &"[redacted]")
+            .field("retired_at", &self.retired_at)
+            .finish()
+    }
+}
+
+/// The HMAC keys used to sign and verify access tokens.
+///
+/// The first key signs new tokens; the others are only used for verification,
+/// so tokens signed before a rotation stay valid until they expire.
+/// Stored as a comma separated list of `kid:secret` pairs, followed by
+/// `:retired_at` for the keys that no longer sign tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigningKeys {
+    keys: Vec<SigningKey>,
+}
+
+impl SigningKeys {
+    pub fn generate() -> Self {
+        Self {
+            keys: vec![SigningKey::generate()],
+        }
+    }
+
+    pub fn active(&self) -> &SigningKey {
+        &self.keys[0]
+    }
+
+    pub fn get(&self, kid: &str) -> Option<&SigningKey> {
+        self.keys.iter().find(|key| key.kid == kid)
+    }
+
+    /// Makes a freshly generated key active, keeping the previous ones for
+    /// verification only until the tokens they signed, which live for `ttl`,
+    /// have expired.
+    pub fn rotate(&mut self, ttl: Duration) -> &SigningKey {
+        let now = Utc::now().timestamp();
+        for key in &mut self.keys {
+            key.retired_at.get_or_insert(now);
+        }
+        self.keys
+            .retain(|key| key.retired_at.is_none_or(|at| at + ttl.num_seconds() > now));
+        self.keys.insert(0, SigningKey::generate());
+        self.active()
+    }
+}
+
+impl FromStr for SigningKeys {
+    type Err = TokenError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let keys = s
+            .split(',')
+            .map(|key| {
+                let mut parts = key.splitn(3, ':');
+                match (parts.next(), parts.next(), parts.next()) {
+                    (Some(kid), Some(secret), retired_at)
+                        if !kid.is_empty() && !secret.is_empty() =>
+                    {
+                        Ok(SigningKey {
+                            kid: kid.to_string(),
+                            secret: secret.to_string(),
+                            retired_at: retired_at
+                                .map(|at| at.parse().map_err(|_| TokenError::MalformedKeys))
+                                .transpose()?,
+                        })
+                    }
+                    _ => Err(TokenError::MalformedKeys),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { keys })
+    }
+}
+
+impl Display for SigningKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys = self
+            .keys
+            .iter()
+            .map(|key| match key.retired_at {
+                Some(at) => format!("{}:{}:{}", key.kid, key.secret, at),
+                None => format!("{}:{}", key.kid, key.secret),
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", keys.join(","))
+    }
+}
+
+/// The minimum time between two reloads of the signing keys.
+const RELOAD_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// The signing keys of a running instance.
+///
+/// `--rotate-jwt-key` only updates the instance it's given to and `JWT_KEYS`
+/// in the data source, so a token signed with a key this instance doesn't
+/// know makes it reload them, at most once per `RELOAD_INTERVAL`. Rotating
+/// keys is therefore safe while other instances are running: they verify
+/// tokens of the new key right away, and keep signing with the previous key
+/// until they're restarted.
+pub struct KeyStore {
+    keys: RwLock<SigningKeys>,
+    source: Option<Box<dyn DataSource + Send + Sync>>,
+    /// When the keys were last reloaded, locked while they're being reloaded.
+    reloaded_at: Mutex<Option<Instant>>,
+}
+
+impl KeyStore {
+    /// Without a `source`, the keys are never reloaded.
+    pub fn new(keys: SigningKeys, source: Option<Box<dyn DataSource + Send + Sync>>) -> Self {
+        Self {
+            keys: RwLock::new(keys),
+            source,
+            reloaded_at: Mutex::new(None),
+        }
+    }
+
+    /// Returns a copy of the current keys, e.g. to sign a token with.
+    pub fn keys(&self) -> SigningKeys {
+        self.keys.read().unwrap().clone()
+    }
+
+    /// Verifies a token like `verify_token`, reloading the keys first if it
+    /// was signed with an unknown key.
+    pub async fn verify(&self, token: &str) -> Result<Uuid, TokenError> {
+        let res = verify_token(&self.keys.read().unwrap(), token);
+        match res {
+            Err(TokenError::UnknownKey(Some(kid))) if self.reload(&kid).await => {
+                verify_token(&self.keys.read().unwrap(), token)
+            }
+            res => res,
+        }
+    }
+
+    /// Reloads the keys from `JWT_KEYS` unless `kid` is known by now or they
+    /// were reloaded recently. Returns whether `kid` may be known afterwards.
+    async fn reload(&self, kid: &str) -> bool {
+        let source = match &self.source {
+            Some(source) => source,
+            None => return false,
+        };
+        let mut reloaded_at = self.reloaded_at.lock().await;
+        if self.keys.read().unwrap().get(kid).is_some() {
+            return true;
+        }
+        if reloaded_at.is_some_and(|at| at.elapsed() < RELOAD_INTERVAL) {
+            return false;
+        }
+        *reloaded_at = Some(Instant::now());
+        let keys = match source.get("JWT_KEYS").await {
+            Ok(Some(value)) => value.parse::<SigningKeys>().map_err(|err| err.to_string()),
+            Ok(None) => return false,
+            Err(err) => Err(err.to_string()),
+        };
+        match keys {
+            Ok(keys) => {
+                info!("Reloaded JWT signing keys");
+                *self.keys.write().unwrap() = keys;
+                true
+            }
+            Err(err) => {
+                warn!("Cannot reload JWT signing keys: {}", err);
+                false
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    /// The user the token was issued to.
+    sub: String,
+    /// Unix timestamp at which the token was issued.
+    iat: i64,
+    /// Unix timestamp after which the token is no longer valid.
+    exp: i64,
+    /// The token's unique identifier.
+    jti: String,
+}
 
-pub fn generate_jwt(secret: &str, user: &User) -> Result<String> {
-    let mut claims = BTreeMap::new();
-    claims.insert("sub", user.uuid.to_string());
-    let key: Hmac<Sha256> = Hmac::new_from_slice(secret.as_bytes())?;
-    let token = claims.sign_with_key(&key)?;
-    Ok(token)
+/// Signs an access token for `user` with the active key that expires after `ttl`.
+pub fn generate_jwt(keys: &SigningKeys, user: &User, ttl: Duration) -> Result<String, TokenError> {
+    let now = Utc::now();
+    let claims = Claims {
+        sub: user.uuid.to_string(),
+        iat: now.timestamp(),
+        exp: (now + ttl).timestamp(),
+        jti: Uuid::new_v4().to_string(),
+    };
+    let key = keys.active();
+    let header = Header {
+        algorithm: AlgorithmType::Hs256,
+        key_id: Some(key.kid.clone()),
+        ..Default::default()
+    };
+    let token = Token::new(header, claims).sign_with_key(&key.hmac()?)?;
+    Ok(token.as_str().to_string())
 }
 
-#[allow(dead_code)]
-pub fn verify_token(secret: &str, token: &str) -> Result<Uuid> {
-    let key: Hmac<Sha256> = Hmac::new_from_slice(secret.as_bytes())?;
-    let claims: BTreeMap<String, String> = token.verify_with_key(&key)?;
-    let uuid = Uuid::parse_str(&claims["sub"])?;
+/// Verifies the signature and expiry of an access token and returns the
+/// uuid of the user it was issued to.
+pub fn verify_token(keys: &SigningKeys, token: &str) -> Result<Uuid, TokenError> {
+    let token: Token<Header, Claims, _> = Token::parse_unverified(token)?;
+    let key = token
+        .header()
+        .key_id
+        .as_deref()
+        .and_then(|kid| keys.get(kid))
+        .ok_or_else(|| TokenError::UnknownKey(token.header().key_id.clone()))?;
+    let token: Token<Header, Claims, _> = token.verify_with_key(&key.hmac()?)?;
+    let claims = token.claims();
+    if claims.exp <= Utc::now().timestamp() {
+        return Err(TokenError::Expired);
+    }
+    let uuid = Uuid::parse_str(&claims.sub)?;
     Ok(uuid)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        error::Error,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use async_trait::async_trait;
+    use secrecy::Secret;
+
+    use super::*;
+
+    /// Holds `JWT_KEYS` and counts how often it's read.
+    struct KeysSource {
+        keys: String,
+        reads: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl DataSource for KeysSource {
+        async fn get(&self, key: &str) -> Result<Option<String>, Box<dyn Error>> {
+            assert_eq!(key, "JWT_KEYS");
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            Ok(Some(self.keys.clone()))
+        }
+
+        async fn set(&mut self, _key: &str, value: String) -> Result<(), Box<dyn Error>> {
+            self.keys = value;
+            Ok(())
+        }
+    }
+
+    fn user() -> User {
+        User::new(
+            "test@example.com",
+            "test",
+            &Secret::new("password".to_string()),
+        )
+    }
+
+    #[test]
+    fn test_verify_token() {
+        let user = user();
+        let keys = SigningKeys::generate();
+        let token = generate_jwt(&keys, &user, Duration::minutes(5)).unwrap();
+        assert_eq!(verify_token(&keys, &token).unwrap(), user.uuid);
+        assert!(matches!(
+            verify_token(&SigningKeys::generate(), &token),
+            Err(TokenError::UnknownKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_token_with_forged_key() {
+        let keys = SigningKeys::generate();
+        let forged: SigningKeys = format!("{}:other", keys.active().kid).parse().unwrap();
+        let token = generate_jwt(&forged, &user(), Duration::minutes(5)).unwrap();
+        assert!(matches!(
+            verify_token(&keys, &token),
+            Err(TokenError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_expired_token() {
+        let keys = SigningKeys::generate();
+        let token = generate_jwt(&keys, &user(), Duration::minutes(-5)).unwrap();
+        assert!(matches!(
+            verify_token(&keys, &token),
+            Err(TokenError::Expired)
+        ));
+    }
+
+    #[test]
+    fn test_rotated_keys_verify_old_tokens() {
+        let user = user();
+        let mut keys = SigningKeys::generate();
+        let ttl = Duration::minutes(5);
+        let old_token = generate_jwt(&keys, &user, ttl).unwrap();
+        keys.rotate(ttl);
+        let new_token = generate_jwt(&keys, &user, ttl).unwrap();
+        // Rotating again right away must not drop keys of unexpired tokens.
+        keys.rotate(ttl);
+        assert_eq!(verify_token(&keys, &old_token).unwrap(), user.uuid);
+        assert_eq!(verify_token(&keys, &new_token).unwrap(), user.uuid);
+    }
+
+    #[test]
+    fn test_rotate_prunes_expired_keys() {
+        let retired_at = Utc::now().timestamp() - 600;
+        let mut keys: SigningKeys = format!("a:s1,b:s2:{}", retired_at).parse().unwrap();
+        keys.rotate(Duration::minutes(5));
+        assert!(keys.get("a").is_some());
+        assert!(keys.get("b").is_none());
+    }
+
+    #[test]
+    fn test_signing_keys_round_trip() {
+        let mut keys = SigningKeys::generate();
+        keys.rotate(Duration::minutes(5));
+        let parsed: SigningKeys = keys.to_string().parse().unwrap();
+        assert_eq!(parsed, keys);
+        assert!("".parse::<SigningKeys>().is_err());
+        assert!("kid".parse::<SigningKeys>().is_err());
+        assert!("kid:secret:never".parse::<SigningKeys>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_key_store_reloads_rotated_keys() {
+        let user = user();
+        let ttl = Duration::minutes(5);
+        let old_keys = SigningKeys::generate();
+        let mut keys = old_keys.clone();
+        keys.rotate(ttl);
+        let reads = Arc::new(AtomicUsize::new(0));
+        let source = KeysSource {
+            keys: keys.to_string(),
+            reads: Arc::clone(&reads),
+        };
+        let store = KeyStore::new(old_keys, Some(Box::new(source)));
+
+        let token = generate_jwt(&keys, &user, ttl).unwrap();
+        assert_eq!(store.verify(&token).await.unwrap(), user.uuid);
+        assert_eq!(store.keys(), keys);
+        assert_eq!(store.verify(&token).await.unwrap(), user.uuid);
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+
+        // Unknown keys don't reload the keys again right away.
+        let token = generate_jwt(&SigningKeys::generate(), &user, ttl).unwrap();
+        for _ in 0..2 {
+            assert!(matches!(
+                store.verify(&token).await,
+                Err(TokenError::UnknownKey(_))
+            ));
+        }
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_signing_keys_debug_redacts_secrets() {
+        let keys: SigningKeys = "kid:s3cr3t".parse().unwrap();
+        assert!(!format!("{:?}", keys).contains("s3cr3t"));
+    }
+}
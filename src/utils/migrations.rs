@@ -0,0 +1,45 @@
+use anyhow::{anyhow, Result};
+use diesel::PgConnection;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use log::info;
+
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
+
+/// Logs which migrations have been applied and which are pending, and applies
+/// the pending ones if `apply` is set.
+///
+/// Fails if migrations are left pending, since the queries wouldn't match the
+/// database schema.
+pub fn run_migrations(conn: &mut PgConnection, apply: bool) -> Result<()> {
+    let applied = conn.applied_migrations().map_err(|err| anyhow!(err))?;
+    match applied.iter().max() {
+        Some(latest) => info!(
+            "{} migrations applied, the latest is {}",
+            applied.len(),
+            latest
+        ),
+        None => info!("No migrations applied"),
+    }
+
+    let pending = conn
+        .pending_migrations(MIGRATIONS)
+        .map_err(|err| anyhow!(err))?;
+    if pending.is_empty() {
+        info!("The database schema is up to date");
+        return Ok(());
+    }
+    for migration in &pending {
+        info!("Pending migration: {}", migration.name());
+    }
+    if !apply {
+        return Err(anyhow!(
+            "{} migrations are pending, apply them with --migrate or AUTO_MIGRATE=true",
+            pending.len()
+        ));
+    }
+
+    conn.run_pending_migrations(MIGRATIONS)
+        .map_err(|err| anyhow!(err))?;
+    info!("Applied {} migrations", pending.len());
+    Ok(())
+}
@@ -1,5 +1,13 @@
+pub mod auth;
 pub mod config;
+pub mod events;
+pub mod fractional_index;
+pub mod guards;
 pub mod img;
 pub mod jwt;
+pub mod migrations;
 pub mod postgresql_data_source;
+pub mod postgresql_event_broker;
+pub mod random;
+pub mod trash;
 pub mod types;
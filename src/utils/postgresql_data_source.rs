@@ -19,23 +19,7 @@ impl PostgresqlDataSource {
             }
         });
 
-        let mut client = Self { client };
-        client.ensure_table().await?;
-        Ok(client)
-    }
-
-    async fn ensure_table(&mut self) -> AResult<()> {
-        self.client
-            .batch_execute(
-                r#"
-            CREATE TABLE IF NOT EXISTS data_source (
-                key TEXT NOT NULL PRIMARY KEY,
-                value TEXT NOT NULL
-            );
-            "#,
-            )
-            .await?;
-        Ok(())
+        Ok(Self { client })
     }
 }
 
@@ -47,7 +31,11 @@ impl DataSource for PostgresqlDataSource {
             .prepare("SELECT value FROM data_source WHERE key = $1")
             .await?;
         let rows = self.client.query(&stmt, &[&key]).await?;
-        Ok(rows.get(0).map(|row| row.get(0)))
+        // Settings saved while they were empty can still be given later.
+        Ok(rows
+            .first()
+            .map(|row| row.get::<_, String>(0))
+            .filter(|value| !value.is_empty()))
     }
 
     async fn set(&mut self, key: &str, value: String) -> Result<(), Box<dyn Error>> {
@@ -0,0 +1,316 @@
+use std::{
+    future::poll_fn,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, mpsc};
+use tokio_postgres::{AsyncMessage, Client, NoTls};
+use uuid::Uuid;
+
+use super::events::{ChangeEvent, EventBroker, LocalEventBroker};
+
+/// The channel change events are sent on.
+const CHANNEL: &str = "unboundnotes_changes";
+
+/// How many events are kept for sending while the connection is down.
+const OUTBOX_SIZE: usize = 1024;
+
+const MIN_RECONNECT_DELAY: Duration = Duration::from_millis(500);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// How often events older than an hour are removed from `change_events`.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(600);
+
+#[derive(Serialize, Deserialize)]
+struct Notification {
+    /// The instance that published the event.
+    origin: Uuid,
+    event: ChangeEvent,
+}
+
+/// A broker that fans events out to every instance through Postgres
+/// `LISTEN`/`NOTIFY`.
+///
+/// Events are delivered to local subscribers right away and stored in
+/// `change_events` before their id is announced to the other instances, over a
+/// dedicated connection that's reopened whenever it drops. Only the id is sent
+/// since `NOTIFY` payloads are limited to 8000 bytes. Events published in the
+/// meantime are queued, and events stored by other instances are caught up on
+/// once the connection is back.
+pub struct PostgresqlEventBroker {
+    instance_uuid: Uuid,
+    local: LocalEventBroker,
+    outbox: mpsc::Sender<String>,
+}
+
+impl PostgresqlEventBroker {
+    pub fn new(url: &str) -> Self {
+        let instance_uuid = Uuid::new_v4();
+        let local = LocalEventBroker::new();
+        let (outbox, outbox_rx) = mpsc::channel(OUTBOX_SIZE);
+        let listener = Listener {
+            instance_uuid,
+            local: local.sender(),
+            last_seen: Arc::new(AtomicI64::new(-1)),
+        };
+        tokio::spawn(listener.run(url.to_string(), outbox_rx));
+        Self {
+            instance_uuid,
+            local,
+            outbox,
+        }
+    }
+}
+
+#[async_trait]
+impl EventBroker for PostgresqlEventBroker {
+    async fn publish(&self, event: ChangeEvent) -> Result<()> {
+        let payload = serde_json::to_string(&Notification {
+            origin: self.instance_uuid,
+            event: event.clone(),
+        })?;
+        self.local.publish(event).await?;
+        self.outbox
+            .try_send(payload)
+            .map_err(|err| anyhow!("Failed to queue event for other instances: {}", err))
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<ChangeEvent> {
+        self.local.subscribe()
+    }
+}
+
+struct Listener {
+    instance_uuid: Uuid,
+    local: broadcast::Sender<ChangeEvent>,
+    /// The id of the newest event delivered to local subscribers, or -1
+    /// before the first connection.
+    ///
+    /// Ids are assigned before commit, so notifications may arrive out of
+    /// order. Catching up therefore only relies on this after a reconnect, and
+    /// an event notified while catching up may be delivered twice.
+    last_seen: Arc<AtomicI64>,
+}
+
+impl Listener {
+    /// Delivers the event `id` to local subscribers unless it came from this
+    /// instance.
+    fn deliver(&self, id: i64, payload: &str) {
+        self.last_seen.fetch_max(id, Ordering::SeqCst);
+        match serde_json::from_str::<Notification>(payload) {
+            // Events from this instance were delivered on publish.
+            Ok(notification) if notification.origin == self.instance_uuid => {}
+            Ok(notification) => {
+                let _ = self.local.send(notification.event);
+            }
+            Err(err) => warn!("Ignoring malformed change event {}: {}", id, err),
+        }
+    }
+
+    /// Keeps a listening connection open, sending the events queued in
+    /// `outbox`, fetching the events other instances announce and catching up
+    /// after every reconnect.
+    async fn run(self, url: String, mut outbox: mpsc::Receiver<String>) {
+        let mut delay = MIN_RECONNECT_DELAY;
+        let mut cleanup = tokio::time::interval(CLEANUP_INTERVAL);
+        // An event that couldn't be sent before the connection dropped.
+        let mut pending = None;
+        loop {
+            match self.listen(&url).await {
+                Ok((client, mut connection, mut notified)) => {
+                    info!("Listening for change events on {}", CHANNEL);
+                    delay = MIN_RECONNECT_DELAY;
+                    loop {
+                        let payload = match pending.take() {
+                            Some(payload) => payload,
+                            None => tokio::select! {
+                                _ = &mut connection => break,
+                                _ = cleanup.tick() => {
+                                    if let Err(err) = cleanup_events(&client).await {
+                                        warn!("Failed to clean up change events: {}", err);
+                                    }
+                                    continue;
+                                }
+                                id = notified.recv() => {
+                                    let id = match id {
+                                        Some(id) => id,
+                                        // The connection ended.
+                                        None => break,
+                                    };
+                                    if let Err(err) = self.deliver_stored(&client, "=", id).await {
+                                        warn!("Failed to fetch change event {}: {}", id, err);
+                                    }
+                                    continue;
+                                }
+                                payload = outbox.recv() => match payload {
+                                    Some(payload) => payload,
+                                    // The broker was dropped.
+                                    None => return,
+                                },
+                            },
+                        };
+                        if let Err(err) = send_event(&client, &payload).await {
+                            if !client.is_closed() {
+                                // Sending it again would fail the same way.
+                                warn!("Dropping change event that failed to send: {}", err);
+                                continue;
+                            }
+                            warn!("Failed to send change event: {}", err);
+                            pending = Some(payload);
+                            break;
+                        }
+                    }
+                    connection.abort();
+                    warn!("Lost the change event connection, reconnecting");
+                }
+                Err(err) => warn!("Failed to listen for change events: {}", err),
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    }
+
+    /// Opens a connection listening on `CHANNEL` and delivers the events
+    /// stored since the last one. Returns the client together with the task
+    /// driving the connection, which ends when the connection does, and the
+    /// ids of the events announced on `CHANNEL`.
+    async fn listen(
+        &self,
+        url: &str,
+    ) -> Result<(
+        Client,
+        tokio::task::JoinHandle<()>,
+        mpsc::UnboundedReceiver<i64>,
+    )> {
+        let (client, mut connection) = tokio_postgres::connect(url, NoTls).await?;
+        let (notify, notified) = mpsc::unbounded_channel();
+        let connection = tokio::spawn(async move {
+            while let Some(message) = poll_fn(|cx| connection.poll_message(cx)).await {
+                match message {
+                    Ok(AsyncMessage::Notification(notification)) => {
+                        match notification.payload().parse() {
+                            Ok(id) => {
+                                let _ = notify.send(id);
+                            }
+                            Err(_) => warn!("Ignoring malformed change event notification"),
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        warn!("Change event connection error: {}", err);
+                        break;
+                    }
+                }
+            }
+        });
+        match self.catch_up(&client).await {
+            Ok(()) => Ok((client, connection, notified)),
+            Err(err) => {
+                connection.abort();
+                Err(err)
+            }
+        }
+    }
+
+    async fn catch_up(&self, client: &Client) -> Result<()> {
+        if self.last_seen.load(Ordering::SeqCst) < 0 {
+            // Only events published after startup are of interest.
+            let row = client
+                .query_one("SELECT coalesce(max(id), 0) FROM change_events", &[])
+                .await?;
+            self.last_seen.fetch_max(row.get(0), Ordering::SeqCst);
+        }
+        let since = self.last_seen.load(Ordering::SeqCst);
+        client.batch_execute(&format!("LISTEN {}", CHANNEL)).await?;
+        self.deliver_stored(client, ">", since).await
+    }
+
+    /// Delivers the stored events whose id compares to `id` with `op`.
+    async fn deliver_stored(&self, client: &Client, op: &str, id: i64) -> Result<()> {
+        let query = format!(
+            "SELECT id, payload FROM change_events WHERE id {} $1 ORDER BY id",
+            op
+        );
+        for row in client.query(&query, &[&id]).await? {
+            self.deliver(row.get(0), row.get(1));
+        }
+        Ok(())
+    }
+}
+
+/// Stores an event and notifies every listening instance about its id.
+async fn send_event(client: &Client, payload: &str) -> Result<()> {
+    client
+        .execute(
+            "WITH event AS (INSERT INTO change_events (payload) VALUES ($2) RETURNING id) \
+             SELECT pg_notify($1, id::text) FROM event",
+            &[&CHANNEL, &payload],
+        )
+        .await?;
+    Ok(())
+}
+
+async fn cleanup_events(client: &Client) -> Result<()> {
+    client
+        .execute(
+            "DELETE FROM change_events WHERE created_at < now() - interval '1 hour'",
+            &[],
+        )
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::models::text_ops::{InsertText, TextEdit, TextOp};
+    use crate::utils::events::ChangeKind;
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database, see TEST_DATABASE_URL"]
+    async fn test_large_event() {
+        let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set");
+        let (publisher, subscriber) = (
+            PostgresqlEventBroker::new(&url),
+            PostgresqlEventBroker::new(&url),
+        );
+        let mut receiver = subscriber.subscribe();
+        let text = "a".repeat(10000);
+        let event = ChangeEvent {
+            kind: ChangeKind::TextEdited,
+            workspace_uuid: Uuid::new_v4(),
+            page_uuid: Uuid::new_v4(),
+            slot_uuid: None,
+            atom_idx: None,
+            user_uuid: None,
+            edit: Some(TextEdit {
+                atom_uuid: Uuid::new_v4(),
+                version: 1,
+                ops: vec![TextOp::Insert(InsertText { pos: 0, text })],
+            }),
+        };
+        // Events published before the subscriber listens aren't delivered, so
+        // keep publishing until one is.
+        let received = timeout(Duration::from_secs(10), async {
+            loop {
+                publisher.publish(event.clone()).await.unwrap();
+                if let Ok(received) = timeout(Duration::from_millis(200), receiver.recv()).await {
+                    return received.unwrap();
+                }
+            }
+        })
+        .await
+        .expect("the event was not delivered");
+        assert_eq!(received, event);
+    }
+}
@@ -0,0 +1,12 @@
+use rand_core::{OsRng, RngCore};
+
+/// Returns `len` bytes from the OS CSPRNG, hex encoded.
+pub fn random_hex(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    OsRng.fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,47 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use log::{info, warn};
+
+use crate::repos::traits::{ImagesRepo, PageRepo, WorkspaceRepo};
+
+/// How often expired pages and workspaces are purged from the trash.
+const PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Deletes the images of purged pages and workspaces. Failures are logged
+/// rather than returned, since the purge itself has already been committed.
+pub async fn delete_images(images_repo: &dyn ImagesRepo, urls: &[String]) {
+    for url in urls {
+        if let Err(err) = images_repo.delete_image(url).await {
+            warn!("Failed to delete image {}: {}", url, err);
+        }
+    }
+}
+
+/// Purges pages and workspaces that have been in the trash for more than
+/// `retention_days`, checking every `PURGE_INTERVAL`.
+pub async fn purge_expired_trash(
+    workspace_repo: Arc<dyn WorkspaceRepo>,
+    page_repo: Arc<dyn PageRepo>,
+    images_repo: Arc<dyn ImagesRepo>,
+    retention_days: i64,
+) {
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let deleted_before = (Utc::now() - chrono::Duration::days(retention_days)).naive_utc();
+        let purged = [
+            workspace_repo
+                .purge_trashed_workspaces(deleted_before)
+                .await,
+            page_repo.purge_trashed_pages(deleted_before).await,
+        ];
+        for images in purged {
+            match images {
+                Ok(images) => delete_images(images_repo.as_ref(), &images).await,
+                Err(err) => warn!("Failed to purge the trash: {}", err),
+            }
+        }
+        info!("Purged items trashed before {}", deleted_before);
+    }
+}
@@ -1,6 +1,7 @@
-use async_graphql::{OutputType, SimpleObject};
+use async_graphql::{ErrorExtensions, FieldError, OutputType, SimpleObject};
+use thiserror::Error;
 
-use crate::models::{Page, Workspace};
+use crate::models::{text_ops::TextEdit, Atom, Page, Slot, Workspace};
 
 #[derive(SimpleObject)]
 pub struct InputError {
@@ -11,6 +12,9 @@ pub struct InputError {
 #[derive(SimpleObject)]
 #[graphql(concrete(name = "WithErrorWorkspace", params(Workspace)))]
 #[graphql(concrete(name = "WithErrorPage", params(Page)))]
+#[graphql(concrete(name = "WithErrorSlot", params(Slot)))]
+#[graphql(concrete(name = "WithErrorAtom", params(Atom)))]
+#[graphql(concrete(name = "WithErrorTextEdit", params(TextEdit)))]
 pub struct WithError<T>
 where
     T: Send + Sync + OutputType,
@@ -30,3 +34,36 @@ where
         }
     }
 }
+
+/// Returned by updates based on an outdated version of a record, because
+/// someone else changed it in the meantime.
+#[derive(Debug, Error)]
+#[error("This {kind} was changed by someone else, reload it and try again")]
+pub struct ConflictError {
+    pub kind: &'static str,
+    /// The record's current version.
+    pub version: i32,
+}
+
+impl ErrorExtensions for ConflictError {
+    fn extend(&self) -> FieldError {
+        self.extend_with(|err, e| {
+            e.set("code", 409);
+            e.set("version", err.version);
+        })
+    }
+}
+
+/// Returned when a request can't be completed right now but may succeed when
+/// retried shortly, e.g. once a list's order keys are rebalanced.
+#[derive(Debug, Error)]
+#[error("{0}, try again")]
+pub struct RetryError(pub &'static str);
+
+impl ErrorExtensions for RetryError {
+    fn extend(&self) -> FieldError {
+        self.extend_with(|_, e| {
+            e.set("code", 503);
+        })
+    }
+}